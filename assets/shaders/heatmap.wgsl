@@ -0,0 +1,33 @@
+#import bevy_sprite::mesh2d_view_bindings
+#import bevy_sprite::mesh2d_bindings
+
+struct HeatmapMaterial {
+    tint: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> material: HeatmapMaterial;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    // Weight of the selected bone as color
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * mesh.model * vec4<f32>(vertex.position, 1.0);
+    out.color = vertex.color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * material.tint;
+}
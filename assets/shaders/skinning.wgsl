@@ -0,0 +1,47 @@
+#import bevy_sprite::mesh2d_view_bindings
+#import bevy_sprite::mesh2d_bindings
+
+struct SkinningMaterial {
+    bone_matrices: array<mat4x4<f32>, 64>,
+};
+
+@group(1) @binding(0)
+var texture: texture_2d<f32>;
+@group(1) @binding(1)
+var texture_sampler: sampler;
+@group(1) @binding(2)
+var<uniform> material: SkinningMaterial;
+
+struct Vertex {
+    // Position in the bind pose
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) bone_indices: vec4<u32>,
+    @location(3) bone_weights: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// Same as gpu_skinning::skin_vertex_like_shader
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let skinning_matrix =
+        material.bone_matrices[vertex.bone_indices.x] * vertex.bone_weights.x
+        + material.bone_matrices[vertex.bone_indices.y] * vertex.bone_weights.y
+        + material.bone_matrices[vertex.bone_indices.z] * vertex.bone_weights.z
+        + material.bone_matrices[vertex.bone_indices.w] * vertex.bone_weights.w;
+    let position = skinning_matrix * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * mesh.model * position;
+    out.uv = vertex.uv;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(texture, texture_sampler, in.uv);
+}
@@ -1,6 +1,6 @@
 use std::ops::MulAssign;
 
-use crate::{bone::Bone, *};
+use crate::{inverse_kinematics::Target, path_constraint::PathConstraint, *};
 use bevy::{math, prelude::*, utils::HashMap};
 use serde::*;
 
@@ -68,7 +68,7 @@ pub struct Animatable;
  *  There is also an easing function for each component, but this should be changed to one easing function for each keyframe
  *  Each ComponentAnimation mus have exactly the same amount of transforms as there are keyframes in the animation
  */
-#[derive(Default)]
+#[derive(Default, Clone, PartialEq)]
 pub struct Animation {
     pub keyframes: Vec<f64>,
     pub comp_animations: HashMap<Entity, ComponentAnimation>,
@@ -83,12 +83,53 @@ impl Animation {
             bone_animation.remove_keyframe(index);
         }
     }
+    /// Time in seconds between the first and the last keyframe
+    pub fn length(&self) -> f64 {
+        if self.keyframes.is_empty() {
+            return 0.0;
+        }
+        self.keyframes.last().unwrap() - self.keyframes[0]
+    }
+    /// Replace the entities of all component animations, according to `entity_map`
+    pub fn remap(&mut self, entity_map: &HashMap<Entity, Entity>) {
+        self.comp_animations = self
+            .comp_animations
+            .drain()
+            .map(|(entity, comp_animation)| {
+                (*entity_map.get(&entity).unwrap_or(&entity), comp_animation)
+            })
+            .collect();
+    }
+    /// Local transforms of all animated components at the keyframe with the given index
+    pub fn pose_at_keyframe(&self, index: usize) -> HashMap<Entity, Transform> {
+        let mut pose = HashMap::new();
+        for (&entity, comp_animation) in self.comp_animations.iter() {
+            if let Some(&transform) = comp_animation.transforms.get(index) {
+                pose.insert(entity, transform);
+            }
+        }
+        pose
+    }
+    /// Local transforms of all animated components at `time` seconds from the start of the animation
+    pub fn pose_at_time(&self, time: f64) -> HashMap<Entity, Transform> {
+        let mut pose = HashMap::new();
+        for (&entity, comp_animation) in self.comp_animations.iter() {
+            if let Some(transform) = comp_animation.sample(&self.keyframes, time) {
+                pose.insert(entity, transform);
+            }
+        }
+        pose
+    }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, PartialEq)]
 pub struct ComponentAnimation {
     pub transforms: Vec<Transform>,
     pub interpolation_functions: Vec<interpolate::Function>,
+    /// Blend weight per keyframe, only used by IK targets and empty for all other components
+    pub ik_weights: Vec<f32>,
+    /// Position on the path per keyframe, only used by bones with a path constraint
+    pub path_positions: Vec<f32>,
 }
 impl ComponentAnimation {
     pub fn remove_keyframe(&mut self, index: usize) {
@@ -98,6 +139,86 @@ impl ComponentAnimation {
         if self.interpolation_functions.len() > index {
             self.interpolation_functions.remove(index);
         }
+        if self.ik_weights.len() > index {
+            self.ik_weights.remove(index);
+        }
+        if self.path_positions.len() > index {
+            self.path_positions.remove(index);
+        }
+    }
+    /// Returns the indices of the keyframes before and after `time` and the eased progress between them.
+    ///
+    /// `time` is measured in seconds from the start of the animation. The same values are
+    /// used during playback, so sampling is deterministic and independent of the frame rate.
+    pub fn frame_progress(&self, keyframes: &[f64], time: f64) -> Option<(usize, usize, f32)> {
+        let frame_count = usize::min(self.transforms.len(), keyframes.len());
+        if frame_count == 0 {
+            return None;
+        }
+        let anim_length_in_secs = keyframes.last().unwrap() - keyframes[0];
+
+        let mut frame_a = 0;
+        for i in 0..frame_count {
+            if time > keyframes[i] {
+                frame_a = i;
+            }
+        }
+        let frame_b = (frame_a + 1) % frame_count;
+
+        // Calculate keyframe length
+        let keyframe_length_in_secs = if frame_b == 0 {
+            // if loop is ending, set to 1.
+            1.
+        } else {
+            keyframes[frame_b] - keyframes[frame_a]
+        };
+
+        let x = if anim_length_in_secs == 0.0 {
+            0.0
+        } else {
+            let comp_time_diff = time % keyframes.last().unwrap();
+            ((comp_time_diff - keyframes[frame_a]) / keyframe_length_in_secs) as f32
+        };
+        let x = match self.interpolation_functions.get(frame_b) {
+            Some(function) => function.apply(x),
+            None => x,
+        };
+        Some((frame_a, frame_b, x))
+    }
+    /// Returns the interpolated transform at `time` seconds from the start of the animation
+    pub fn sample(&self, keyframes: &[f64], time: f64) -> Option<Transform> {
+        let (frame_a, frame_b, x) = self.frame_progress(keyframes, time)?;
+        Some(Transform {
+            translation: interpolate::lerp(
+                self.transforms[frame_a].translation,
+                self.transforms[frame_b].translation,
+                x,
+            ),
+            rotation: quat_nlerp(
+                self.transforms[frame_a].rotation,
+                self.transforms[frame_b].rotation,
+                x,
+            ),
+            scale: interpolate::lerp(
+                self.transforms[frame_a].scale,
+                self.transforms[frame_b].scale,
+                x,
+            ),
+        })
+    }
+    /// Returns the interpolated IK weight at `time` seconds from the start of the animation
+    pub fn sample_ik_weight(&self, keyframes: &[f64], time: f64) -> Option<f32> {
+        let (frame_a, frame_b, x) = self.frame_progress(keyframes, time)?;
+        let weight_a = *self.ik_weights.get(frame_a)?;
+        let weight_b = *self.ik_weights.get(frame_b)?;
+        Some(weight_a + (weight_b - weight_a) * x)
+    }
+    /// Returns the interpolated position on the path at `time` seconds from the start of the animation
+    pub fn sample_path_position(&self, keyframes: &[f64], time: f64) -> Option<f32> {
+        let (frame_a, frame_b, x) = self.frame_progress(keyframes, time)?;
+        let position_a = *self.path_positions.get(frame_a)?;
+        let position_b = *self.path_positions.get(frame_b)?;
+        Some(position_a + (position_b - position_a) * x)
     }
 }
 
@@ -150,7 +271,10 @@ pub fn start_stop(keys: Res<Input<KeyCode>>, mut state: ResMut<State>, time: Res
 }
 
 pub fn apply_animation(
-    mut q: Query<(&mut Transform, Option<&Bone>), With<Animatable>>,
+    mut q: Query<
+        (&mut Transform, Option<&mut Target>, Option<&mut PathConstraint>),
+        With<Animatable>,
+    >,
     cursor_pos: Res<CursorPos>,
     state: ResMut<State>,
     anims: Res<Animations>,
@@ -173,68 +297,28 @@ pub fn apply_animation(
             if anim.keyframes.is_empty() {
                 return;
             }
-            let anim_length_in_secs = anim.keyframes.iter().last().unwrap() - anim.keyframes[0]; // + 1.;
-            let time_diff = (time.seconds_since_startup() - state.start_time) % anim_length_in_secs;
+            let time_diff = (time.seconds_since_startup() - state.start_time) % anim.length();
             for (&key, comp_animation) in anim.comp_animations.iter() {
                 if q.get_mut(key).is_err() || comp_animation.transforms.len() == 0 {
                     continue;
                 }
-                if let Some(bone) = q.get_mut(key).unwrap().1 {
-                    if bone.is_ik_maneuvered {
-                        continue;
+
+                let (mut transform, opt_target, opt_path) = q.get_mut(key).unwrap();
+
+                if transform_is_valid(&transform) {
+                    if let Some(sampled) = comp_animation.sample(&anim.keyframes, time_diff) {
+                        *transform = sampled;
                     }
                 }
-
-                let mut current_frame_a = 0;
-                for i in 0..comp_animation.transforms.len() {
-                    if time_diff > anim.keyframes[i] {
-                        current_frame_a = i;
+                if let Some(mut target) = opt_target {
+                    if let Some(weight) = comp_animation.sample_ik_weight(&anim.keyframes, time_diff) {
+                        target.weight = weight;
                     }
                 }
-                let mut current_frame_b = (current_frame_a + 1) % comp_animation.transforms.len();
-
-                // Calculate keyframe length
-                let keyframe_length_in_secs = if current_frame_b == 0 {
-                    // if loop is ending, set to 1.
-                    1.
-                } else {
-                    anim.keyframes[current_frame_b] - anim.keyframes[current_frame_a]
-                };
-
-                let mut x = if anim_length_in_secs == 0.0 {
-                    0.0
-                } else {
-                    let comp_time_diff = time_diff % anim.keyframes.last().unwrap();
-                    ((comp_time_diff - anim.keyframes[current_frame_a]) / keyframe_length_in_secs)
-                        as f32
-                };
-                x = match comp_animation.interpolation_functions[current_frame_b] {
-                    interpolate::Function::Linear => x,
-                    interpolate::Function::EaseInOut => interpolate::ease_in_out(x),
-                    interpolate::Function::EaseIn => interpolate::ease_in(x),
-                    interpolate::Function::EaseOut => interpolate::ease_out(x),
-                    interpolate::Function::EaseOutElastic => interpolate::ease_out_elastic(x),
-                    interpolate::Function::EaseInOutElastic => interpolate::ease_in_out_elastic(x),
-                    interpolate::Function::EaseInOutBack => interpolate::ease_in_out_back(x),
-                };
-                let (mut transform, _) = q.get_mut(key).unwrap();
-
-                if transform_is_valid(&transform) {
-                    transform.translation = interpolate::lerp(
-                        comp_animation.transforms[current_frame_a].translation,
-                        comp_animation.transforms[current_frame_b].translation,
-                        x,
-                    );
-                    transform.rotation = quat_nlerp(
-                        comp_animation.transforms[current_frame_a].rotation,
-                        comp_animation.transforms[current_frame_b].rotation,
-                        x,
-                    );
-                    transform.scale = interpolate::lerp(
-                        comp_animation.transforms[current_frame_a].scale,
-                        comp_animation.transforms[current_frame_b].scale,
-                        x,
-                    );
+                if let Some(mut path) = opt_path {
+                    if let Some(position) = comp_animation.sample_path_position(&anim.keyframes, time_diff) {
+                        path.position = position;
+                    }
                 }
             }
         }
@@ -295,51 +379,18 @@ pub fn apply_animation(
             if anim.keyframes.is_empty() {
                 return;
             }
-            let anim_length_in_secs = anim.keyframes.iter().last().unwrap() - anim.keyframes[0]; // + 1.;
-            let time_diff = (time.seconds_since_startup() - state.start_time) % anim_length_in_secs;
+            let time_diff = (time.seconds_since_startup() - state.start_time) % anim.length();
             for (&key, comp_animation) in anim.comp_animations.iter() {
                 if q.get_mut(key).is_err() || comp_animation.transforms.len() == 0 {
                     continue;
                 }
-                if let Some(bone) = q.get_mut(key).unwrap().1 {
-                    if bone.is_ik_maneuvered {
-                        continue;
-                    }
-                }
 
-                let mut current_frame_a = 0;
-                for i in 0..comp_animation.transforms.len() {
-                    if time_diff > anim.keyframes[i] {
-                        current_frame_a = i;
-                    }
-                }
-                let mut current_frame_b = (current_frame_a + 1) % comp_animation.transforms.len();
-
-                // Calculate keyframe length
-                let keyframe_length_in_secs = if current_frame_b == 0 {
-                    // if loop is ending, set to 1.
-                    1.
-                } else {
-                    anim.keyframes[current_frame_b] - anim.keyframes[current_frame_a]
-                };
-
-                let mut x = if anim_length_in_secs == 0.0 {
-                    0.0
-                } else {
-                    let comp_time_diff = time_diff % anim.keyframes.last().unwrap();
-                    ((comp_time_diff - anim.keyframes[current_frame_a]) / keyframe_length_in_secs)
-                        as f32
-                };
-                x = match comp_animation.interpolation_functions[current_frame_b] {
-                    interpolate::Function::Linear => x,
-                    interpolate::Function::EaseInOut => interpolate::ease_in_out(x),
-                    interpolate::Function::EaseIn => interpolate::ease_in(x),
-                    interpolate::Function::EaseOut => interpolate::ease_out(x),
-                    interpolate::Function::EaseOutElastic => interpolate::ease_out_elastic(x),
-                    interpolate::Function::EaseInOutElastic => interpolate::ease_in_out_elastic(x),
-                    interpolate::Function::EaseInOutBack => interpolate::ease_in_out_back(x),
-                };
-                let (mut transform, _) = q.get_mut(key).unwrap();
+                let (current_frame_a, current_frame_b, x) =
+                    match comp_animation.frame_progress(&anim.keyframes, time_diff) {
+                        Some(progress) => progress,
+                        None => continue,
+                    };
+                let (mut transform, opt_target, opt_path) = q.get_mut(key).unwrap();
 
                 if first {
                     transform.translation = Vec3::new(0., 0., 0.);
@@ -347,6 +398,23 @@ pub fn apply_animation(
                     transform.scale = Vec3::new(0., 0., 0.);
                 }
 
+                if let Some(mut target) = opt_target {
+                    if let Some(ik_weight) = comp_animation.sample_ik_weight(&anim.keyframes, time_diff) {
+                        if first {
+                            target.weight = 0.;
+                        }
+                        target.weight += ik_weight * weight;
+                    }
+                }
+                if let Some(mut path) = opt_path {
+                    if let Some(position) = comp_animation.sample_path_position(&anim.keyframes, time_diff) {
+                        if first {
+                            path.position = 0.;
+                        }
+                        path.position += position * weight;
+                    }
+                }
+
                 transform.translation += interpolate::lerp(
                     comp_animation.transforms[current_frame_a].translation,
                     comp_animation.transforms[current_frame_b].translation,
@@ -371,10 +439,21 @@ pub fn apply_animation(
 }
 
 pub fn create_or_change_keyframe(
-    q: Query<(&Transform, &Transformable, Entity), With<Animatable>>,
+    q: Query<
+        (
+            &Transform,
+            &Transformable,
+            Entity,
+            Option<&Target>,
+            Option<&PathConstraint>,
+        ),
+        With<Animatable>,
+    >,
     keys: Res<Input<KeyCode>>,
     egui_state: Res<egui::State>,
     mut anims: ResMut<Animations>,
+    state: Res<State>,
+    mut history: ResMut<history::History>,
 ) {
     // Create KeyFrame only if K was pressed, edit selected keyframe if J was pressed
     let is_create = keys.just_pressed(KeyCode::K);
@@ -382,6 +461,7 @@ pub fn create_or_change_keyframe(
     if !is_create && !is_change {
         return;
     }
+    let before = history::AnimationsSnapshot::new(&anims, &state);
 
     let anim_name = &egui_state.plots[egui_state.edit_plot].name;
     if !anims.map.contains_key(anim_name) {
@@ -400,7 +480,7 @@ pub fn create_or_change_keyframe(
         });
     }
 
-    for (transform, transformable, entity) in q.iter() {
+    for (transform, transformable, entity, opt_target, opt_path) in q.iter() {
         // Only add keyframe for selected objects, or ones that are already part of animation
         if !transformable.is_selected && !transformable.is_part_of_layer {
             continue;
@@ -419,6 +499,16 @@ pub fn create_or_change_keyframe(
                 .interpolation_functions
                 .push(egui_state.interpolation_function);
         }
+        if let Some(target) = opt_target {
+            while comp_animation.ik_weights.len() < anim_mut.keyframes.len() {
+                comp_animation.ik_weights.push(target.weight);
+            }
+        }
+        if let Some(path) = opt_path {
+            while comp_animation.path_positions.len() < anim_mut.keyframes.len() {
+                comp_animation.path_positions.push(path.position);
+            }
+        }
         if is_change {
             let index = egui_state.plots[egui_state.edit_plot].selected_keyframe_index;
             if anim_mut.keyframes.len() > index {
@@ -426,14 +516,32 @@ pub fn create_or_change_keyframe(
                     continue;
                 }
                 *comp_animation.transforms.get_mut(index).unwrap() = transform.clone();
+                if let (Some(target), Some(ik_weight)) =
+                    (opt_target, comp_animation.ik_weights.get_mut(index))
+                {
+                    *ik_weight = target.weight;
+                }
+                if let (Some(path), Some(path_position)) =
+                    (opt_path, comp_animation.path_positions.get_mut(index))
+                {
+                    *path_position = path.position;
+                }
             }
         }
     }
+
+    let after = history::AnimationsSnapshot::new(&anims, &state);
+    if after != before {
+        history.push(
+            if is_create { "Add keyframe" } else { "Change keyframe" },
+            history::Command::Animations { before, after },
+        );
+    }
 }
 
 pub fn show_keyframe(
     mut show_keyframe_evr: EventReader<ShowKeyframeEvent>,
-    mut q: Query<&mut Transform>,
+    mut q: Query<(&mut Transform, Option<&mut Target>, Option<&mut PathConstraint>)>,
     state: Res<State>,
     mut animations: ResMut<Animations>,
 ) {
@@ -453,10 +561,21 @@ pub fn show_keyframe(
                 let comp_anim = &anim.comp_animations[&entity];
                 for i in 0..comp_anim.transforms.len() {
                     if i == ev.keyframe_index {
-                        let mut transform = q.get_mut(entity).expect("entity doesn't exist!");
+                        let (mut transform, opt_target, opt_path) =
+                            q.get_mut(entity).expect("entity doesn't exist!");
                         transform.translation = comp_anim.transforms[i].translation;
                         transform.scale = comp_anim.transforms[i].scale;
                         transform.rotation = comp_anim.transforms[i].rotation;
+                        if let (Some(mut target), Some(&ik_weight)) =
+                            (opt_target, comp_anim.ik_weights.get(i))
+                        {
+                            target.weight = ik_weight;
+                        }
+                        if let (Some(mut path), Some(&path_position)) =
+                            (opt_path, comp_anim.path_positions.get(i))
+                        {
+                            path.position = path_position;
+                        }
                     }
                 }
             }
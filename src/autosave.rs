@@ -0,0 +1,239 @@
+use crate::{
+    bone::Bone,
+    cloth::Cloth,
+    constraints::ConstraintStack,
+    inverse_kinematics::{Pole, Target},
+    path_constraint::{PathConstraint, PathPoint},
+    save_load::CompleteJson,
+    skeleton::Skeleton,
+    skin::Skin,
+    *,
+};
+use bevy::app::AppExit;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, path::Path, time::SystemTime};
+
+#[cfg(test)]
+#[path = "tests/autosave_tests.rs"]
+mod autosave_tests;
+
+// Only used natively, on the web autosaves are kept in local storage
+const AUTOSAVE_DIRECTORY: &str = "assets/anims/autosave";
+// Exists while the editor is running, if it still exists on startup, the editor crashed
+#[cfg(not(target_arch = "wasm32"))]
+const LOCK_FILE_NAME: &str = "session.lock";
+// Set by the panic hook, the browser doesn't tell us whether the tab was closed properly
+#[cfg(target_arch = "wasm32")]
+const CRASH_FLAG_KEY: &str = "autosave_crashed";
+#[cfg(target_arch = "wasm32")]
+const NEWEST_SLOT_KEY: &str = "autosave_newest";
+
+pub struct State {
+    pub enabled: bool,
+    /// Time between two autosaves in seconds
+    pub interval: f64,
+    /// Number of autosaves that are kept, the oldest one is overwritten
+    pub slot_count: usize,
+    /// Newest autosave, if the editor wasn't shut down properly the last time
+    pub recovery: Option<CompleteJson>,
+    last_save_time: f64,
+    next_slot: usize,
+    last_saved: String,
+}
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: 60.,
+            slot_count: 5,
+            recovery: None,
+            last_save_time: 0.,
+            next_slot: 0,
+            last_saved: String::new(),
+        }
+    }
+}
+impl State {
+    /// Returns the slot of the next autosave and moves on to the following one
+    fn take_slot(&mut self) -> usize {
+        let slot = self.next_slot % self.slot_count;
+        self.next_slot = (slot + 1) % self.slot_count;
+        slot
+    }
+    /// Continues after the newest autosave of the last session, so that it is overwritten last
+    fn continue_after(&mut self, newest_slot: usize) {
+        self.next_slot = (newest_slot + 1) % self.slot_count;
+    }
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new().with_system(autosave)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn slot_path(directory: &str, slot: usize) -> String {
+    format!("{}/autosave_{}.anim", directory, slot)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn lock_file_path(directory: &str) -> String {
+    format!("{}/{}", directory, LOCK_FILE_NAME)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_slot(directory: &str, slot: usize, serialized: &str) {
+    if let Err(err) = fs::write(slot_path(directory, slot), serialized) {
+        println!("autosave: failed to write {}: {}", slot_path(directory, slot), err);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_slot(_directory: &str, slot: usize, serialized: &str) {
+    let local_storage = web_sys::window().unwrap().local_storage().unwrap().unwrap();
+    if local_storage
+        .set_item(&format!("autosave_{}", slot), serialized)
+        .is_err()
+    {
+        println!("autosave: failed to write to local storage");
+        return;
+    }
+    let _ = local_storage.set_item(NEWEST_SLOT_KEY, &slot.to_string());
+}
+
+/// Returns slot and content of the newest autosave
+#[cfg(not(target_arch = "wasm32"))]
+fn read_newest_slot(directory: &str, slot_count: usize) -> Option<(usize, String)> {
+    let mut newest: Option<(usize, SystemTime)> = None;
+    for slot in 0..slot_count {
+        let modified = match fs::metadata(slot_path(directory, slot)).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(_) => continue,
+        };
+        if newest.is_none() || modified > newest.unwrap().1 {
+            newest = Some((slot, modified));
+        }
+    }
+    let (slot, _) = newest?;
+    Some((slot, fs::read_to_string(slot_path(directory, slot)).ok()?))
+}
+
+/// Returns slot and content of the newest autosave
+#[cfg(target_arch = "wasm32")]
+fn read_newest_slot(_directory: &str, _slot_count: usize) -> Option<(usize, String)> {
+    let local_storage = web_sys::window().unwrap().local_storage().unwrap().unwrap();
+    let slot = local_storage
+        .get_item(NEWEST_SLOT_KEY)
+        .ok()??
+        .parse::<usize>()
+        .ok()?;
+    Some((slot, local_storage.get_item(&format!("autosave_{}", slot)).ok()??))
+}
+
+/// Returns true, if the editor wasn't shut down properly the last time it was running
+#[cfg(not(target_arch = "wasm32"))]
+fn start_session(directory: &str) -> bool {
+    let is_unclean = Path::new(&lock_file_path(directory)).exists();
+    if let Err(err) = fs::create_dir_all(directory)
+        .and_then(|_| fs::write(lock_file_path(directory), ""))
+    {
+        println!("autosave: failed to create lock file: {}", err);
+    }
+    is_unclean
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn remove_lock_file(directory: &str) {
+    if let Err(err) = fs::remove_file(lock_file_path(directory)) {
+        println!("autosave: failed to remove lock file: {}", err);
+    }
+}
+
+/// Returns true, if the editor crashed the last time it was running
+#[cfg(target_arch = "wasm32")]
+fn start_session(_directory: &str) -> bool {
+    let local_storage = web_sys::window().unwrap().local_storage().unwrap().unwrap();
+    let is_unclean = matches!(local_storage.get_item(CRASH_FLAG_KEY), Ok(Some(_)));
+    let _ = local_storage.remove_item(CRASH_FLAG_KEY);
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Some(Ok(Some(local_storage))) = web_sys::window().map(|w| w.local_storage()) {
+            let _ = local_storage.set_item(CRASH_FLAG_KEY, "true");
+        }
+        default_hook(info);
+    }));
+    is_unclean
+}
+
+pub fn check_for_unclean_shutdown(mut state: ResMut<State>) {
+    let is_unclean = start_session(AUTOSAVE_DIRECTORY);
+    if let Some((slot, serialized)) = read_newest_slot(AUTOSAVE_DIRECTORY, state.slot_count) {
+        state.continue_after(slot);
+        if is_unclean {
+            match serde_json::from_str::<CompleteJson>(&serialized) {
+                Ok(data) => state.recovery = Some(data),
+                Err(err) => println!("autosave: failed to parse newest autosave: {}", err),
+            }
+        }
+    }
+}
+
+pub fn autosave(
+    mut state: ResMut<State>,
+    time: Res<Time>,
+    q_bones: Query<(Entity, &Bone, &Transform, Option<&Parent>, &Transformable)>,
+    q_skins: Query<(Entity, &Skin, Option<&Cloth>)>,
+    q_targets: Query<(Entity, &Target, &Transform)>,
+    q_poles: Query<&Transform, With<Pole>>,
+    q_paths: Query<(Entity, &PathConstraint)>,
+    q_path_points: Query<&Transform, With<PathPoint>>,
+    q_constraints: Query<&ConstraintStack>,
+    animations: Res<animation::Animations>,
+    anim_state: Res<animation::State>,
+    skeleton: Res<Skeleton>,
+) {
+    // Don't overwrite autosaves, that might still be restored
+    if !state.enabled || state.recovery.is_some() {
+        return;
+    }
+    if time.seconds_since_startup() - state.last_save_time < state.interval {
+        return;
+    }
+    state.last_save_time = time.seconds_since_startup();
+
+    let serialized = match serde_json::to_string(&save_load::build_complete_json(
+        &q_bones,
+        &q_skins,
+        &q_targets,
+        &q_poles,
+        &q_paths,
+        &q_path_points,
+        &q_constraints,
+        &animations,
+        &anim_state,
+        &skeleton,
+    )) {
+        Ok(serialized) => serialized,
+        Err(err) => {
+            println!("autosave: failed to serialize: {}", err);
+            return;
+        }
+    };
+    // Nothing changed since last autosave
+    if serialized == state.last_saved {
+        return;
+    }
+
+    let slot = state.take_slot();
+    write_slot(AUTOSAVE_DIRECTORY, slot, &serialized);
+    state.last_saved = serialized;
+}
+
+/// Removes the lock file, so that the next start isn't treated as crash recovery
+pub fn end_session(mut exit_evr: EventReader<AppExit>) {
+    if exit_evr.iter().next().is_none() {
+        return;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    remove_lock_file(AUTOSAVE_DIRECTORY);
+}
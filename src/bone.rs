@@ -1,15 +1,52 @@
 use crate::{animation::Animatable, skeleton::Skeleton, *};
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
 
-#[derive(Default, Clone)]
+#[cfg(test)]
+#[path = "tests/bone_tests.rs"]
+mod bone_tests;
+
+/// Range of local rotations that IK may give a bone, in radians. No constraint, if `start == end`.
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AngleConstraint {
     pub start: f32,
     pub end: f32,
 }
+impl AngleConstraint {
+    /// Moves `start` into [0, 2π) and `end` into [start, start + 2π)
+    pub fn normalize(&mut self) {
+        self.start = (self.start % (2. * PI) + 2. * PI) % (2. * PI);
+        self.end = (self.end % (2. * PI) + 2. * PI) % (2. * PI);
+        if self.end < self.start {
+            self.end += 2. * PI;
+        }
+    }
+}
+
+/// Which end of an angle constraint is dragged
+#[derive(Clone, Copy, PartialEq)]
+pub enum AngleConstraintHandle {
+    Start,
+    End,
+}
+
+/// Rotation of a bone before and after inverse kinematics were applied
+#[derive(Clone, Copy)]
+pub struct IKOverride {
+    pub fk_rotation: Quat,
+    pub ik_rotation: Quat,
+}
 
 #[derive(Component)]
 pub struct Bone {
-    pub is_ik_maneuvered: bool,
+    /// Used to find the opposite bone when mirroring, e.g. `left_arm` and `right_arm`
+    pub name: String,
+    /// Set, if the rotation was overridden by an IK target in the last frame
+    pub ik_override: Option<IKOverride>,
     pub ik_angle_constraint: Option<AngleConstraint>,
+    /// Local transform, that skins are bound to. Set when a skin is bound for the first time.
+    pub bind_pose: Option<Transform>,
 }
 impl Bone {
     pub fn get_tip(gl_transform: &GlobalTransform) -> Vec2 {
@@ -29,14 +66,211 @@ impl Bone {
 impl Default for Bone {
     fn default() -> Self {
         Self {
-            is_ik_maneuvered: false,
+            name: String::new(),
+            ik_override: None,
             ik_angle_constraint: Some(AngleConstraint::default()),
+            bind_pose: None,
         }
     }
 }
 
 pub fn system_set() -> SystemSet {
-    SystemSet::new().with_system(add_bone_on_mouse_click)
+    SystemSet::new()
+        .with_system(drag_angle_constraint_handles.before(add_bone_on_mouse_click))
+        .with_system(add_bone_on_mouse_click)
+}
+
+/// Radius of the angle constraint visualizer of a bone with the global transform `gl_transform`
+pub fn get_angle_constraint_radius(gl_transform: &Transform) -> f32 {
+    f32::max(gl_transform.scale.y / 3.0, 0.3)
+}
+
+/// Returns the global positions of the start and end handles of `constraint`.
+///
+/// The angles of the constraint are relative to the parent, whose global rotation is
+/// `parent_gl_rotation`.
+pub fn get_angle_constraint_handles(
+    constraint: &AngleConstraint,
+    gl_transform: &Transform,
+    parent_gl_rotation: Quat,
+) -> (Vec2, Vec2) {
+    let radius = get_angle_constraint_radius(gl_transform);
+    let joint = gl_transform.translation.truncate();
+    let handle = |angle: f32| {
+        joint
+            + (parent_gl_rotation
+                * Vec2::new(0.0, radius)
+                    .rotate(Vec2::from_angle(angle))
+                    .extend(0.))
+            .truncate()
+    };
+    (handle(constraint.start), handle(constraint.end))
+}
+
+/// Lets the start and end of the angle constraint of the first selected bone be dragged with the mouse
+pub fn drag_angle_constraint_handles(
+    mouse: Res<Input<MouseButton>>,
+    cursor_pos: Res<CursorPos>,
+    egui_state: Res<egui::State>,
+    debug_drawer: Res<DebugDrawer>,
+    mut transform_state: ResMut<transform::State>,
+    mut history: ResMut<history::History>,
+    q_transforms: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    mut q_bones: Query<&mut Bone>,
+    mut dragged: Local<Option<(Entity, AngleConstraintHandle)>>,
+) {
+    if let Some((entity, handle)) = *dragged {
+        // Keep other transform actions and selection from reacting to the mouse
+        transform_state.action = Action::Done;
+        let (gl_transform, parent_gl_rotation) =
+            match get_gl_and_parent_rotation(entity, &q_transforms) {
+                Some(transforms) => transforms,
+                None => {
+                    *dragged = None;
+                    return;
+                }
+            };
+        if let Ok(mut bone) = q_bones.get_mut(entity) {
+            if let Some(constraint) = &mut bone.ik_angle_constraint {
+                let v = (parent_gl_rotation.inverse()
+                    * (cursor_pos.0 - gl_transform.translation.truncate()).extend(0.))
+                .truncate();
+                if v != Vec2::ZERO {
+                    let angle = f32::atan2(-v.x, v.y);
+                    match handle {
+                        AngleConstraintHandle::Start => constraint.start = angle,
+                        AngleConstraintHandle::End => constraint.end = angle,
+                    }
+                    constraint.normalize();
+                }
+            }
+        }
+        if mouse.just_released(MouseButton::Left) {
+            *dragged = None;
+            history.record_snapshot("Change angle constraint");
+        }
+        return;
+    }
+
+    if !mouse.just_pressed(MouseButton::Left)
+        || !debug_drawer.bone_debug_enabled
+        || egui_state.ui_hover
+        || transform_state.action != Action::None
+    {
+        return;
+    }
+    // Handles are only shown for the first selected bone
+    let entity = match transform_state.selected_entities.iter().next() {
+        Some(&entity) => entity,
+        None => return,
+    };
+    let constraint = match q_bones.get(entity) {
+        Ok(Bone {
+            ik_angle_constraint: Some(constraint),
+            ..
+        }) => constraint,
+        _ => return,
+    };
+    let (gl_transform, parent_gl_rotation) =
+        match get_gl_and_parent_rotation(entity, &q_transforms) {
+            Some(transforms) => transforms,
+            None => return,
+        };
+    let (start, end) = get_angle_constraint_handles(constraint, &gl_transform, parent_gl_rotation);
+    let grab_distance = 10. / PIXELS_PER_UNIT as f32;
+    // Prefer the end, so that a bone without constraint gets one by dragging away from the start
+    let handle = if cursor_pos.0.distance(end) <= grab_distance {
+        AngleConstraintHandle::End
+    } else if cursor_pos.0.distance(start) <= grab_distance {
+        AngleConstraintHandle::Start
+    } else {
+        return;
+    };
+    *dragged = Some((entity, handle));
+    transform_state.action = Action::Done;
+}
+
+/// Makes `opt_new_parent` the parent of `bone` or makes `bone` a root, if it is `None`.
+///
+/// The global transform of the bone and its global bind pose are kept, `bind_poses` contains the bind
+/// poses of all bones. Returns false, if the new parent is the bone itself or one of its descendants.
+pub fn reparent(
+    commands: &mut Commands,
+    bone: Entity,
+    bone_component: &mut Bone,
+    opt_new_parent: Option<Entity>,
+    query: &Query<(&Transform, Option<&Parent>), With<Bone>>,
+    bind_poses: &HashMap<Entity, Transform>,
+) -> bool {
+    let (&transform, opt_old_parent) = match query.get(bone) {
+        Ok(bone) => bone,
+        Err(_) => return false,
+    };
+    let opt_old_parent = opt_old_parent.map(|parent| parent.get());
+    if opt_new_parent == opt_old_parent {
+        return false;
+    }
+
+    // Check for cycles
+    let mut next_bone = opt_new_parent;
+    while let Some(ancestor) = next_bone {
+        if ancestor == bone {
+            return false;
+        }
+        next_bone = query
+            .get(ancestor)
+            .ok()
+            .and_then(|(_, opt_parent)| opt_parent.map(|parent| parent.get()));
+    }
+
+    let gl_transform = match get_bone_gl_transform(bone, query) {
+        Some(gl_transform) => gl_transform,
+        None => return false,
+    };
+    let new_parent_gl_transform = match opt_new_parent {
+        Some(new_parent) => match get_bone_gl_transform(new_parent, query) {
+            Some(gl_transform) => gl_transform,
+            None => return false,
+        },
+        None => Transform::default(),
+    };
+    let mut new_transform = get_relative_transform(&new_parent_gl_transform, &gl_transform);
+    new_transform.translation.z = transform.translation.z;
+
+    if let Some(bind_pose) = bone_component.bind_pose {
+        let bind_gl_transform = get_bone_gl_transform_with_overrides(bone, query, bind_poses);
+        let new_parent_bind_gl_transform = match opt_new_parent {
+            Some(new_parent) => get_bone_gl_transform_with_overrides(new_parent, query, bind_poses),
+            None => Some(Transform::default()),
+        };
+        if let (Some(bind_gl_transform), Some(new_parent_bind_gl_transform)) =
+            (bind_gl_transform, new_parent_bind_gl_transform)
+        {
+            let mut new_bind_pose =
+                get_relative_transform(&new_parent_bind_gl_transform, &bind_gl_transform);
+            new_bind_pose.translation.z = bind_pose.translation.z;
+            bone_component.bind_pose = Some(new_bind_pose);
+        }
+    }
+
+    if let Some(old_parent) = opt_old_parent {
+        commands.entity(old_parent).remove_children(&[bone]);
+    }
+    if let Some(new_parent) = opt_new_parent {
+        commands.entity(new_parent).add_child(bone);
+    }
+    commands.entity(bone).insert(new_transform);
+    true
+}
+
+/// Global transform of a bone and the global rotation of its parent
+pub fn get_gl_and_parent_rotation(
+    entity: Entity,
+    query: &Query<(&Transform, Option<&Parent>), With<Bone>>,
+) -> Option<(Transform, Quat)> {
+    let (transform, _) = query.get(entity).ok()?;
+    let gl_transform = get_bone_gl_transform(entity, query)?;
+    Some((gl_transform, gl_transform.rotation * transform.rotation.inverse()))
 }
 
 pub fn add_bone_on_mouse_click(
@@ -53,6 +287,7 @@ pub fn add_bone_on_mouse_click(
     )>,
     mut transform_state: ResMut<transform::State>,
     mut skeleton: ResMut<Skeleton>,
+    mut history: ResMut<history::History>,
 ) {
     // Return if action is already taken
     if transform_state.action != Action::None || egui_state.ui_hover {
@@ -63,6 +298,7 @@ pub fn add_bone_on_mouse_click(
         return;
     }
     let bone_depth = 0.1;
+    let name = format!("bone_{}", skeleton.bones.len());
     let mut opt_parent: Option<Entity> = None;
     for (_, opt_bone, entity, transformable) in q.iter() {
         if transformable.is_selected && opt_bone.is_some() {
@@ -103,7 +339,10 @@ pub fn add_bone_on_mouse_click(
                     visibility: Visibility { is_visible: false },
                     ..Default::default()
                 })
-                .insert(Bone::default())
+                .insert(Bone {
+                    name,
+                    ..Default::default()
+                })
                 .insert(Transformable::default())
                 .insert(Animatable)
                 .id();
@@ -123,12 +362,16 @@ pub fn add_bone_on_mouse_click(
                 visibility: Visibility { is_visible: false },
                 ..Default::default()
             })
-            .insert(Bone::default())
+            .insert(Bone {
+                name,
+                ..Default::default()
+            })
             .insert(Transformable::default())
             .insert(Animatable)
             .id()
     };
     skeleton.bones.push(entity);
+    history.record_snapshot("Add bone");
     // Unselect all transformables
     for (_, _, _, mut transformable) in q.iter_mut() {
         transformable.is_selected = false;
@@ -160,3 +403,31 @@ pub fn get_bone_gl_transform(
     }
     Some(bone_gl_transform)
 }
+
+/// Like [`get_bone_gl_transform`], but uses the local transforms stored in `overrides` instead of
+/// the current ones for all bones that have an entry.
+pub fn get_bone_gl_transform_with_overrides(
+    bone_entity: Entity,
+    query: &Query<(&Transform, Option<&Parent>), With<Bone>>,
+    overrides: &HashMap<Entity, Transform>,
+) -> Option<Transform> {
+    let mut bone_gl_transform = Transform::default();
+    let mut next_bone = bone_entity;
+    loop {
+        if let Ok((&bone_transform, opt_parent)) = query.get(next_bone) {
+            let local_transform = match overrides.get(&next_bone) {
+                Some(&transform) => transform,
+                None => bone_transform,
+            };
+            bone_gl_transform = combined_transform(&local_transform, &bone_gl_transform);
+            if let Some(parent) = opt_parent {
+                next_bone = parent.get();
+            } else {
+                break;
+            }
+        } else {
+            return None;
+        }
+    }
+    Some(bone_gl_transform)
+}
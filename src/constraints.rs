@@ -0,0 +1,227 @@
+use crate::{bone::Bone, *};
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+#[path = "tests/constraints_tests.rs"]
+mod constraints_tests;
+
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum ConstraintType {
+    /// Takes the global rotation of the target
+    CopyRotation,
+    /// Points the bone at the target
+    LookAt,
+    /// Points the bone at the target and scales it, so that its tip touches the target
+    StretchTo,
+    /// Keeps the bone within `distance` of the target
+    LimitDistance,
+    /// Takes the global translation, rotation and scale of the target
+    CopyTransforms,
+}
+impl ConstraintType {
+    pub fn all() -> impl ExactSizeIterator<Item = ConstraintType> {
+        [
+            Self::CopyRotation,
+            Self::LookAt,
+            Self::StretchTo,
+            Self::LimitDistance,
+            Self::CopyTransforms,
+        ]
+        .iter()
+        .copied()
+    }
+}
+impl ToString for ConstraintType {
+    fn to_string(&self) -> String {
+        match self {
+            Self::CopyRotation => String::from("Copy Rotation"),
+            Self::LookAt => String::from("Look At"),
+            Self::StretchTo => String::from("Stretch To"),
+            Self::LimitDistance => String::from("Limit Distance"),
+            Self::CopyTransforms => String::from("Copy Transforms"),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Constraint {
+    pub constraint_type: ConstraintType,
+    /// Bone or IK target, whose global transform is used
+    pub target: Option<Entity>,
+    /// Blends between the transform before (0) and after (1) applying the constraint
+    pub influence: f32,
+    /// Only used by [`ConstraintType::LimitDistance`]
+    pub distance: f32,
+    pub enabled: bool,
+}
+impl Constraint {
+    pub fn new(constraint_type: ConstraintType) -> Self {
+        Self {
+            constraint_type,
+            target: None,
+            influence: 1.,
+            distance: 1.,
+            enabled: true,
+        }
+    }
+}
+
+/// Constraints of a bone, applied in order after animations, IK and paths.
+///
+/// Bones are evaluated from root to leaf, so constraints see the constrained transforms of the bones'
+/// parents.
+#[derive(Component, Default, Clone)]
+pub struct ConstraintStack {
+    pub constraints: Vec<Constraint>,
+    /// Local transform before and after the constraints were applied in the last frame
+    last_override: Option<(Transform, Transform)>,
+}
+impl ConstraintStack {
+    pub fn new(constraints: Vec<Constraint>) -> Self {
+        Self {
+            constraints,
+            last_override: None,
+        }
+    }
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new().with_system(apply_constraints)
+}
+
+/// Restores the transforms of constrained bones, unless they were changed since the last frame, e.g. by an
+/// animation. Has to run before IK, which restores its own override.
+pub fn restore_unconstrained(mut q: Query<(&mut Transform, &mut ConstraintStack)>) {
+    for (mut transform, mut stack) in q.iter_mut() {
+        if let Some((unconstrained, constrained)) = stack.last_override.take() {
+            if *transform == constrained {
+                *transform = unconstrained;
+            }
+        }
+    }
+}
+
+/// Returns the local transform `local` of a bone after applying `constraint`
+pub fn apply_constraint(
+    constraint: &Constraint,
+    local: Transform,
+    parent_gl_transform: &Transform,
+    target_gl_transform: &Transform,
+) -> Transform {
+    let gl_transform = combined_transform(parent_gl_transform, &local);
+    let mut constrained = gl_transform;
+    let to_target = (target_gl_transform.translation - gl_transform.translation).truncate();
+    match constraint.constraint_type {
+        ConstraintType::CopyRotation => {
+            constrained.rotation = target_gl_transform.rotation;
+        }
+        ConstraintType::LookAt | ConstraintType::StretchTo => {
+            if to_target == Vec2::ZERO {
+                return local;
+            }
+            constrained.rotation = Quat::from_rotation_z(f32::atan2(-to_target.x, to_target.y));
+            if constraint.constraint_type == ConstraintType::StretchTo {
+                constrained.scale.y = to_target.length();
+            }
+        }
+        ConstraintType::LimitDistance => {
+            if to_target.length() <= constraint.distance {
+                return local;
+            }
+            let translation = target_gl_transform.translation.truncate()
+                - to_target.normalize() * constraint.distance;
+            constrained.translation = translation.extend(gl_transform.translation.z);
+        }
+        ConstraintType::CopyTransforms => {
+            constrained = *target_gl_transform;
+            constrained.translation.z = gl_transform.translation.z;
+        }
+    }
+
+    let mut constrained_local = get_relative_transform(parent_gl_transform, &constrained);
+    constrained_local.translation.z = local.translation.z;
+    let influence = constraint.influence.clamp(0., 1.);
+    Transform {
+        translation: local.translation.lerp(constrained_local.translation, influence),
+        rotation: local
+            .rotation
+            .slerp(constrained_local.rotation, influence)
+            .normalize(),
+        scale: local.scale.lerp(constrained_local.scale, influence),
+    }
+}
+
+/// Global transform of a bone, or the transform of any other entity, e.g. an IK target
+fn get_gl_transform(
+    entity: Entity,
+    q_bones: &Query<(&mut Transform, Option<&Parent>), With<Bone>>,
+    q_others: &Query<&Transform, Without<Bone>>,
+) -> Option<Transform> {
+    if !q_bones.contains(entity) {
+        return q_others.get(entity).ok().copied();
+    }
+    let mut gl_transform = Transform::default();
+    let mut next_bone = Some(entity);
+    while let Some(bone) = next_bone {
+        let (transform, opt_parent) = q_bones.get(bone).ok()?;
+        gl_transform = combined_transform(transform, &gl_transform);
+        next_bone = opt_parent.map(|parent| parent.get());
+    }
+    Some(gl_transform)
+}
+
+pub fn apply_constraints(
+    mut q_bones: Query<(&mut Transform, Option<&Parent>), With<Bone>>,
+    mut q_stacks: Query<(Entity, &mut ConstraintStack)>,
+    q_others: Query<&Transform, Without<Bone>>,
+) {
+    // Order by number of ancestors, so parents are constrained before their children
+    let mut order: Vec<(usize, Entity)> = vec![];
+    for (entity, stack) in q_stacks.iter() {
+        if stack.constraints.is_empty() {
+            continue;
+        }
+        let mut depth = 0;
+        let mut next_bone = entity;
+        while let Ok((_, Some(parent))) = q_bones.get(next_bone) {
+            depth += 1;
+            next_bone = parent.get();
+        }
+        order.push((depth, entity));
+    }
+    order.sort_by_key(|&(depth, _)| depth);
+
+    for (_, entity) in order {
+        let (unconstrained, parent_gl_transform) = match q_bones.get(entity) {
+            Ok((&transform, opt_parent)) => (
+                transform,
+                match opt_parent {
+                    Some(parent) => match get_gl_transform(parent.get(), &q_bones, &q_others) {
+                        Some(gl_transform) => gl_transform,
+                        None => continue,
+                    },
+                    None => Transform::default(),
+                },
+            ),
+            Err(_) => continue,
+        };
+        let (_, mut stack) = q_stacks.get_mut(entity).unwrap();
+
+        let mut local = unconstrained;
+        for constraint in stack.constraints.iter().filter(|constraint| constraint.enabled) {
+            // Bones can't be constrained to themselves
+            let target_gl_transform = match constraint
+                .target
+                .filter(|&target| target != entity)
+                .and_then(|target| get_gl_transform(target, &q_bones, &q_others))
+            {
+                Some(gl_transform) => gl_transform,
+                None => continue,
+            };
+            local = apply_constraint(constraint, local, &parent_gl_transform, &target_gl_transform);
+        }
+
+        *q_bones.get_mut(entity).unwrap().0 = local;
+        stack.last_override = Some((unconstrained, local));
+    }
+}
@@ -1,11 +1,12 @@
 use std::{ascii::EscapeDefault, f32::consts::PI};
 
-use bevy::utils::HashSet;
+use bevy::utils::{HashMap, HashSet};
 use bevy_prototype_lyon::{entity::ShapeBundle, shapes::Polygon};
 
 use crate::*;
 
 const RIGHT_HALF_BITMASK: u32 = (1 << 16) - 1;
+const COLOR_SELECTED_VERTEX: Color = Color::rgb(0., 0.9, 1.);
 
 pub struct DebugDrawer {
     lines: Vec<Line>,
@@ -94,7 +95,10 @@ pub fn system_set() -> SystemSet {
     SystemSet::new()
         .with_system(draw_skin_bounding_box.before(draw_all_debug_shapes))
         .with_system(draw_skin_mesh.before(draw_all_debug_shapes))
+        .with_system(draw_skinning_comparison.before(draw_all_debug_shapes))
         .with_system(draw_select_box.before(draw_all_debug_shapes))
+        .with_system(draw_motion_path.before(draw_all_debug_shapes))
+        .with_system(draw_paths.before(draw_all_debug_shapes))
         .with_system(draw_ccd_target)
         .with_system(
             draw_bones
@@ -205,10 +209,12 @@ pub fn clear_debug_drawer(mut debug_drawer: ResMut<DebugDrawer>) {
 
 pub fn draw_skin_bounding_box(
     meshes: Res<Assets<Mesh>>,
-    mut q: Query<(&mut Transformable, &skin::Skin)>,
+    mut q: Query<(&mut Transformable, &skin::Skin, Entity)>,
     mut debug_drawer: ResMut<DebugDrawer>,
+    gpu_state: Res<gpu_skinning::State>,
+    skinning_materials: Res<Assets<gpu_skinning::SkinningMaterial>>,
 ) {
-    for (mut transformable, skin) in q.iter_mut() {
+    for (mut transformable, skin, entity) in q.iter_mut() {
         // if mesh doesn't exist, continue
         let opt_mesh = meshes.get(&skin.mesh_handle.clone().unwrap().0);
         if opt_mesh.is_none() {
@@ -216,15 +222,16 @@ pub fn draw_skin_bounding_box(
         }
         let mesh = opt_mesh.unwrap();
 
-        let vertices: Vec<Vec3> = mesh::get_vertices(mesh);
+        let vertices: Vec<Vec3> =
+            gpu_skinning::get_vertices(mesh, entity, &gpu_state, &skinning_materials);
 
         let mut sum = Vec3::ZERO;
-        let mut min = mesh::get_vertex(mesh, 0);
-        let mut max = mesh::get_vertex(mesh, 0);
+        let mut min = vertices[0].to_array();
+        let mut max = vertices[0].to_array();
 
         for i in 0..vertices.len() {
-            let vertex = mesh::get_vertex(mesh, i);
-            sum += Vec3::from_slice(&vertex);
+            let vertex = vertices[i].to_array();
+            sum += vertices[i];
             for index in 0..2 {
                 min[index] = f32::min(min[index], vertex[index]);
                 max[index] = f32::max(max[index], vertex[index]);
@@ -280,6 +287,9 @@ pub fn draw_skin_mesh(
     mut debug_drawer: ResMut<DebugDrawer>,
     transform_state: Res<transform::State>,
     egui_state: Res<egui::State>,
+    gpu_state: Res<gpu_skinning::State>,
+    skinning_materials: Res<Assets<gpu_skinning::SkinningMaterial>>,
+    weight_editor_state: Res<weight_editor::State>,
 ) {
     if !debug_drawer.mesh_debug_enabled {
         return;
@@ -292,7 +302,8 @@ pub fn draw_skin_mesh(
         }
         let mesh = opt_mesh.unwrap();
 
-        let vertices: Vec<Vec3> = mesh::get_vertices(mesh);
+        let vertices: Vec<Vec3> =
+            gpu_skinning::get_vertices(mesh, entity, &gpu_state, &skinning_materials);
 
         let color = if transformable.is_selected {
             COLOR_SELECTED
@@ -325,7 +336,7 @@ pub fn draw_skin_mesh(
                     for j in 0..v_mapping.bones.len() {
                         if v_mapping.bones[j] == bone_entity {
                             let weight = v_mapping.weights[j];
-                            vertex_color = Color::rgb(weight, 1.0 - weight, 0.0);
+                            vertex_color = weight_paint::get_heatmap_color(weight).into();
                             vertex_size = 10.0;
                             break;
                         }
@@ -343,25 +354,15 @@ pub fn draw_skin_mesh(
             debug_drawer.square(vertices[i].truncate(), vertex_size, vertex_color);
         }
 
-        // draw LINES
-        let mut i = 2;
-        let mut lines_hashset: HashSet<u32> = HashSet::new();
-        while i < skin.indices.len() {
-            let inds = [
-                skin.indices[i] as usize,
-                skin.indices[i - 1] as usize,
-                skin.indices[i - 2] as usize,
-            ];
-            // Add each unique combination of indices to lines_hashset
-            for j in 0..inds.len() {
-                let mut ii = [inds[j] as u32, inds[(j + 1) % inds.len()] as u32];
-                ii.sort_unstable();
-                // Store both indices as a single u32
-                lines_hashset.insert((ii[0] << 16) + ii[1]);
+        // Highlight vertices selected in the weight editor
+        for selected in weight_editor_state.selected_vertices.iter() {
+            if selected.skin == entity && selected.index < vertices.len() {
+                debug_drawer.square(vertices[selected.index].truncate(), 14.0, COLOR_SELECTED_VERTEX);
             }
-            i += 3;
         }
-        for line in lines_hashset {
+
+        // draw LINES
+        for line in get_mesh_lines(&skin.indices) {
             debug_drawer.line_thick(
                 vertices[(line >> 16) as usize].truncate(), // 16 most significant bits
                 vertices[(line & RIGHT_HALF_BITMASK) as usize].truncate(), // 16 least significant bits
@@ -372,6 +373,90 @@ pub fn draw_skin_mesh(
     }
 }
 
+/// Returns each edge of a triangle mesh once, with both vertex indices stored in a single u32
+fn get_mesh_lines(indices: &Vec<u16>) -> HashSet<u32> {
+    let mut i = 2;
+    let mut lines_hashset: HashSet<u32> = HashSet::new();
+    while i < indices.len() {
+        let inds = [
+            indices[i] as usize,
+            indices[i - 1] as usize,
+            indices[i - 2] as usize,
+        ];
+        // Add each unique combination of indices to lines_hashset
+        for j in 0..inds.len() {
+            let mut ii = [inds[j] as u32, inds[(j + 1) % inds.len()] as u32];
+            ii.sort_unstable();
+            // Store both indices as a single u32
+            lines_hashset.insert((ii[0] << 16) + ii[1]);
+        }
+        i += 3;
+    }
+    lines_hashset
+}
+
+/// Draws the mesh of bound skins deformed with the skinning method, that they don't use, to compare both
+pub fn draw_skinning_comparison(
+    meshes: Res<Assets<Mesh>>,
+    skin_state: Res<skin::State>,
+    skeleton: Res<skeleton::Skeleton>,
+    mut debug_drawer: ResMut<DebugDrawer>,
+    q_bones: Query<(&Transform, Option<&Parent>), With<bone::Bone>>,
+    q_bone_entities: Query<(Entity, &bone::Bone)>,
+    q_skins: Query<&skin::Skin>,
+) {
+    if !skin_state.compare_skinning_methods {
+        return;
+    }
+
+    let mut bone_gl_transforms: HashMap<Entity, Transform> = HashMap::new();
+    for (entity, _) in q_bone_entities.iter() {
+        if let Some(gl_transform) = bone::get_bone_gl_transform(entity, &q_bones) {
+            bone_gl_transforms.insert(entity, gl_transform);
+        }
+    }
+    let bind_gl_transforms = skeleton::get_bind_gl_transforms(&q_bones, &q_bone_entities);
+    let skinning_transforms =
+        skeleton::get_skinning_transforms(&bone_gl_transforms, &bind_gl_transforms);
+
+    for skin_mapping in skeleton.skin_mappings.iter() {
+        if skin_mapping.vertex_mappings.is_empty() {
+            continue;
+        }
+        let skin = match skin_mapping.skin.map(|entity| q_skins.get(entity)) {
+            Some(Ok(skin)) => skin,
+            _ => continue,
+        };
+        let mesh = match meshes.get(&skin.mesh_handle.clone().unwrap().0) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        let (other_method, color) = match skin.skinning_method {
+            skeleton::SkinningMethod::Linear => {
+                (skeleton::SkinningMethod::DualQuaternion, COLOR_GREEN_TRANSPARENT)
+            }
+            skeleton::SkinningMethod::DualQuaternion => {
+                (skeleton::SkinningMethod::Linear, COLOR_RED_TRANSPARENT)
+            }
+        };
+        let vertices = skeleton::get_skinned_vertices(
+            skin_mapping,
+            skin,
+            mesh,
+            other_method,
+            &skinning_transforms,
+        );
+        for line in get_mesh_lines(&skin.indices) {
+            debug_drawer.line_thick(
+                Vec2::from_slice(&vertices[(line >> 16) as usize]),
+                Vec2::from_slice(&vertices[(line & RIGHT_HALF_BITMASK) as usize]),
+                color,
+                2.,
+            )
+        }
+    }
+}
+
 pub fn draw_bones(
     mut debug_drawer: ResMut<DebugDrawer>,
     cursor_pos: Res<CursorPos>,
@@ -410,6 +495,9 @@ pub fn draw_bones(
 
     let bone_entities: Vec<Entity> = set.p0().iter().map(|(entity, _)| entity).collect();
     for entity in bone_entities {
+        if set.p2().get(entity).unwrap().is_hidden {
+            continue;
+        }
         let transform = set.p1().get(entity).expect("bone entity doesn't exist").0.clone();
         let opt_bone_gl_transform = bone::get_bone_gl_transform(entity, &set.p1());
         if let Some(gl_transform) = opt_bone_gl_transform {
@@ -432,7 +520,17 @@ pub fn draw_bones(
                             set.p0().get(entity).unwrap().1.ik_angle_constraint.clone()
                         {
                             let mut angle = angle_constraint.start;
-                            let radius = f32::max(gl_transform.scale.y / 3.0, 0.3);
+                            let radius = bone::get_angle_constraint_radius(&gl_transform);
+                            let parent_gl_rotation = gl_transform.rotation * transform.rotation.inverse();
+
+                            // Handles to drag the start and end of the constraint
+                            let (start, end) = bone::get_angle_constraint_handles(
+                                &angle_constraint,
+                                &gl_transform,
+                                parent_gl_rotation,
+                            );
+                            debug_drawer.square(start, 9., COLOR_GREEN);
+                            debug_drawer.square(end, 9., COLOR_GREEN);
                             for angle_constraint_entity in set.p3().iter() {
                                 // Calculate polygon points
                                 let mut points = vec![Vec2::splat(0.)];
@@ -455,7 +553,7 @@ pub fn draw_bones(
                                             options: FillOptions::default(),
                                         }),
                                         Transform {
-                                            rotation: gl_transform.rotation * transform.rotation.inverse(),
+                                            rotation: parent_gl_rotation,
                                             translation: gl_transform
                                                 .translation
                                                 .truncate()
@@ -470,7 +568,6 @@ pub fn draw_bones(
                 }
             }
 
-            let z = 0.001;
             let color = if set.p2().get(entity).unwrap().is_selected {
                 if set.p2().get(entity).unwrap().is_part_of_layer {
                     COLOR_SELECTED_ACTIVE
@@ -484,29 +581,87 @@ pub fn draw_bones(
                     COLOR_DEFAULT
                 }
             };
-            let mut points = vec![
-                Vec3::new(0., 0., z),
-                Vec3::new(-0.1, 0.1, z),
-                Vec3::new(0., 1., z),
-                Vec3::new(0.1, 0.1, z),
-                Vec3::new(0., 0., z),
-            ];
-            for i in 0..points.len() {
-                points[i].x *= gl_transform.scale.x;
-                points[i].y *= gl_transform.scale.y;
-            }
-            for i in 0..points.len() {
-                debug_drawer.line_thick(
-                    (gl_transform.translation + Quat::mul_vec3(gl_transform.rotation, points[i]))
-                        .truncate(),
-                    (gl_transform.translation
-                        + Quat::mul_vec3(gl_transform.rotation, points[(i + 1) % points.len()]))
-                    .truncate(),
-                    color,
-                    5.,
-                );
+            draw_bone(&mut debug_drawer, &gl_transform, color);
+        }
+    }
+}
+
+/// Draw the outline of a bone with the given global transform
+pub fn draw_bone(debug_drawer: &mut DebugDrawer, gl_transform: &Transform, color: Color) {
+    let z = 0.001;
+    let mut points = vec![
+        Vec3::new(0., 0., z),
+        Vec3::new(-0.1, 0.1, z),
+        Vec3::new(0., 1., z),
+        Vec3::new(0.1, 0.1, z),
+        Vec3::new(0., 0., z),
+    ];
+    for i in 0..points.len() {
+        points[i].x *= gl_transform.scale.x;
+        points[i].y *= gl_transform.scale.y;
+    }
+    for i in 0..points.len() {
+        debug_drawer.line_thick(
+            (gl_transform.translation + Quat::mul_vec3(gl_transform.rotation, points[i]))
+                .truncate(),
+            (gl_transform.translation
+                + Quat::mul_vec3(gl_transform.rotation, points[(i + 1) % points.len()]))
+            .truncate(),
+            color,
+            5.,
+        );
+    }
+    debug_drawer.square(gl_transform.translation.truncate(), 7., color);
+}
+
+pub fn draw_motion_path(
+    mut debug_drawer: ResMut<DebugDrawer>,
+    motion_path_state: Res<motion_path::State>,
+    egui_state: Res<egui::State>,
+) {
+    let path = &motion_path_state.path;
+    for i in 1..path.len() {
+        debug_drawer.line_thick(path[i - 1], path[i], COLOR_LIGHTER_GRAY, 2.);
+    }
+
+    // Draw a dot for each keyframe
+    let selected_keyframe_index = egui_state.plots[egui_state.edit_plot].selected_keyframe_index;
+    for (i, &point) in motion_path_state.keyframe_points.iter().enumerate() {
+        let color = if i == selected_keyframe_index {
+            COLOR_SELECTED
+        } else {
+            COLOR_DEFAULT
+        };
+        debug_drawer.square(point, 9., color);
+    }
+}
+
+pub fn draw_paths(
+    mut debug_drawer: ResMut<DebugDrawer>,
+    q_paths: Query<&path_constraint::PathConstraint>,
+    q_points: Query<&Transform, With<path_constraint::PathPoint>>,
+) {
+    if !debug_drawer.bone_debug_enabled {
+        return;
+    }
+    for path in q_paths.iter() {
+        let points: Vec<Vec2> = path
+            .points
+            .iter()
+            .filter_map(|&point| q_points.get(point).ok())
+            .map(|transform| transform.translation.truncate())
+            .collect();
+        // Bezier handles
+        if path.spline_type == path_constraint::SplineType::Bezier {
+            for i in 1..points.len() {
+                if i % 3 != 2 {
+                    debug_drawer.line(points[i - 1], points[i], COLOR_LIGHT_GRAY);
+                }
             }
-            debug_drawer.square(gl_transform.translation.truncate(), 7., color);
+        }
+        let polyline = path_constraint::sample_spline(&points, path.spline_type);
+        for i in 1..polyline.len() {
+            debug_drawer.line_thick(polyline[i - 1], polyline[i], COLOR_LIGHTER_GRAY, 2.);
         }
     }
 }
@@ -521,10 +676,24 @@ pub fn enable_debug_lines(keys: Res<Input<KeyCode>>, mut debug_drawer: ResMut<De
 }
 
 pub fn draw_ccd_target(
-    debug_drawer: Res<DebugDrawer>,
-    mut q: Query<(&Transformable, &mut Visibility, &mut Sprite), With<inverse_kinematics::Target>>,
+    mut debug_drawer: ResMut<DebugDrawer>,
+    mut q: Query<
+        (
+            &Transformable,
+            &mut Visibility,
+            &mut Sprite,
+            &Transform,
+            Option<&inverse_kinematics::Target>,
+        ),
+        Or<(
+            With<inverse_kinematics::Target>,
+            With<inverse_kinematics::Pole>,
+            With<path_constraint::PathPoint>,
+        )>,
+    >,
+    q_poles: Query<&Transform, With<inverse_kinematics::Pole>>,
 ) {
-    for (transformable, mut visibility, mut sprite) in q.iter_mut() {
+    for (transformable, mut visibility, mut sprite, transform, opt_target) in q.iter_mut() {
         if debug_drawer.bone_debug_enabled {
             visibility.is_visible = true;
         } else {
@@ -544,6 +713,21 @@ pub fn draw_ccd_target(
                 COLOR_DEFAULT
             }
         };
+
+        let target = match opt_target {
+            Some(target) => target,
+            None => continue,
+        };
+        let center = transform.translation.truncate();
+        // Show the rotation the end effector has to match
+        if target.match_rotation {
+            let dir = transform.rotation.mul_vec3(Vec3::Y).truncate();
+            debug_drawer.line_thick(center, center + dir * 0.6, sprite.color, 2.);
+        }
+        // Connect target and pole
+        if let Some(pole_transform) = target.pole.and_then(|pole| q_poles.get(pole).ok()) {
+            debug_drawer.line(center, pole_transform.translation.truncate(), COLOR_LIGHT_GRAY);
+        }
     }
 }
 
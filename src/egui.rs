@@ -3,6 +3,7 @@ use crate::{
     save_load::SaveEvent,
     *,
 };
+use bevy::utils::{HashMap, HashSet};
 use bevy_egui::{
     egui::{
         self,
@@ -11,9 +12,11 @@ use bevy_egui::{
     },
     EguiContext,
 };
+use constraints::{Constraint, ConstraintStack, ConstraintType};
 use interpolate::Function;
 use inverse_kinematics::*;
-use std::{f32::consts::PI, fs};
+use path_constraint::{PathConstraint, SplineType};
+use std::fs;
 
 pub struct PlotState {
     pub name: String,
@@ -31,12 +34,18 @@ impl Default for PlotState {
 pub struct OpenWindows {
     pub is_open_animations: bool,
     pub is_open_skins: bool,
+    pub is_open_history: bool,
+    pub is_open_bone: bool,
+    pub is_open_outliner: bool,
 }
 impl Default for OpenWindows {
     fn default() -> Self {
         OpenWindows {
             is_open_animations: false,
             is_open_skins: false,
+            is_open_history: false,
+            is_open_bone: false,
+            is_open_outliner: false,
         }
     }
 }
@@ -62,8 +71,14 @@ pub struct State {
     pub delaunay_triangle_size: f32,
     pub delaunay_borderline_width: f32,
     pub adjust_vertex_weights_mode: bool,
+    pub weighting_method: skeleton::WeightingMethod,
+    /// Maximum number of bones influencing a vertex, when binding or limiting influences
+    pub max_influences: usize,
+    pub prune_threshold: f32,
     pub brush_size: f32,
     pub save_filename: String,
+    /// The current pose becomes the bind pose, after the UI has been shown
+    pub set_bind_pose: bool,
 }
 impl Default for State {
     fn default() -> Self {
@@ -88,8 +103,12 @@ impl Default for State {
             delaunay_triangle_size: 15.,
             delaunay_borderline_width: 3.,
             adjust_vertex_weights_mode: false,
+            weighting_method: skeleton::WeightingMethod::Heat,
+            max_influences: gpu_skinning::MAX_BONES_PER_VERTEX,
+            prune_threshold: 0.05,
             brush_size: 0.5,
             save_filename: String::from("my_animation"),
+            set_bind_pose: false,
         }
     }
 }
@@ -101,19 +120,311 @@ pub fn system_set() -> SystemSet {
                 .before(skin_menu)
                 .before(animation_menu)
                 .before(get_selection_stats)
+                .before(history_menu)
+                .before(bone_menu)
+                .before(outliner_menu)
+                .before(recovery_prompt)
                 .before(panel),
         )
         .with_system(panel)
         .with_system(skin_menu)
         .with_system(animation_menu)
+        .with_system(history_menu)
+        .with_system(bone_menu)
+        .with_system(outliner_menu)
+        .with_system(recovery_prompt)
         .with_system(get_selection_stats)
 }
 
-fn skin_settings(ui: &mut Ui, state: &mut State, skin_state: &mut skin::State) {
+fn onion_skin_settings(ui: &mut Ui, onion_skin_state: &mut onion_skin::State) {
     ui.horizontal(|ui| {
-        if ui.button("toogle adjust weights mode").clicked() {
+        ui.checkbox(&mut onion_skin_state.enabled, "onion skinning");
+        if ui
+            .button(format!("mode: {}", onion_skin_state.mode.to_string()))
+            .clicked()
+        {
+            onion_skin_state.mode = match onion_skin_state.mode {
+                onion_skin::Mode::Keyframes => onion_skin::Mode::TimeOffsets,
+                onion_skin::Mode::TimeOffsets => onion_skin::Mode::Keyframes,
+            };
+        }
+    });
+
+    if !onion_skin_state.enabled {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut onion_skin_state.show_bones, "bones");
+        ui.checkbox(&mut onion_skin_state.show_skins, "skins");
+    });
+    ui.horizontal(|ui| {
+        ui.label("previous: ");
+        ui.add(egui::DragValue::new(&mut onion_skin_state.count_previous).clamp_range(0..=10));
+        ui.color_edit_button_rgba_unmultiplied(&mut onion_skin_state.tint_previous);
+        ui.label("next: ");
+        ui.add(egui::DragValue::new(&mut onion_skin_state.count_next).clamp_range(0..=10));
+        ui.color_edit_button_rgba_unmultiplied(&mut onion_skin_state.tint_next);
+    });
+    if onion_skin_state.mode == onion_skin::Mode::TimeOffsets {
+        ui.horizontal(|ui| {
+            ui.label("offset: ");
+            ui.add(
+                egui::DragValue::new(&mut onion_skin_state.time_offset)
+                    .speed(0.01)
+                    .clamp_range(0.01..=10.0)
+                    .suffix("s"),
+            );
+        });
+    }
+}
+
+fn motion_path_settings(ui: &mut Ui, motion_path_state: &mut motion_path::State) {
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut motion_path_state.enabled, "motion path");
+        if motion_path_state.enabled {
+            ui.label("samples per second: ");
+            ui.add(
+                egui::DragValue::new(&mut motion_path_state.samples_per_second)
+                    .clamp_range(1.0..=240.0),
+            );
+        }
+    });
+}
+
+/// Weight pruning and normalization for the bound skins among the selected entities.
+///
+/// The skeleton is only dereferenced mutably when a button is clicked, otherwise it would be
+/// marked as changed every frame and all GPU skins would be updated.
+fn weight_tools(
+    ui: &mut Ui,
+    state: &mut State,
+    skeleton: &mut ResMut<skeleton::Skeleton>,
+    transform_state: &transform::State,
+    history: &mut history::History,
+) {
+    ui.label("WEIGHTS");
+    ui.horizontal(|ui| {
+        ui.label("Max influences: ");
+        ui.add(egui::DragValue::new(&mut state.max_influences).clamp_range(1..=16));
+        ui.label("Prune threshold: ");
+        ui.add(
+            egui::DragValue::new(&mut state.prune_threshold)
+                .speed(0.01)
+                .clamp_range(0.0..=1.0),
+        );
+    });
+
+    let selected_mapping_indices: Vec<usize> = (0..skeleton.skin_mappings.len())
+        .filter(|&i| match skeleton.skin_mappings[i].skin {
+            Some(skin) => transform_state.selected_entities.contains(&skin),
+            None => false,
+        })
+        .filter(|&i| !skeleton.skin_mappings[i].vertex_mappings.is_empty())
+        .collect();
+    if selected_mapping_indices.is_empty() {
+        ui.label("select a bound skin");
+        return;
+    }
+
+    let (mut vertex_count, mut exceeding_count) = (0, 0);
+    for &i in selected_mapping_indices.iter() {
+        vertex_count += skeleton.skin_mappings[i].vertex_mappings.len();
+        exceeding_count += skeleton.skin_mappings[i].count_exceeding_influences(state.max_influences);
+    }
+    ui.label(format!(
+        "{} of {} vertices exceed {} influences",
+        exceeding_count, vertex_count, state.max_influences
+    ));
+
+    ui.horizontal(|ui| {
+        if ui.button("limit influences").clicked() {
+            for &i in selected_mapping_indices.iter() {
+                skeleton.skin_mappings[i].limit_influences(state.max_influences);
+            }
+            history.record_snapshot("Limit influences");
+        }
+        if ui.button("prune weights").clicked() {
+            for &i in selected_mapping_indices.iter() {
+                skeleton.skin_mappings[i].prune_weights(state.prune_threshold);
+            }
+            history.record_snapshot("Prune weights");
+        }
+        if ui.button("normalize all").clicked() {
+            for &i in selected_mapping_indices.iter() {
+                skeleton.skin_mappings[i].normalize_weights();
+            }
+            history.record_snapshot("Normalize weights");
+        }
+    });
+}
+
+fn paint_settings(ui: &mut Ui, state: &mut State, paint_state: &mut weight_paint::State) {
+    ui.label("PAINT WEIGHTS");
+    ui.horizontal(|ui| {
+        if ui
+            .selectable_label(state.adjust_vertex_weights_mode, "paint mode")
+            .on_hover_text("W, select the bone to paint in the Outliner")
+            .clicked()
+        {
             state.adjust_vertex_weights_mode = !state.adjust_vertex_weights_mode;
-        };
+        }
+        ui.checkbox(&mut paint_state.show_heatmap, "heatmap");
+    });
+    ui.horizontal(|ui| {
+        ui.label("Brush: ");
+        egui::ComboBox::from_id_source("brush_mode")
+            .selected_text(paint_state.mode.to_string())
+            .show_ui(ui, |ui| {
+                for mode in weight_paint::BrushMode::all() {
+                    ui.selectable_value(&mut paint_state.mode, mode, mode.to_string());
+                }
+            });
+        ui.label("Falloff: ");
+        egui::ComboBox::from_id_source("brush_falloff")
+            .selected_text(paint_state.falloff.to_string())
+            .show_ui(ui, |ui| {
+                for falloff in weight_paint::BrushFalloff::all() {
+                    ui.selectable_value(&mut paint_state.falloff, falloff, falloff.to_string());
+                }
+            });
+    });
+    ui.horizontal(|ui| {
+        ui.label("Size: ");
+        ui.add(
+            egui::DragValue::new(&mut state.brush_size)
+                .speed(0.01)
+                .clamp_range(0.05..=f32::MAX),
+        );
+        ui.label("Strength: ");
+        ui.add(
+            egui::DragValue::new(&mut paint_state.strength)
+                .speed(0.01)
+                .clamp_range(0.0..=1.0),
+        );
+        if paint_state.mode != weight_paint::BrushMode::Smooth {
+            ui.label("Weight: ");
+            ui.add(
+                egui::DragValue::new(&mut paint_state.weight)
+                    .speed(0.01)
+                    .clamp_range(0.0..=1.0),
+            );
+        }
+    });
+    if paint_state.has_locked_vertices {
+        ui.colored_label(
+            Color32::LIGHT_YELLOW,
+            "some vertices are only influenced by this bone and keep their full weight, paint another bone onto them first",
+        );
+    }
+}
+
+/// Weights of the selected vertices. Like in [`weight_tools`], the skeleton is only dereferenced
+/// mutably when a weight is changed.
+fn vertex_weight_editor(
+    ui: &mut Ui,
+    editor_state: &mut weight_editor::State,
+    skeleton: &mut ResMut<skeleton::Skeleton>,
+    q_bones: &Query<&bone::Bone>,
+    history: &mut history::History,
+) {
+    ui.label("VERTEX WEIGHTS");
+    if editor_state.selected_vertices.is_empty() {
+        ui.label("hold V and click on vertices of a bound skin to select them");
+    }
+    egui::ScrollArea::vertical().max_height(200.).show(ui, |ui| {
+        for (i, selected) in editor_state.selected_vertices.iter().enumerate() {
+            let skin_mapping_index = match skeleton
+                .skin_mappings
+                .iter()
+                .position(|skin_mapping| skin_mapping.skin == Some(selected.skin))
+            {
+                Some(index) => index,
+                None => continue,
+            };
+            let mapping = match skeleton.skin_mappings[skin_mapping_index]
+                .vertex_mappings
+                .get(selected.index)
+            {
+                Some(mapping) => mapping,
+                None => continue,
+            };
+            let mut changed_weight = None;
+            ui.label(format!("vertex {}", selected.index));
+            egui::Grid::new(("vertex_weights", i)).show(ui, |ui| {
+                for j in 0..mapping.bones.len() {
+                    let bone_entity = mapping.bones[j];
+                    let name = match q_bones.get(bone_entity) {
+                        Ok(bone) if !bone.name.is_empty() => bone.name.clone(),
+                        _ => format!("bone {:?}", bone_entity),
+                    };
+                    ui.label(name);
+                    let mut weight = mapping.weights[j];
+                    // The only bone of a vertex has nowhere to move its weight to
+                    let response = ui
+                        .add_enabled(
+                            weight < 1. || weight_paint::can_lower_weight(mapping, bone_entity),
+                            egui::DragValue::new(&mut weight)
+                                .speed(0.01)
+                                .clamp_range(0.0..=1.0),
+                        )
+                        .on_disabled_hover_text("the only bone of the vertex keeps its full weight");
+                    if response.changed() {
+                        changed_weight = Some((bone_entity, weight));
+                    }
+                    if is_edit_finished(&response) {
+                        history.record_snapshot("Edit vertex weight");
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some((bone_entity, weight)) = changed_weight {
+                let mapping =
+                    &mut skeleton.skin_mappings[skin_mapping_index].vertex_mappings[selected.index];
+                // The other weights are scaled, so that all weights still sum up to 1
+                weight_paint::set_weight(mapping, bone_entity, weight, Vec2::ZERO);
+            }
+        }
+    });
+
+    ui.horizontal(|ui| {
+        if ui.button("copy weights").clicked() {
+            editor_state.pending_action = Some(weight_editor::Action::Copy);
+        }
+        if ui
+            .add_enabled(
+                editor_state.has_copied_weights(),
+                egui::Button::new("paste weights"),
+            )
+            .clicked()
+        {
+            editor_state.pending_action = Some(weight_editor::Action::Paste);
+        }
+        if ui
+            .button("mirror weights")
+            .on_hover_text("copies the weights of the selected vertices to the vertices on the opposite side of the skin's center, swapping left and right bones")
+            .clicked()
+        {
+            editor_state.pending_action = Some(weight_editor::Action::Mirror);
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("mirror across ");
+        for axis in pose::MirrorAxis::all() {
+            ui.radio_value(&mut editor_state.mirror_axis, axis, axis.to_string());
+        }
+    });
+}
+
+fn skin_settings(
+    ui: &mut Ui,
+    state: &mut State,
+    skin_state: &mut skin::State,
+    gpu_state: &mut gpu_skinning::State,
+) {
+    ui.checkbox(&mut gpu_state.enabled, "GPU skinning")
+        .on_hover_text("cloths, dual quaternion skins and skins with more than four bones per vertex are deformed on the CPU");
+    ui.horizontal(|ui| {
         ui.label(if state.skin_bound_status_is_valid {
             if state.skin_is_bound {
                 String::from("skin is bound")
@@ -124,6 +435,16 @@ fn skin_settings(ui: &mut Ui, state: &mut State, skin_state: &mut skin::State) {
             String::from("-")
         });
     });
+    ui.horizontal(|ui| {
+        ui.label("Automatic weights: ");
+        egui::ComboBox::from_id_source("weighting_method")
+            .selected_text(state.weighting_method.to_string())
+            .show_ui(ui, |ui| {
+                for method in skeleton::WeightingMethod::all() {
+                    ui.selectable_value(&mut state.weighting_method, method, method.to_string());
+                }
+            });
+    });
 
     ui.separator();
 
@@ -367,7 +688,9 @@ fn animations_all(
     show_keyframe_evw: &mut EventWriter<animation::ShowKeyframeEvent>,
     q: &Query<&mut Transform>,
     q_bones: &mut Query<(Entity, &Transformable, &mut bone::Bone)>,
+    q_targets: &mut Query<&mut Target>,
     transform_state: &transform::State,
+    history: &mut history::History,
 ) {
     // LAYERS
     ui.label("LAYERS");
@@ -439,60 +762,134 @@ fn animations_all(
     // Inverse Kinematics
     ui.label("INVERSE KINEMATICS");
     ui.horizontal(|ui| {
-        if ui.button(state.ik_method.to_string()).clicked() {
-            state.ik_method = if state.ik_method == IKMethod::CCD {
-                IKMethod::Jacobian
-            } else {
-                IKMethod::CCD
-            };
-        }
+        ui.label("Method: ");
+        egui::ComboBox::from_id_source("ik_method")
+            .selected_text(state.ik_method.to_string())
+            .show_ui(ui, |ui| {
+                for method in IKMethod::all() {
+                    ui.selectable_value(&mut state.ik_method, method, method.to_string());
+                }
+            });
         ui.label("Depth: ");
         ui.add(
             egui::DragValue::new(&mut state.ik_depth)
                 .speed(1)
                 .clamp_range(1..=30),
         );
-        ui.label("Max Iterations (global): ");
+        ui.label("Max Iterations: ");
         ui.add(
             egui::DragValue::new(&mut state.ik_max_iterations)
                 .speed(1)
                 .clamp_range(1..=50),
         );
     });
-
-    ui.separator();
-
-    // Set Angle Constraints
-    ui.label("ANGLE CONSTRAINTS (only CCD, saving not currently supported)");
-    ui.horizontal(|ui| {
-        if let Some(&first_selected_entity) = transform_state.selected_entities.iter().next() {
-            if let Ok((_, _, mut bone)) = q_bones.get_mut(first_selected_entity) {
-                if let Some(angle_constraint) = &mut bone.ik_angle_constraint {
-                    ui.label("Start: ");
-                    ui.add(
-                        egui::DragValue::new(&mut angle_constraint.start)
-                            .speed(0.1)
-                            .custom_formatter(|n, _| format!("{:.1}°", n.to_degrees())),
-                    );
-                    ui.label("End: ");
-                    ui.add(
-                        egui::DragValue::new(&mut angle_constraint.end)
-                            .speed(0.1)
-                            .custom_formatter(|n, _| format!("{:.1}°", n.to_degrees())),
-                    );
-                    if ui.button("No Constraint").clicked() {
-                        angle_constraint.start = 0.0;
-                        angle_constraint.end = 0.0;
+    // Method of the selected target, new targets use the method above
+    let opt_selected_target = transform_state
+        .selected_entities
+        .iter()
+        .find(|&&entity| q_targets.contains(entity))
+        .copied();
+    if let Some(mut target) = opt_selected_target.and_then(|entity| q_targets.get_mut(entity).ok()) {
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut target.enabled, "Enabled").changed() {
+                history.record_snapshot("Toggle IK target");
+            }
+            if ui.checkbox(&mut target.match_rotation, "Match Rotation").changed() {
+                history.record_snapshot("Toggle IK rotation goal");
+            }
+            // Poles are placed with Alt + LMouse while the target is selected
+            if target.pole.is_some() && ui.button("Remove Pole").clicked() {
+                target.pole = None;
+                history.record_snapshot("Remove pole");
+            }
+            // Can be keyed like the target's transform
+            ui.label("Weight: ");
+            let response = ui.add(
+                egui::DragValue::new(&mut target.weight)
+                    .speed(0.01)
+                    .clamp_range(0.0..=1.0),
+            );
+            if is_edit_finished(&response) {
+                history.record_snapshot("Change IK weight");
+            }
+            // Decides between targets whose chains share bones
+            ui.label("Priority: ");
+            let response = ui.add(egui::DragValue::new(&mut target.priority).clamp_range(0..=10));
+            if is_edit_finished(&response) {
+                history.record_snapshot("Change IK priority");
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Selected target: ");
+            let mut ik_method = target.ik_method;
+            egui::ComboBox::from_id_source("target_ik_method")
+                .selected_text(ik_method.to_string())
+                .show_ui(ui, |ui| {
+                    for method in IKMethod::all() {
+                        ui.selectable_value(&mut ik_method, method, method.to_string());
                     }
-                    angle_constraint.start = (angle_constraint.start + 2. * PI) % (2. * PI);
-                    angle_constraint.end = (angle_constraint.end + 2. * PI) % (2. * PI);
-                    if angle_constraint.end < angle_constraint.start {
-                        angle_constraint.end += 2. * PI;
+                });
+            if ik_method != target.ik_method {
+                target.ik_method = ik_method;
+                history.record_snapshot("Change IK method");
+            }
+        });
+        if target.ik_method == IKMethod::TwoBone {
+            ui.horizontal(|ui| {
+                ui.label("Bend: ");
+                for bend_direction in BendDirection::all() {
+                    if ui
+                        .radio(target.bend_direction == bend_direction, bend_direction.to_string())
+                        .clicked()
+                        && target.bend_direction != bend_direction
+                    {
+                        target.bend_direction = bend_direction;
+                        history.record_snapshot("Change bend direction");
                     }
                 }
-            }
+                ui.label("Soft Limit: ");
+                let response = ui.add(
+                    egui::DragValue::new(&mut target.soft_limit)
+                        .speed(0.005)
+                        .clamp_range(0.0..=0.5),
+                );
+                if is_edit_finished(&response) {
+                    history.record_snapshot("Change soft limit");
+                }
+            });
         }
-    });
+        ui.horizontal(|ui| {
+            ui.label("Tolerance: ");
+            let response = ui.add(
+                egui::DragValue::new(&mut target.tolerance)
+                    .speed(0.001)
+                    .clamp_range(0.0001..=1.0),
+            );
+            if is_edit_finished(&response) {
+                history.record_snapshot("Change IK tolerance");
+            }
+            ui.label("Max Iterations: ");
+            let response = ui.add(
+                egui::DragValue::new(&mut target.max_iterations)
+                    .speed(1)
+                    .clamp_range(1..=200),
+            );
+            if is_edit_finished(&response) {
+                history.record_snapshot("Change IK iterations");
+            }
+            if target.ik_method == IKMethod::DampedLeastSquares {
+                ui.label("Damping: ");
+                let response = ui.add(
+                    egui::DragValue::new(&mut target.damping)
+                        .speed(0.005)
+                        .clamp_range(0.001..=2.0),
+                );
+                if is_edit_finished(&response) {
+                    history.record_snapshot("Change IK damping");
+                }
+            }
+        });
+    }
 
     ui.separator();
 
@@ -768,6 +1165,18 @@ pub fn panel(
                 if ui.button("Skins").clicked() {
                     open_windows.is_open_skins = !open_windows.is_open_skins;
                 }
+                ui.add_space(7.);
+                if ui.button("History").clicked() {
+                    open_windows.is_open_history = !open_windows.is_open_history;
+                }
+                ui.add_space(7.);
+                if ui.button("Bone").clicked() {
+                    open_windows.is_open_bone = !open_windows.is_open_bone;
+                }
+                ui.add_space(7.);
+                if ui.button("Outliner").clicked() {
+                    open_windows.is_open_outliner = !open_windows.is_open_outliner;
+                }
             });
             ui.add_space(7.);
         })
@@ -787,9 +1196,15 @@ pub fn animation_menu(
     keys: Res<Input<KeyCode>>,
     mut anim_state: ResMut<animation::State>,
     mut q: Query<&mut Transform>,
-    mut q_bones: Query<(Entity, &transform::Transformable, &mut bone::Bone)>,
+    (mut q_bones, mut q_targets): (
+        Query<(Entity, &transform::Transformable, &mut bone::Bone)>,
+        Query<&mut Target>,
+    ),
     mut save_evw: EventWriter<save_load::SaveEvent>,
     mut open_windows: ResMut<OpenWindows>,
+    mut onion_skin_state: ResMut<onion_skin::State>,
+    mut motion_path_state: ResMut<motion_path::State>,
+    mut history: ResMut<history::History>,
 ) {
     // Hide window when transforming
     if transform_state.action != transform::Action::None
@@ -816,6 +1231,13 @@ pub fn animation_menu(
     //         .collect();
     // }
 
+    // Animations are only edited with the mouse, so they are only copied and compared while egui uses it
+    let is_editing = open_windows.is_open_animations
+        && egui_context.ctx_mut().wants_pointer_input()
+        && (mouse.pressed(MouseButton::Left) || mouse.just_released(MouseButton::Left));
+    let opt_animations_before =
+        is_editing.then(|| history::AnimationsSnapshot::new(&animations, &anim_state));
+
     // Show Window
     let opt_response = egui::Window::new("Animations")
         .open(&mut open_windows.is_open_animations)
@@ -863,8 +1285,117 @@ pub fn animation_menu(
                 &mut show_keyframe_evw,
                 &q,
                 &mut q_bones,
+                &mut q_targets,
                 &transform_state,
+                &mut history,
             );
+
+            ui.separator();
+
+            onion_skin_settings(ui, &mut onion_skin_state);
+            motion_path_settings(ui, &mut motion_path_state);
+        });
+
+    if let Some(animations_before) = opt_animations_before {
+        let animations_after = history::AnimationsSnapshot::new(&animations, &anim_state);
+        if animations_after != animations_before {
+            history.push(
+                "Edit animations",
+                history::Command::Animations {
+                    before: animations_before,
+                    after: animations_after,
+                },
+            );
+        }
+    }
+
+    if let Some(inner) = opt_response {
+        check_mouse_interaction(&mut egui_context, inner.response, &mut state, &mouse);
+    }
+}
+
+pub fn history_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State>,
+    mut history: ResMut<history::History>,
+    mut autosave_state: ResMut<autosave::State>,
+    mouse: Res<Input<MouseButton>>,
+    mut open_windows: ResMut<OpenWindows>,
+) {
+    // Show Window
+    let opt_response = egui::Window::new("History")
+        .open(&mut open_windows.is_open_history)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("undo").clicked() {
+                    history.undo();
+                }
+                if ui.button("redo").clicked() {
+                    history.redo();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("memory budget: ");
+                let mut budget_mb = history.memory_budget as f32 / 1_000_000.;
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut budget_mb)
+                            .clamp_range(1.0..=1000.0)
+                            .suffix(" MB"),
+                    )
+                    .changed()
+                {
+                    history.memory_budget = (budget_mb * 1_000_000.) as usize;
+                }
+                ui.label(format!(
+                    "(used: {:.1} MB)",
+                    history.memory_usage() as f32 / 1_000_000.
+                ));
+            });
+
+            ui.separator();
+
+            // Clicking on an entry restores the state after that entry
+            let mut opt_go_to: Option<usize> = None;
+            egui::ScrollArea::vertical()
+                .max_height(300.)
+                .show(ui, |ui| {
+                    let undo_count = history.undo_entries().len();
+                    if ui.selectable_label(undo_count == 0, "initial state").clicked() {
+                        opt_go_to = Some(0);
+                    }
+                    for (i, entry) in history.undo_entries().iter().enumerate() {
+                        if ui.selectable_label(i + 1 == undo_count, &entry.name).clicked() {
+                            opt_go_to = Some(i + 1);
+                        }
+                    }
+                    for (i, entry) in history.redo_entries().iter().rev().enumerate() {
+                        let text = egui::RichText::new(&entry.name).color(Color32::GRAY);
+                        if ui.selectable_label(false, text).clicked() {
+                            opt_go_to = Some(undo_count + i + 1);
+                        }
+                    }
+                });
+            if let Some(count) = opt_go_to {
+                history.go_to(count);
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut autosave_state.enabled, "autosave");
+                if autosave_state.enabled {
+                    ui.label("every ");
+                    ui.add(
+                        egui::DragValue::new(&mut autosave_state.interval)
+                            .clamp_range(5.0..=3600.0)
+                            .suffix("s"),
+                    );
+                    ui.label("keep ");
+                    ui.add(egui::DragValue::new(&mut autosave_state.slot_count).clamp_range(1..=20));
+                }
+            });
         });
 
     if let Some(inner) = opt_response {
@@ -872,6 +1403,650 @@ pub fn animation_menu(
     }
 }
 
+/// Shows translation, rotation in degrees and scale of `transform` as editable fields.
+///
+/// Returns whether a value was changed and whether an edit was finished.
+fn transform_fields(ui: &mut Ui, transform: &mut Transform) -> (bool, bool) {
+    let mut changed = false;
+    let mut finished = false;
+    let mut angle = transform.rotation.to_euler(EulerRot::XYZ).2.to_degrees();
+    let mut field = |ui: &mut Ui, label: &str, value: &mut f32, speed: f64, suffix: &str| {
+        ui.label(label);
+        let response = ui.add(egui::DragValue::new(value).speed(speed).suffix(suffix));
+        changed |= response.changed();
+        finished |= is_edit_finished(&response);
+    };
+    ui.horizontal(|ui| {
+        field(ui, "x: ", &mut transform.translation.x, 0.01, "");
+        field(ui, "y: ", &mut transform.translation.y, 0.01, "");
+        field(ui, "rotation: ", &mut angle, 1., "°");
+    });
+    ui.horizontal(|ui| {
+        field(ui, "scale x: ", &mut transform.scale.x, 0.01, "");
+        field(ui, "scale y: ", &mut transform.scale.y, 0.01, "");
+    });
+    transform.rotation = Quat::from_rotation_z(angle.to_radians());
+    (changed, finished)
+}
+
+/// Editable local and global transform of a bone
+fn bone_transform_settings(
+    ui: &mut Ui,
+    bone_entity: Entity,
+    q_transforms: &mut Query<(&mut Transform, Option<&Parent>), With<bone::Bone>>,
+    history: &mut history::History,
+) {
+    let (mut local, opt_parent) = match q_transforms.get(bone_entity) {
+        Ok((&transform, opt_parent)) => (transform, opt_parent.map(|parent| parent.get())),
+        Err(_) => return,
+    };
+    let mut parent_gl_transform = Transform::default();
+    let mut next_bone = opt_parent;
+    while let Some(bone) = next_bone {
+        match q_transforms.get(bone) {
+            Ok((transform, opt_parent)) => {
+                parent_gl_transform = combined_transform(transform, &parent_gl_transform);
+                next_bone = opt_parent.map(|parent| parent.get());
+            }
+            Err(_) => break,
+        }
+    }
+    let mut gl_transform = combined_transform(&parent_gl_transform, &local);
+
+    ui.label("local");
+    let (local_changed, local_finished) = transform_fields(ui, &mut local);
+    ui.label("global");
+    let (mut gl_changed, mut gl_finished) = transform_fields(ui, &mut gl_transform);
+    ui.horizontal(|ui| {
+        ui.label("length: ");
+        let response = ui.add(
+            egui::DragValue::new(&mut gl_transform.scale.y)
+                .speed(0.01)
+                .clamp_range(0.01..=f32::MAX),
+        );
+        gl_changed |= response.changed();
+        gl_finished |= is_edit_finished(&response);
+    });
+
+    if gl_changed {
+        let z = local.translation.z;
+        local = get_relative_transform(&parent_gl_transform, &gl_transform);
+        local.translation.z = z;
+    }
+    if local_changed || gl_changed {
+        *q_transforms.get_mut(bone_entity).unwrap().0 = local;
+    }
+    if local_finished || gl_finished {
+        history.record_snapshot("Edit bone transform");
+    }
+}
+
+pub fn bone_menu(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State>,
+    transform_state: Res<transform::State>,
+    mut pose_state: ResMut<pose::State>,
+    mut path_state: ResMut<path_constraint::State>,
+    mut history: ResMut<history::History>,
+    mouse: Res<Input<MouseButton>>,
+    mut open_windows: ResMut<OpenWindows>,
+    mut q_bones: Query<(
+        Entity,
+        &mut bone::Bone,
+        Option<&mut PathConstraint>,
+        Option<&mut ConstraintStack>,
+    )>,
+    mut q_transforms: Query<(&mut Transform, Option<&Parent>), With<bone::Bone>>,
+    q_targets: Query<Entity, With<Target>>,
+    mut is_renaming: Local<bool>,
+) {
+    // Hide window when transforming
+    if transform_state.action != transform::Action::None
+        && transform_state.action != transform::Action::Done
+    {
+        return;
+    }
+
+    let opt_selected_bone = transform_state
+        .selected_entities
+        .iter()
+        .find(|&&entity| q_bones.contains(entity))
+        .copied();
+
+    // Bones and IK targets, that constraints can refer to
+    let mut constraint_targets: Vec<(Entity, String)> = q_bones
+        .iter()
+        .map(|(entity, bone, _, _)| {
+            if bone.name.is_empty() {
+                (entity, format!("bone {:?}", entity))
+            } else {
+                (entity, bone.name.clone())
+            }
+        })
+        .collect();
+    constraint_targets.extend(
+        q_targets
+            .iter()
+            .map(|entity| (entity, format!("IK target {:?}", entity))),
+    );
+
+    // Show Window
+    let opt_response = egui::Window::new("Bone")
+        .open(&mut open_windows.is_open_bone)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            match opt_selected_bone.and_then(|entity| q_bones.get_mut(entity).ok()) {
+                Some((_, mut bone, _, _)) => {
+                    ui.horizontal(|ui| {
+                        ui.label("name: ");
+                        let response = ui.text_edit_singleline(&mut bone.name);
+                        if response.changed() {
+                            *is_renaming = true;
+                        }
+                        // One history entry per rename, not per typed character
+                        if response.lost_focus() && *is_renaming {
+                            *is_renaming = false;
+                            history.record_snapshot("Rename bone");
+                        }
+                    });
+
+                    // Angle constraint used by IK, also editable by dragging its handles
+                    if let Some(angle_constraint) = &mut bone.ik_angle_constraint {
+                        ui.horizontal(|ui| {
+                            ui.label("angle constraint: ");
+                            // Stored in radians, but edited in degrees
+                            let mut start = angle_constraint.start.to_degrees();
+                            let response =
+                                ui.add(egui::DragValue::new(&mut start).speed(1.).suffix("°"));
+                            if response.changed() {
+                                angle_constraint.start = start.to_radians();
+                            }
+                            if is_edit_finished(&response) {
+                                history.record_snapshot("Change angle constraint");
+                            }
+                            ui.label("to");
+                            let mut end = angle_constraint.end.to_degrees();
+                            let response =
+                                ui.add(egui::DragValue::new(&mut end).speed(1.).suffix("°"));
+                            if response.changed() {
+                                angle_constraint.end = end.to_radians();
+                            }
+                            if is_edit_finished(&response) {
+                                history.record_snapshot("Change angle constraint");
+                            }
+                            if ui.button("no constraint").clicked() {
+                                angle_constraint.start = 0.0;
+                                angle_constraint.end = 0.0;
+                                history.record_snapshot("Remove angle constraint");
+                            }
+                        });
+                        angle_constraint.normalize();
+                    }
+                }
+                None => {
+                    ui.label("no bone selected");
+                }
+            }
+            if let Some(bone_entity) = opt_selected_bone {
+                bone_transform_settings(ui, bone_entity, &mut q_transforms, &mut history);
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui.button("copy pose").clicked() {
+                    pose_state.pending_action = Some(pose::Action::Copy);
+                }
+                ui.label(format!("({} bones copied)", pose_state.clipboard_len()));
+            });
+            ui.horizontal(|ui| {
+                if ui.button("paste pose").clicked() {
+                    pose_state.pending_action = Some(pose::Action::Paste);
+                }
+                if ui.button("paste mirrored").clicked() {
+                    pose_state.pending_action = Some(pose::Action::PasteMirrored);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("mirror across ");
+                for axis in pose::MirrorAxis::all() {
+                    ui.radio_value(&mut pose_state.mirror_axis, axis, axis.to_string());
+                }
+            });
+
+            // Bind pose of all bones, that skins are bound to
+            ui.horizontal(|ui| {
+                if ui
+                    .button("set bind pose")
+                    .on_hover_text("bound skins are re-bound, so they keep their current shape")
+                    .clicked()
+                {
+                    state.set_bind_pose = true;
+                }
+                if ui.button("reset to bind pose").clicked() {
+                    for (entity, bone, _, _) in q_bones.iter() {
+                        if let (Some(bind_pose), Ok((mut transform, _))) =
+                            (bone.bind_pose, q_transforms.get_mut(entity))
+                        {
+                            *transform = bind_pose;
+                        }
+                    }
+                    history.record_snapshot("Reset to bind pose");
+                }
+            });
+
+            ui.separator();
+
+            // Path constraint of the selected bone
+            ui.label("PATH");
+            let bone_entity = match opt_selected_bone {
+                Some(entity) => entity,
+                None => return,
+            };
+            match q_bones.get_mut(bone_entity) {
+                Ok((_, _, Some(mut path), _)) => {
+                    ui.horizontal(|ui| {
+                        ui.label("spline: ");
+                        let mut spline_type = path.spline_type;
+                        egui::ComboBox::from_id_source("spline_type")
+                            .selected_text(spline_type.to_string())
+                            .show_ui(ui, |ui| {
+                                for spline in SplineType::all() {
+                                    ui.selectable_value(&mut spline_type, spline, spline.to_string());
+                                }
+                            });
+                        if spline_type != path.spline_type {
+                            path.spline_type = spline_type;
+                            history.record_snapshot("Change spline type");
+                        }
+                        ui.label("depth: ");
+                        let response = ui.add(
+                            egui::DragValue::new(&mut path.depth)
+                                .speed(1)
+                                .clamp_range(1..=30),
+                        );
+                        if is_edit_finished(&response) {
+                            history.record_snapshot("Change path depth");
+                        }
+                    });
+                    // Only chains starting at a root bone can be moved along the path
+                    let mut chain_root = bone_entity;
+                    for _ in 1..path.depth {
+                        match q_transforms.get(chain_root) {
+                            Ok((_, Some(parent))) => chain_root = parent.get(),
+                            _ => break,
+                        }
+                    }
+                    let is_root_chain = matches!(q_transforms.get(chain_root), Ok((_, None)));
+                    ui.horizontal(|ui| {
+                        // Can be keyed like the bone's transform
+                        ui.label("position: ");
+                        let response = ui
+                            .add_enabled(
+                                is_root_chain,
+                                egui::DragValue::new(&mut path.position)
+                                    .speed(0.01)
+                                    .clamp_range(0.0..=1.0),
+                            )
+                            .on_disabled_hover_text(
+                                "the chain starts at a child bone and follows the path from there",
+                            );
+                        if is_edit_finished(&response) {
+                            history.record_snapshot("Change path position");
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("add point").clicked() {
+                            path_state.pending_action = Some(path_constraint::Action::AddPoint(bone_entity));
+                        }
+                        if ui.button("remove path").clicked() {
+                            path_state.pending_action = Some(path_constraint::Action::Remove(bone_entity));
+                        }
+                    });
+                }
+                Ok((_, _, None, _)) => {
+                    ui.horizontal(|ui| {
+                        if ui.button("add path").clicked() {
+                            path_state.pending_action = Some(path_constraint::Action::Add(bone_entity));
+                        }
+                        // Uses the depth of new IK targets
+                        ui.label(format!("(for a chain of {} bones)", state.ik_depth));
+                    });
+                }
+                Err(_) => {}
+            }
+
+            ui.separator();
+
+            // Constraints of the selected bone, applied from top to bottom
+            ui.label("CONSTRAINTS");
+            let mut opt_added = None;
+            if let Ok((_, _, _, Some(mut stack))) = q_bones.get_mut(bone_entity) {
+                let mut opt_moved_up = None;
+                let mut opt_removed = None;
+                for (i, constraint) in stack.constraints.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        let name = constraint.constraint_type.to_string();
+                        if ui.checkbox(&mut constraint.enabled, name).changed() {
+                            history.record_snapshot("Toggle constraint");
+                        }
+                        let mut target = constraint.target;
+                        let target_name = constraint_targets
+                            .iter()
+                            .find(|(entity, _)| Some(*entity) == target)
+                            .map_or(String::from("no target"), |(_, name)| name.clone());
+                        egui::ComboBox::from_id_source(format!("constraint_target_{}", i))
+                            .selected_text(target_name)
+                            .show_ui(ui, |ui| {
+                                for (entity, name) in constraint_targets.iter() {
+                                    if *entity != bone_entity {
+                                        ui.selectable_value(&mut target, Some(*entity), name);
+                                    }
+                                }
+                            });
+                        if target != constraint.target {
+                            constraint.target = target;
+                            history.record_snapshot("Change constraint target");
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("influence: ");
+                        let response = ui.add(
+                            egui::DragValue::new(&mut constraint.influence)
+                                .speed(0.01)
+                                .clamp_range(0.0..=1.0),
+                        );
+                        if is_edit_finished(&response) {
+                            history.record_snapshot("Change constraint influence");
+                        }
+                        if constraint.constraint_type == ConstraintType::LimitDistance {
+                            ui.label("distance: ");
+                            let response = ui.add(
+                                egui::DragValue::new(&mut constraint.distance)
+                                    .speed(0.01)
+                                    .clamp_range(0.0..=f32::MAX),
+                            );
+                            if is_edit_finished(&response) {
+                                history.record_snapshot("Change constraint distance");
+                            }
+                        }
+                        if i > 0 && ui.button("up").clicked() {
+                            opt_moved_up = Some(i);
+                        }
+                        if ui.button("remove").clicked() {
+                            opt_removed = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = opt_moved_up {
+                    stack.constraints.swap(i - 1, i);
+                    history.record_snapshot("Reorder constraints");
+                }
+                if let Some(i) = opt_removed {
+                    stack.constraints.remove(i);
+                    history.record_snapshot("Remove constraint");
+                }
+            }
+            ui.menu_button("add constraint", |ui| {
+                for constraint_type in ConstraintType::all() {
+                    if ui.button(constraint_type.to_string()).clicked() {
+                        opt_added = Some(constraint_type);
+                        ui.close_menu();
+                    }
+                }
+            });
+            if let Some(constraint_type) = opt_added {
+                // Another selected bone or target becomes the target of the new constraint
+                let mut constraint = Constraint::new(constraint_type);
+                constraint.target = transform_state
+                    .selected_entities
+                    .iter()
+                    .find(|&&entity| {
+                        entity != bone_entity
+                            && constraint_targets.iter().any(|(target, _)| *target == entity)
+                    })
+                    .copied();
+                match q_bones.get_mut(bone_entity) {
+                    Ok((_, _, _, Some(mut stack))) => stack.constraints.push(constraint),
+                    _ => {
+                        commands
+                            .entity(bone_entity)
+                            .insert(ConstraintStack::new(vec![constraint]));
+                    }
+                }
+                history.record_snapshot("Add constraint");
+            }
+        });
+
+    if let Some(inner) = opt_response {
+        check_mouse_interaction(&mut egui_context, inner.response, &mut state, &mouse);
+    }
+}
+
+/// Bone hierarchy as a tree, in which bones can be selected, renamed, hidden, locked and reparented by
+/// drag and drop
+pub fn outliner_menu(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State>,
+    mut transform_state: ResMut<transform::State>,
+    mut history: ResMut<history::History>,
+    mouse: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut open_windows: ResMut<OpenWindows>,
+    mut q_bones: Query<(Entity, &mut bone::Bone, Option<&Parent>, &mut Transformable)>,
+    q_transforms: Query<(&Transform, Option<&Parent>), With<bone::Bone>>,
+    mut q_other_transformables: Query<(Entity, &mut Transformable), Without<bone::Bone>>,
+    mut collapsed: Local<HashSet<Entity>>,
+    mut renaming: Local<Option<Entity>>,
+    mut dragged: Local<Option<Entity>>,
+) {
+    // Hide window when transforming
+    if transform_state.action != transform::Action::None
+        && transform_state.action != transform::Action::Done
+    {
+        return;
+    }
+
+    // Children of each bone, roots are stored under None
+    let mut children: HashMap<Option<Entity>, Vec<Entity>> = HashMap::new();
+    let mut bones: Vec<(Entity, Option<Entity>)> = q_bones
+        .iter()
+        .map(|(entity, _, opt_parent, _)| {
+            let opt_parent = opt_parent
+                .map(|parent| parent.get())
+                .filter(|&parent| q_bones.contains(parent));
+            (entity, opt_parent)
+        })
+        .collect();
+    bones.sort_by_key(|&(entity, _)| entity.id());
+    for (entity, opt_parent) in bones {
+        children.entry(opt_parent).or_default().push(entity);
+    }
+
+    // Visible rows in depth first order
+    let mut rows: Vec<(Entity, usize)> = vec![];
+    let mut stack: Vec<(Entity, usize)> = children
+        .get(&None)
+        .map_or(vec![], |roots| roots.iter().rev().map(|&root| (root, 0)).collect());
+    while let Some((entity, depth)) = stack.pop() {
+        rows.push((entity, depth));
+        if let Some(bone_children) = children.get(&Some(entity)) {
+            if !collapsed.contains(&entity) {
+                stack.extend(bone_children.iter().rev().map(|&child| (child, depth + 1)));
+            }
+        }
+    }
+    if renaming.map_or(false, |entity| !q_bones.contains(entity)) {
+        *renaming = None;
+    }
+
+    let mut opt_clicked = None;
+    let mut opt_reparent = None;
+    let opt_response = egui::Window::new("Outliner")
+        .open(&mut open_windows.is_open_outliner)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            if rows.is_empty() {
+                ui.label("no bones");
+            }
+            // Bones, that can be dropped on, and where they are shown
+            let mut drop_rects: Vec<(Option<Entity>, egui::Rect)> = vec![];
+            egui::ScrollArea::vertical().max_height(400.).show(ui, |ui| {
+                for &(entity, depth) in rows.iter() {
+                    let (_, mut bone, _, mut transformable) = q_bones.get_mut(entity).unwrap();
+                    ui.horizontal(|ui| {
+                        ui.add_space(depth as f32 * 15.);
+                        if children.contains_key(&Some(entity)) {
+                            let is_collapsed = collapsed.contains(&entity);
+                            if ui.small_button(if is_collapsed { "+" } else { "-" }).clicked() {
+                                if is_collapsed {
+                                    collapsed.remove(&entity);
+                                } else {
+                                    collapsed.insert(entity);
+                                }
+                            }
+                        }
+                        if ui
+                            .selectable_label(!transformable.is_hidden, "👁")
+                            .on_hover_text("show/hide")
+                            .clicked()
+                        {
+                            transformable.is_hidden = !transformable.is_hidden;
+                            history.record_snapshot(if transformable.is_hidden {
+                                "Hide bone"
+                            } else {
+                                "Show bone"
+                            });
+                        }
+                        if ui
+                            .selectable_label(transformable.is_locked, "🔒")
+                            .on_hover_text("lock/unlock")
+                            .clicked()
+                        {
+                            transformable.is_locked = !transformable.is_locked;
+                            history.record_snapshot(if transformable.is_locked {
+                                "Lock bone"
+                            } else {
+                                "Unlock bone"
+                            });
+                        }
+                        // Hidden and locked bones can't stay selected
+                        if !transformable.is_selectable() {
+                            transformable.is_selected = false;
+                            transform_state.selected_entities.remove(&entity);
+                        }
+
+                        if *renaming == Some(entity) {
+                            let response = ui.text_edit_singleline(&mut bone.name);
+                            if response.lost_focus() {
+                                *renaming = None;
+                                history.record_snapshot("Rename bone");
+                            } else if !response.has_focus() {
+                                response.request_focus();
+                            }
+                            return;
+                        }
+                        let name = if bone.name.is_empty() {
+                            format!("bone {:?}", entity)
+                        } else {
+                            bone.name.clone()
+                        };
+                        let mut text = egui::RichText::new(name);
+                        if transformable.is_selected {
+                            text = text.strong().color(ui.visuals().selection.stroke.color);
+                        } else if transformable.is_hidden {
+                            text = text.color(Color32::GRAY);
+                        }
+                        let response = ui
+                            .add(egui::Label::new(text).sense(egui::Sense::click_and_drag()))
+                            .on_hover_text("double click to rename, drag onto a bone to reparent");
+                        if response.double_clicked() {
+                            *renaming = Some(entity);
+                        } else if response.clicked() {
+                            opt_clicked = Some(entity);
+                        }
+                        if response.drag_started() {
+                            *dragged = Some(entity);
+                        }
+                        drop_rects.push((Some(entity), response.rect));
+                    });
+                }
+            });
+            if dragged.is_some() {
+                let response = ui.label("(drop here to make it a root bone)");
+                drop_rects.push((None, response.rect));
+            }
+
+            // Drop the dragged bone on the bone below the pointer
+            if let Some(dragged_entity) = *dragged {
+                let opt_target = ui.input().pointer.interact_pos().and_then(|pos| {
+                    drop_rects
+                        .iter()
+                        .find(|(_, rect)| rect.contains(pos))
+                        .copied()
+                });
+                if let Some((_, rect)) = opt_target {
+                    ui.painter()
+                        .rect_stroke(rect.expand(2.), 2., (1., Color32::WHITE));
+                }
+                if ui.input().pointer.any_released() {
+                    *dragged = None;
+                    if let Some((opt_new_parent, _)) = opt_target {
+                        opt_reparent = Some((dragged_entity, opt_new_parent));
+                    }
+                }
+            }
+        });
+
+    if let Some((entity, opt_new_parent)) = opt_reparent {
+        let bind_poses: HashMap<Entity, Transform> = q_bones
+            .iter()
+            .filter_map(|(entity, bone, _, _)| bone.bind_pose.map(|bind_pose| (entity, bind_pose)))
+            .collect();
+        let (_, mut bone, _, _) = q_bones.get_mut(entity).unwrap();
+        if bone::reparent(
+            &mut commands,
+            entity,
+            &mut bone,
+            opt_new_parent,
+            &q_transforms,
+            &bind_poses,
+        ) {
+            history.record_snapshot("Reparent bone");
+        }
+    }
+
+    // Select the clicked bone, add it to the selection while LShift is pressed
+    if let Some(entity) = opt_clicked {
+        if !keys.pressed(KeyCode::LShift) {
+            for (other, _, _, mut transformable) in q_bones.iter_mut() {
+                transformable.is_selected = false;
+                transform_state.selected_entities.remove(&other);
+            }
+            for (other, mut transformable) in q_other_transformables.iter_mut() {
+                transformable.is_selected = false;
+                transform_state.selected_entities.remove(&other);
+            }
+        }
+        if let Ok((_, _, _, mut transformable)) = q_bones.get_mut(entity) {
+            if transformable.is_selectable() {
+                transformable.is_selected = !transformable.is_selected;
+                if transformable.is_selected {
+                    transform_state.selected_entities.insert(entity);
+                } else {
+                    transform_state.selected_entities.remove(&entity);
+                }
+            }
+        }
+    }
+
+    if let Some(inner) = opt_response {
+        check_mouse_interaction(&mut egui_context, inner.response, &mut state, &mouse);
+    }
+}
+
 pub fn skin_menu(
     mut egui_context: ResMut<EguiContext>,
     mut state: ResMut<State>,
@@ -879,6 +2054,13 @@ pub fn skin_menu(
     mut skin_state: ResMut<skin::State>,
     mouse: Res<Input<MouseButton>>,
     mut open_windows: ResMut<OpenWindows>,
+    mut gpu_state: ResMut<gpu_skinning::State>,
+    mut q_skins: Query<&mut skin::Skin>,
+    mut history: ResMut<history::History>,
+    mut skeleton: ResMut<skeleton::Skeleton>,
+    mut paint_state: ResMut<weight_paint::State>,
+    mut editor_state: ResMut<weight_editor::State>,
+    q_bones: Query<&bone::Bone>,
 ) {
     // Hide window when transforming
     if transform_state.action != transform::Action::None
@@ -892,7 +2074,48 @@ pub fn skin_menu(
         .open(&mut open_windows.is_open_skins)
         .resizable(false)
         .show(egui_context.ctx_mut(), |ui| {
-            skin_settings(ui, &mut state, &mut skin_state);
+            skin_settings(ui, &mut state, &mut skin_state, &mut gpu_state);
+
+            ui.separator();
+
+            ui.checkbox(&mut skin_state.compare_skinning_methods, "compare skinning methods")
+                .on_hover_text("draws bound skins with the other skinning method on top");
+            // Skinning method of the selected skins
+            let opt_selected_skin = transform_state
+                .selected_entities
+                .iter()
+                .find(|&&entity| q_skins.contains(entity))
+                .copied();
+            if let Some(selected_skin) = opt_selected_skin {
+                let mut skinning_method = q_skins.get(selected_skin).unwrap().skinning_method;
+                ui.horizontal(|ui| {
+                    ui.label("Selected skin: ");
+                    egui::ComboBox::from_id_source("skinning_method")
+                        .selected_text(skinning_method.to_string())
+                        .show_ui(ui, |ui| {
+                            for method in skeleton::SkinningMethod::all() {
+                                ui.selectable_value(&mut skinning_method, method, method.to_string());
+                            }
+                        });
+                });
+                if skinning_method != q_skins.get(selected_skin).unwrap().skinning_method {
+                    for &entity in transform_state.selected_entities.iter() {
+                        if let Ok(mut skin) = q_skins.get_mut(entity) {
+                            skin.skinning_method = skinning_method;
+                        }
+                    }
+                    history.record_snapshot("Change skinning method");
+                }
+            }
+
+            ui.separator();
+            weight_tools(ui, &mut state, &mut skeleton, &transform_state, &mut history);
+
+            ui.separator();
+            paint_settings(ui, &mut state, &mut paint_state);
+
+            ui.separator();
+            vertex_weight_editor(ui, &mut editor_state, &mut skeleton, &q_bones, &mut history);
         });
 
     if let Some(inner) = opt_response {
@@ -900,6 +2123,70 @@ pub fn skin_menu(
     }
 }
 
+pub fn recovery_prompt(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State>,
+    mut autosave_state: ResMut<autosave::State>,
+    mut load_evw: EventWriter<save_load::LoadEvent>,
+    mouse: Res<Input<MouseButton>>,
+) {
+    if autosave_state.recovery.is_none() {
+        return;
+    }
+
+    let mut is_restore = false;
+    let mut is_discard = false;
+    let opt_response = egui::Window::new("Recovery")
+        .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label("The editor wasn't closed properly the last time.");
+            ui.label("Do you want to restore the newest autosave?");
+            ui.horizontal(|ui| {
+                is_restore = ui.button("restore").clicked();
+                is_discard = ui.button("discard").clicked();
+            });
+        });
+
+    if is_restore {
+        if let Some(data) = autosave_state.recovery.take() {
+            load_evw.send(save_load::LoadEvent {
+                data,
+                from_history: false,
+            });
+        }
+    } else if is_discard {
+        autosave_state.recovery = None;
+    }
+
+    if let Some(inner) = opt_response {
+        check_mouse_interaction(&mut egui_context, inner.response, &mut state, &mouse);
+    }
+}
+
+/// True once per edit of a [`egui::DragValue`], i.e. when dragging ends or a typed in value is confirmed
+fn is_edit_finished(response: &egui::Response) -> bool {
+    if response.drag_released() {
+        return true;
+    }
+
+    // Typed in values are applied on every keystroke, so remember the change until the field loses focus
+    if response.has_focus() {
+        if response.changed() {
+            response.ctx.memory().data.insert_temp(response.id, true);
+        }
+        false
+    } else if response.lost_focus() {
+        let mut memory = response.ctx.memory();
+        let is_changed = memory.data.get_temp::<bool>(response.id).unwrap_or(false);
+        memory.data.remove::<bool>(response.id);
+        is_changed || response.changed()
+    } else {
+        response.changed() && !response.dragged()
+    }
+}
+
 fn check_mouse_interaction(
     egui_context: &mut EguiContext,
     response: egui::Response,
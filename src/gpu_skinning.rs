@@ -0,0 +1,345 @@
+use crate::{bone::Bone, cloth::Cloth, skeleton::SkinMapping, skin::Skin, *};
+use bevy::{
+    reflect::TypeUuid,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+        },
+    },
+    sprite::{Material2d, Material2dKey},
+    utils::HashMap,
+};
+
+#[cfg(test)]
+#[path = "tests/gpu_skinning_tests.rs"]
+mod gpu_skinning_tests;
+
+/// Size of the bone matrix uniform, skins with more bones are deformed on the CPU
+pub const MAX_BONES: usize = 64;
+/// Number of bones, that can influence a vertex in the vertex shader
+pub const MAX_BONES_PER_VERTEX: usize = 4;
+
+pub struct State {
+    pub enabled: bool,
+    /// Skins, that are currently deformed in the vertex shader, and their materials
+    pub gpu_skins: HashMap<Entity, Handle<SkinningMaterial>>,
+}
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            gpu_skins: HashMap::new(),
+        }
+    }
+}
+
+/// Textured material, whose vertex shader deforms the mesh with up to four bones per vertex.
+///
+/// Vertex positions are bind positions, bone indices and weights are stored in
+/// `Mesh::ATTRIBUTE_JOINT_INDEX` and `Mesh::ATTRIBUTE_JOINT_WEIGHT`.
+#[derive(AsBindGroup, TypeUuid, Clone)]
+#[uuid = "3c6a1f0e-8d2b-4b6e-9a51-7f3e2d1c0b94"]
+pub struct SkinningMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub texture: Option<Handle<Image>>,
+    #[uniform(2)]
+    pub bone_matrices: [Mat4; MAX_BONES],
+}
+impl Material2d for SkinningMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/skinning.wgsl".into()
+    }
+    fn fragment_shader() -> ShaderRef {
+        "shaders/skinning.wgsl".into()
+    }
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(1),
+            Mesh::ATTRIBUTE_JOINT_INDEX.at_shader_location(2),
+            Mesh::ATTRIBUTE_JOINT_WEIGHT.at_shader_location(3),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+/// Material of a mesh before it was deformed on the GPU, restored when falling back to the CPU
+#[derive(Component)]
+pub struct CpuMaterial(Handle<ColorMaterial>);
+
+/// Bone indices, bone weights and bind positions of all vertices of a skin
+pub struct VertexAttributes {
+    pub bones: Vec<Entity>,
+    pub bone_indices: Vec<[u16; 4]>,
+    pub bone_weights: Vec<[f32; 4]>,
+    pub positions: Vec<[f32; 3]>,
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new().with_system(update_gpu_skins)
+}
+
+/// Returns the vertex attributes of a bound skin, or None, if it can't be deformed in the vertex shader,
+/// e.g. because a vertex is influenced by more than four bones (see
+/// [`skeleton::SkinMapping::limit_influences`]). Weights are normalized, like on the CPU.
+pub fn get_vertex_attributes(skin_mapping: &SkinMapping) -> Option<VertexAttributes> {
+    let mut attributes = VertexAttributes {
+        bones: vec![],
+        bone_indices: vec![],
+        bone_weights: vec![],
+        positions: vec![],
+    };
+    let mut bone_to_index: HashMap<Entity, u16> = HashMap::new();
+    for (mapping, bind_vertex) in skin_mapping
+        .vertex_mappings
+        .iter()
+        .zip(skin_mapping.bind_vertices.iter())
+    {
+        if mapping.is_free || mapping.influence_count() > MAX_BONES_PER_VERTEX {
+            return None;
+        }
+        let total_weight: f32 = mapping.weights.iter().sum();
+        // Vertices without weight stay in the bind pose
+        if total_weight == 0. {
+            return None;
+        }
+        let mut bone_indices = [0; 4];
+        let mut bone_weights = [0.; 4];
+        // Bones without weight don't need a slot
+        let influences = mapping
+            .bones
+            .iter()
+            .zip(mapping.weights.iter())
+            .filter(|(_, &weight)| weight > 0.);
+        for (i, (&bone, &weight)) in influences.enumerate() {
+            bone_indices[i] = match bone_to_index.get(&bone) {
+                Some(&index) => index,
+                None => {
+                    if attributes.bones.len() == MAX_BONES {
+                        return None;
+                    }
+                    attributes.bones.push(bone);
+                    bone_to_index.insert(bone, attributes.bones.len() as u16 - 1);
+                    attributes.bones.len() as u16 - 1
+                }
+            };
+            bone_weights[i] = weight / total_weight;
+        }
+        attributes.bone_indices.push(bone_indices);
+        attributes.bone_weights.push(bone_weights);
+        attributes
+            .positions
+            .push([bind_vertex.x, bind_vertex.y, 0.]);
+    }
+    if attributes.positions.len() != skin_mapping.vertex_mappings.len() {
+        return None;
+    }
+    Some(attributes)
+}
+
+/// Vertex attributes of a skin, that isn't bound, but follows its own transform as its only "bone"
+pub fn get_loose_vertex_attributes(skin: &Skin) -> VertexAttributes {
+    VertexAttributes {
+        bones: vec![],
+        bone_indices: vec![[0; 4]; skin.vertices.len()],
+        bone_weights: vec![[1., 0., 0., 0.]; skin.vertices.len()],
+        positions: skin.vertices.clone(),
+    }
+}
+
+/// Does the same computation as the vertex shader, used as CPU reference
+pub fn skin_vertex_like_shader(
+    position: [f32; 3],
+    bone_indices: [u16; 4],
+    bone_weights: [f32; 4],
+    bone_matrices: &[Mat4; MAX_BONES],
+) -> Vec3 {
+    let mut matrix = Mat4::ZERO;
+    for i in 0..4 {
+        matrix = matrix + bone_matrices[bone_indices[i] as usize] * bone_weights[i];
+    }
+    (matrix * Vec3::from_slice(&position).extend(1.)).truncate()
+}
+
+/// Positions of the mesh's vertices after deformation. For skins deformed on the GPU, the mesh only
+/// holds bind positions, so the vertex shader is emulated.
+pub fn get_vertices(
+    mesh: &Mesh,
+    skin_entity: Entity,
+    state: &State,
+    materials: &Assets<SkinningMaterial>,
+) -> Vec<Vec3> {
+    let material = match state
+        .gpu_skins
+        .get(&skin_entity)
+        .and_then(|handle| materials.get(handle))
+    {
+        Some(material) => material,
+        None => return mesh::get_vertices(mesh),
+    };
+    match (
+        mesh.attribute(Mesh::ATTRIBUTE_JOINT_INDEX),
+        mesh.attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT),
+    ) {
+        (
+            Some(VertexAttributeValues::Uint16x4(bone_indices)),
+            Some(VertexAttributeValues::Float32x4(bone_weights)),
+        ) => mesh::get_vertices(mesh)
+            .iter()
+            .zip(bone_indices.iter().zip(bone_weights.iter()))
+            .map(|(position, (&indices, &weights))| {
+                skin_vertex_like_shader(
+                    position.to_array(),
+                    indices,
+                    weights,
+                    &material.bone_matrices,
+                )
+            })
+            .collect(),
+        _ => mesh::get_vertices(mesh),
+    }
+}
+
+/// Deforms bound and loose skins in the vertex shader, falls back to the CPU for cloths, skins with too
+/// many bones per vertex or dual quaternion skinning and while vertex weights are adjusted, because the
+/// weight tools read the deformed positions from the mesh.
+pub fn update_gpu_skins(
+    mut commands: Commands,
+    mut state: ResMut<State>,
+    skeleton: Res<skeleton::Skeleton>,
+    egui_state: Res<egui::State>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    color_materials: Res<Assets<ColorMaterial>>,
+    mut skinning_materials: ResMut<Assets<SkinningMaterial>>,
+    q_bones: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    q_bone_entities: Query<(Entity, &Bone)>,
+    q_skins: Query<(Entity, &Skin, &GlobalTransform, Option<&Cloth>)>,
+    q_meshes: Query<(
+        Entity,
+        &Mesh2dHandle,
+        Option<&Handle<ColorMaterial>>,
+        Option<&Handle<SkinningMaterial>>,
+        Option<&CpuMaterial>,
+    )>,
+) {
+    let mut bone_gl_transforms: HashMap<Entity, Transform> = HashMap::new();
+    for (entity, _) in q_bone_entities.iter() {
+        if let Some(gl_transform) = bone::get_bone_gl_transform(entity, &q_bones) {
+            bone_gl_transforms.insert(entity, gl_transform);
+        }
+    }
+    let bind_gl_transforms = skeleton::get_bind_gl_transforms(&q_bones, &q_bone_entities);
+    let skinning_matrices =
+        skeleton::get_skinning_matrices(&bone_gl_transforms, &bind_gl_transforms);
+
+    let mesh_entities: HashMap<Handle<Mesh>, Entity> = q_meshes
+        .iter()
+        .map(|(entity, handle, ..)| (handle.0.clone_weak(), entity))
+        .collect();
+
+    let mut gpu_skins: HashMap<Entity, Handle<SkinningMaterial>> = HashMap::new();
+    for (skin_entity, skin, gl_transform, opt_cloth) in q_skins.iter() {
+        let mesh_handle = match &skin.mesh_handle {
+            Some(mesh_handle) => mesh_handle,
+            None => continue,
+        };
+        let (mesh_entity, _, opt_color_material, opt_skinning_material, opt_cpu_material) =
+            match mesh_entities
+                .get(&mesh_handle.0)
+                .and_then(|entity| q_meshes.get(*entity).ok())
+            {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+
+        let opt_skin_mapping = skeleton.skin_mappings.iter().find(|mapping| {
+            mapping.skin == Some(skin_entity) && !mapping.vertex_mappings.is_empty()
+        });
+        let is_gpu_possible = state.enabled
+            && !egui_state.adjust_vertex_weights_mode
+            && opt_cloth.is_none()
+            && skin.skinning_method == skeleton::SkinningMethod::Linear;
+        let opt_attributes = match opt_skin_mapping {
+            _ if !is_gpu_possible => None,
+            Some(skin_mapping) => get_vertex_attributes(skin_mapping),
+            None => Some(get_loose_vertex_attributes(skin)),
+        };
+
+        let attributes = match opt_attributes {
+            Some(attributes) => attributes,
+            None => {
+                // Let the CPU deform the mesh again
+                if let Some(cpu_material) = opt_cpu_material {
+                    commands
+                        .entity(mesh_entity)
+                        .remove::<Handle<SkinningMaterial>>()
+                        .remove::<CpuMaterial>()
+                        .insert(cpu_material.0.clone());
+                }
+                continue;
+            }
+        };
+
+        let mut bone_matrices = [Mat4::ZERO; MAX_BONES];
+        if opt_skin_mapping.is_some() {
+            for (i, bone) in attributes.bones.iter().enumerate() {
+                if let Some(&skinning_matrix) = skinning_matrices.get(bone) {
+                    bone_matrices[i] = skinning_matrix;
+                }
+            }
+        } else {
+            let (scale, rotation, translation) = gl_transform.to_scale_rotation_translation();
+            bone_matrices[0] = Transform {
+                translation: translation.truncate().extend(0.),
+                rotation,
+                scale,
+            }
+            .compute_matrix();
+        }
+
+        match opt_skinning_material {
+            Some(handle) => {
+                if let Some(material) = skinning_materials.get_mut(handle) {
+                    material.bone_matrices = bone_matrices;
+                }
+                gpu_skins.insert(skin_entity, handle.clone());
+                // Vertex attributes only change, if skins are bound or weights are adjusted
+                if !skeleton.is_changed() {
+                    continue;
+                }
+            }
+            None => {
+                let color_material = match opt_color_material {
+                    Some(color_material) => color_material,
+                    None => continue,
+                };
+                let texture = color_materials
+                    .get(color_material)
+                    .and_then(|material| material.texture.clone());
+                let handle = skinning_materials.add(SkinningMaterial {
+                    texture,
+                    bone_matrices,
+                });
+                gpu_skins.insert(skin_entity, handle.clone());
+                commands
+                    .entity(mesh_entity)
+                    .remove::<Handle<ColorMaterial>>()
+                    .insert(CpuMaterial(color_material.clone()))
+                    .insert(handle);
+            }
+        }
+        if let Some(mesh) = meshes.get_mut(&mesh_handle.0) {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, attributes.positions);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_INDEX, attributes.bone_indices);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, attributes.bone_weights);
+        }
+    }
+    state.gpu_skins = gpu_skins;
+}
@@ -0,0 +1,291 @@
+use crate::*;
+use bevy::utils::HashMap;
+
+#[cfg(test)]
+#[path = "tests/heat_weights_tests.rs"]
+mod heat_weights_tests;
+
+/// Heat a vertex receives from its nearest bone, relative to the heat flowing along the mesh
+const HEAT_CONSTANT: f64 = 1.;
+/// Vertices lying on a bone would receive infinite heat
+const MIN_BONE_DISTANCE: f64 = 0.01;
+/// Bones within this factor of the nearest distance count as nearest as well
+const NEAREST_BONE_TOLERANCE: f64 = 1.0001;
+/// Smaller weights are set to zero, so that vertices aren't influenced by every bone
+const MIN_WEIGHT: f32 = 0.01;
+const SOLVER_MAX_ITERATIONS: usize = 2000;
+const SOLVER_TOLERANCE: f64 = 1e-8;
+
+/// Sparse symmetric matrix of the form diagonal - off_diagonal, with positive off diagonal entries
+struct SparseMatrix {
+    diagonal: Vec<f64>,
+    off_diagonal: Vec<Vec<(usize, f64)>>,
+}
+impl SparseMatrix {
+    fn mul(&self, x: &[f64]) -> Vec<f64> {
+        (0..x.len())
+            .map(|i| {
+                self.diagonal[i] * x[i]
+                    - self.off_diagonal[i]
+                        .iter()
+                        .map(|&(j, weight)| weight * x[j])
+                        .sum::<f64>()
+            })
+            .collect()
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+/// Computes automatic weights like "bone heat" (Baran and Popović, 2007): each bone heats the vertices, to
+/// which it is nearest, and the heat diffuses along the triangle mesh. Unlike distance based weights, heat
+/// doesn't flow across gaps in the mesh, e.g. between legs.
+///
+/// `bones` are the start and end points of the bones' segments, in the same space as `vertices`. Returns
+/// one weight per bone for each vertex, the weights of a vertex sum up to 1. Returns None, if there are no
+/// bones or vertices.
+pub fn compute_heat_weights(
+    vertices: &Vec<Vec2>,
+    indices: &Vec<u16>,
+    bones: &Vec<(Vec2, Vec2)>,
+) -> Option<Vec<Vec<f32>>> {
+    if vertices.is_empty() || bones.is_empty() {
+        return None;
+    }
+    let (laplacian, masses) = get_cotangent_laplacian(vertices, indices);
+    let boundary_edges = get_boundary_edges(indices);
+
+    // Heat each vertex receives and which bones emit it
+    let mut heats: Vec<f64> = vec![];
+    let mut nearest_bones: Vec<Vec<usize>> = vec![];
+    for (v_i, &vertex) in vertices.iter().enumerate() {
+        let distances: Vec<f64> = bones
+            .iter()
+            .map(|&(start, end)| transform::distance_segment_point(start, end, vertex) as f64)
+            .collect();
+        let visible: Vec<usize> = (0..bones.len())
+            .filter(|&b_i| {
+                let closest_point = get_closest_point(bones[b_i], vertex);
+                is_visible(v_i, closest_point, vertices, &boundary_edges)
+            })
+            .collect();
+        // Bones outside the mesh aren't visible from any vertex, use the nearest one anyway
+        let candidates: Vec<usize> = if visible.is_empty() {
+            (0..bones.len()).collect()
+        } else {
+            visible
+        };
+        let min_distance = candidates
+            .iter()
+            .map(|&b_i| distances[b_i])
+            .fold(f64::MAX, f64::min);
+        nearest_bones.push(
+            candidates
+                .into_iter()
+                .filter(|&b_i| distances[b_i] <= min_distance * NEAREST_BONE_TOLERANCE)
+                .collect(),
+        );
+        heats.push(HEAT_CONSTANT / f64::max(min_distance, MIN_BONE_DISTANCE).powi(2));
+    }
+
+    // Solve (L + M * H) * w = M * H * p for each bone, with the cotangent laplacian L, vertex areas M,
+    // heats H and p being 1 for vertices nearest to the bone
+    let mut matrix = laplacian;
+    for v_i in 0..vertices.len() {
+        matrix.diagonal[v_i] += masses[v_i] * heats[v_i];
+    }
+    let mut weights: Vec<Vec<f32>> = vec![vec![0.; bones.len()]; vertices.len()];
+    for b_i in 0..bones.len() {
+        let nearest: Vec<f64> = nearest_bones
+            .iter()
+            .map(|nearest| {
+                if nearest.contains(&b_i) {
+                    1. / nearest.len() as f64
+                } else {
+                    0.
+                }
+            })
+            .collect();
+        let rhs: Vec<f64> = (0..vertices.len())
+            .map(|v_i| masses[v_i] * heats[v_i] * nearest[v_i])
+            .collect();
+        let solution = solve_conjugate_gradient(&matrix, &rhs, nearest);
+        for v_i in 0..vertices.len() {
+            weights[v_i][b_i] = (solution[v_i] as f32).clamp(0., 1.);
+        }
+    }
+
+    for (v_i, vertex_weights) in weights.iter_mut().enumerate() {
+        for weight in vertex_weights.iter_mut() {
+            if *weight < MIN_WEIGHT {
+                *weight = 0.;
+            }
+        }
+        let total: f32 = vertex_weights.iter().sum();
+        if total == 0. {
+            // Can only happen if the solver failed, fall back to the nearest bones
+            for &b_i in nearest_bones[v_i].iter() {
+                vertex_weights[b_i] = 1. / nearest_bones[v_i].len() as f32;
+            }
+            continue;
+        }
+        for weight in vertex_weights.iter_mut() {
+            *weight /= total;
+        }
+    }
+    Some(weights)
+}
+
+/// Returns the cotangent laplacian of the mesh and the area belonging to each vertex. Negative cotangent
+/// weights of obtuse triangles are clamped to zero, so that weights stay between 0 and 1.
+fn get_cotangent_laplacian(vertices: &Vec<Vec2>, indices: &Vec<u16>) -> (SparseMatrix, Vec<f64>) {
+    let mut edge_weights: Vec<HashMap<usize, f64>> = vec![HashMap::new(); vertices.len()];
+    let mut masses: Vec<f64> = vec![0.; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let corners = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        let area = 0.5
+            * (vertices[corners[1]] - vertices[corners[0]])
+                .perp_dot(vertices[corners[2]] - vertices[corners[0]])
+                .abs() as f64;
+        if area == 0. {
+            continue;
+        }
+        for k in 0..3 {
+            // Cotangent of the angle at corner k, opposite of edge (i, j)
+            let (i, j) = (corners[(k + 1) % 3], corners[(k + 2) % 3]);
+            let u = (vertices[i] - vertices[corners[k]]).as_dvec2();
+            let v = (vertices[j] - vertices[corners[k]]).as_dvec2();
+            let cotangent = u.dot(v) / u.perp_dot(v).abs();
+            *edge_weights[i].entry(j).or_insert(0.) += 0.5 * cotangent;
+            *edge_weights[j].entry(i).or_insert(0.) += 0.5 * cotangent;
+            masses[corners[k]] += area / 3.;
+        }
+    }
+
+    let mut matrix = SparseMatrix {
+        diagonal: vec![0.; vertices.len()],
+        off_diagonal: vec![vec![]; vertices.len()],
+    };
+    for (i, weights) in edge_weights.iter().enumerate() {
+        for (&j, &weight) in weights.iter() {
+            let weight = f64::max(weight, 0.);
+            matrix.diagonal[i] += weight;
+            matrix.off_diagonal[i].push((j, weight));
+        }
+        // Vertices, that aren't part of a triangle, only depend on their own heat
+        if masses[i] == 0. {
+            masses[i] = 1.;
+        }
+    }
+    (matrix, masses)
+}
+
+/// Edges, that belong to only one triangle
+fn get_boundary_edges(indices: &Vec<u16>) -> Vec<[usize; 2]> {
+    let mut edge_counts: HashMap<[usize; 2], u32> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        for k in 0..3 {
+            let mut edge = [triangle[k] as usize, triangle[(k + 1) % 3] as usize];
+            edge.sort_unstable();
+            *edge_counts.entry(edge).or_insert(0) += 1;
+        }
+    }
+    edge_counts
+        .into_iter()
+        .filter(|&(_, count)| count == 1)
+        .map(|(edge, _)| edge)
+        .collect()
+}
+
+fn get_closest_point((start, end): (Vec2, Vec2), point: Vec2) -> Vec2 {
+    let segment = end - start;
+    if segment.length_squared() == 0. {
+        return start;
+    }
+    let t = ((point - start).dot(segment) / segment.length_squared()).clamp(0., 1.);
+    start + t * segment
+}
+
+/// Whether the line from vertex `v_i` to `point` stays inside the mesh, i.e. doesn't cross its boundary
+fn is_visible(
+    v_i: usize,
+    point: Vec2,
+    vertices: &Vec<Vec2>,
+    boundary_edges: &Vec<[usize; 2]>,
+) -> bool {
+    let start = vertices[v_i];
+    let direction = point - start;
+    for &[a, b] in boundary_edges.iter() {
+        if a == v_i || b == v_i {
+            continue;
+        }
+        let edge = vertices[b] - vertices[a];
+        let denominator = direction.perp_dot(edge);
+        // Parallel edges run along the line, but don't block it
+        if denominator == 0. {
+            continue;
+        }
+        let to_edge = vertices[a] - start;
+        let s = to_edge.perp_dot(edge) / denominator;
+        let t = to_edge.perp_dot(direction) / denominator;
+        // Touching the boundary at the point itself is fine, e.g. for bones starting at the mesh's border
+        if s > 0.0001 && s < 0.9999 && (0. ..=1.).contains(&t) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Solves `matrix * x = rhs` for a symmetric positive definite `matrix`, starting at `x`
+fn solve_conjugate_gradient(matrix: &SparseMatrix, rhs: &Vec<f64>, mut x: Vec<f64>) -> Vec<f64> {
+    // Jacobi preconditioner
+    let inverse_diagonal: Vec<f64> = matrix
+        .diagonal
+        .iter()
+        .map(|&d| if d != 0. { 1. / d } else { 1. })
+        .collect();
+    let rhs_norm = dot(rhs, rhs).sqrt();
+    if rhs_norm == 0. {
+        return vec![0.; rhs.len()];
+    }
+
+    let mut residual: Vec<f64> = matrix
+        .mul(&x)
+        .iter()
+        .zip(rhs.iter())
+        .map(|(ax, b)| b - ax)
+        .collect();
+    let mut z: Vec<f64> = residual
+        .iter()
+        .zip(inverse_diagonal.iter())
+        .map(|(r, d)| r * d)
+        .collect();
+    let mut direction = z.clone();
+    let mut rz = dot(&residual, &z);
+    for _ in 0..SOLVER_MAX_ITERATIONS {
+        if dot(&residual, &residual).sqrt() <= SOLVER_TOLERANCE * rhs_norm {
+            return x;
+        }
+        let a_direction = matrix.mul(&direction);
+        let alpha = rz / dot(&direction, &a_direction);
+        for i in 0..x.len() {
+            x[i] += alpha * direction[i];
+            residual[i] -= alpha * a_direction[i];
+            z[i] = residual[i] * inverse_diagonal[i];
+        }
+        let rz_new = dot(&residual, &z);
+        let beta = rz_new / rz;
+        rz = rz_new;
+        for i in 0..x.len() {
+            direction[i] = z[i] + beta * direction[i];
+        }
+    }
+    println!("solve_conjugate_gradient: Failed to converge, weights might be inaccurate");
+    x
+}
@@ -0,0 +1,415 @@
+use crate::{
+    animation::{Animation, Animations, BlendingStyle},
+    bone::Bone,
+    cloth::Cloth,
+    constraints::ConstraintStack,
+    inverse_kinematics::{Pole, Target},
+    path_constraint::{PathConstraint, PathPoint},
+    save_load::{CompleteJson, LoadEvent},
+    skeleton::{Skeleton, SkinMapping},
+    skin::Skin,
+    *,
+};
+use bevy::utils::HashMap;
+use std::mem::size_of;
+
+#[cfg(test)]
+#[path = "tests/history_tests.rs"]
+mod history_tests;
+
+const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+// Changes of the same kind within this interval (in seconds) are merged into one entry
+const MERGE_INTERVAL: f64 = 0.5;
+
+/// A change of the editor state, that can be undone and redone
+pub enum Command {
+    /// Entity, transform before and transform after the change
+    Transforms(Vec<(Entity, Transform, Transform)>),
+    Weights {
+        before: Vec<SkinMapping>,
+        after: Vec<SkinMapping>,
+    },
+    Animations {
+        before: AnimationsSnapshot,
+        after: AnimationsSnapshot,
+    },
+    /// Complete state of the editor, used for changes that spawn or despawn entities
+    Snapshot {
+        before: CompleteJson,
+        after: CompleteJson,
+    },
+}
+impl Command {
+    /// Approximate memory usage in bytes
+    fn size(&self) -> usize {
+        match self {
+            Command::Transforms(transforms) => {
+                transforms.len() * size_of::<(Entity, Transform, Transform)>()
+            }
+            Command::Weights { before, after } => before
+                .iter()
+                .chain(after.iter())
+                .map(|skin_mapping| {
+                    skin_mapping
+                        .vertex_mappings
+                        .iter()
+                        .map(|mapping| {
+                            size_of::<skeleton::VertexMapping>()
+                                + mapping.bones.len()
+                                    * (size_of::<Entity>() + size_of::<f32>() + size_of::<Vec2>())
+                        })
+                        .sum::<usize>()
+                })
+                .sum(),
+            Command::Animations { before, after } => before.size() + after.size(),
+            Command::Snapshot { before, after } => {
+                serde_json::to_vec(before).map_or(0, |v| v.len())
+                    + serde_json::to_vec(after).map_or(0, |v| v.len())
+            }
+        }
+    }
+    fn remap(&mut self, entity_map: &HashMap<Entity, Entity>) {
+        match self {
+            Command::Transforms(transforms) => {
+                for (entity, _, _) in transforms.iter_mut() {
+                    *entity = *entity_map.get(entity).unwrap_or(entity);
+                }
+            }
+            Command::Weights { before, after } => {
+                for skin_mapping in before.iter_mut().chain(after.iter_mut()) {
+                    skin_mapping.remap(entity_map);
+                }
+            }
+            Command::Animations { before, after } => {
+                for anim in before.map.values_mut().chain(after.map.values_mut()) {
+                    anim.remap(entity_map);
+                }
+            }
+            Command::Snapshot { before, after } => {
+                before.remap(entity_map);
+                after.remap(entity_map);
+            }
+        }
+    }
+    /// Two commands can be merged, if they change the same kind of data. Weights are only pushed once per
+    /// brush stroke or action, so quick strokes stay separate entries.
+    fn can_merge(&self, other: &Command) -> bool {
+        matches!(
+            (self, other),
+            (Command::Animations { .. }, Command::Animations { .. })
+        )
+    }
+    /// Keep the state before `self` and the state after `other`
+    fn merge(&mut self, other: Command) {
+        if let (
+            Command::Animations { after, .. },
+            Command::Animations {
+                after: new_after, ..
+            },
+        ) = (self, other)
+        {
+            *after = new_after;
+        }
+    }
+}
+
+/// All animations, layers and the blending style
+#[derive(Clone, PartialEq)]
+pub struct AnimationsSnapshot {
+    map: HashMap<String, Animation>,
+    layers: Vec<String>,
+    blending_style: BlendingStyle,
+}
+impl AnimationsSnapshot {
+    pub fn new(animations: &Animations, anim_state: &animation::State) -> Self {
+        Self {
+            map: animations.map.clone(),
+            layers: anim_state.layers.clone(),
+            blending_style: anim_state.blending_style,
+        }
+    }
+    fn apply(&self, animations: &mut Animations, anim_state: &mut animation::State) {
+        animations.map = self.map.clone();
+        anim_state.layers = self.layers.clone();
+        anim_state.blending_style = self.blending_style;
+    }
+    fn size(&self) -> usize {
+        self.map
+            .values()
+            .map(|anim| {
+                anim.keyframes.len() * size_of::<f64>()
+                    + anim
+                        .comp_animations
+                        .values()
+                        .map(|comp_animation| {
+                            comp_animation.transforms.len() * size_of::<Transform>()
+                                + comp_animation.interpolation_functions.len()
+                                    * size_of::<interpolate::Function>()
+                                + comp_animation.ik_weights.len() * size_of::<f32>()
+                                + comp_animation.path_positions.len() * size_of::<f32>()
+                        })
+                        .sum::<usize>()
+            })
+            .sum()
+    }
+}
+
+pub struct Entry {
+    pub name: String,
+    command: Command,
+    size: usize,
+    time: f64,
+}
+
+pub struct History {
+    undo_stack: Vec<Entry>,
+    redo_stack: Vec<Entry>,
+    /// Oldest entries are dropped, when all entries together use more memory (in bytes)
+    pub memory_budget: usize,
+    /// Editor state after the last change, needed as starting point of snapshots
+    baseline: Option<CompleteJson>,
+    baseline_is_outdated: bool,
+    /// Set when a change was merged into the last entry in this frame, e.g. while dragging something
+    is_merging: bool,
+    pending_snapshot: Option<String>,
+    /// Number of steps still to be done, negative for undo, positive for redo
+    pending_steps: i32,
+    waiting_for_load: bool,
+    time: f64,
+}
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo_stack: vec![],
+            redo_stack: vec![],
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            baseline: None,
+            baseline_is_outdated: true,
+            is_merging: false,
+            pending_snapshot: None,
+            pending_steps: 0,
+            waiting_for_load: false,
+            time: 0.,
+        }
+    }
+}
+impl History {
+    /// Add a change to the history.
+    ///
+    /// Changes of the same name in quick succession (e.g. while dragging something) are merged into one entry.
+    pub fn push(&mut self, name: &str, command: Command) {
+        self.redo_stack.clear();
+        self.baseline_is_outdated = true;
+
+        if let Some(last) = self.undo_stack.last_mut() {
+            if last.name == name
+                && self.time - last.time < MERGE_INTERVAL
+                && last.command.can_merge(&command)
+            {
+                last.command.merge(command);
+                last.size = last.command.size();
+                last.time = self.time;
+                self.is_merging = true;
+                self.enforce_memory_budget();
+                return;
+            }
+        }
+
+        let size = command.size();
+        self.undo_stack.push(Entry {
+            name: String::from(name),
+            command,
+            size,
+            time: self.time,
+        });
+        self.enforce_memory_budget();
+    }
+    /// Record a change that spawns or despawns entities.
+    ///
+    /// The state after the change is only available after commands have been applied,
+    /// so the entry is created at the end of the frame.
+    pub fn record_snapshot(&mut self, name: &str) {
+        self.pending_snapshot = Some(String::from(name));
+    }
+    pub fn undo(&mut self) {
+        self.pending_steps -= 1;
+    }
+    pub fn redo(&mut self) {
+        self.pending_steps += 1;
+    }
+    /// Undo or redo until `count` entries are on the undo stack
+    pub fn go_to(&mut self, count: usize) {
+        self.pending_steps = count as i32 - self.undo_stack.len() as i32;
+    }
+    pub fn undo_entries(&self) -> &Vec<Entry> {
+        &self.undo_stack
+    }
+    /// Entries that can be redone, the next one is the last
+    pub fn redo_entries(&self) -> &Vec<Entry> {
+        &self.redo_stack
+    }
+    pub fn memory_usage(&self) -> usize {
+        self.undo_stack
+            .iter()
+            .chain(self.redo_stack.iter())
+            .map(|entry| entry.size)
+            .sum()
+    }
+    /// Replace all entities stored in the history, e.g. after entities have been respawned by loading
+    pub fn remap(&mut self, entity_map: &HashMap<Entity, Entity>) {
+        for entry in self.undo_stack.iter_mut().chain(self.redo_stack.iter_mut()) {
+            entry.command.remap(entity_map);
+        }
+        if let Some(baseline) = &mut self.baseline {
+            baseline.remap(entity_map);
+        }
+        self.baseline_is_outdated = true;
+        self.waiting_for_load = false;
+    }
+    fn enforce_memory_budget(&mut self) {
+        let mut usage = self.memory_usage();
+        // Always keep the newest entry
+        while usage > self.memory_budget && self.undo_stack.len() > 1 {
+            usage -= self.undo_stack.remove(0).size;
+        }
+        while usage > self.memory_budget && !self.redo_stack.is_empty() {
+            usage -= self.redo_stack.remove(0).size;
+        }
+    }
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new()
+        .with_system(undo_redo_on_key_press)
+        .with_system(apply_pending_steps.after(undo_redo_on_key_press))
+}
+
+pub fn undo_redo_on_key_press(keys: Res<Input<KeyCode>>, mut history: ResMut<History>) {
+    if !keys.pressed(KeyCode::LControl) || !keys.just_pressed(KeyCode::Z) {
+        return;
+    }
+    if keys.pressed(KeyCode::LShift) {
+        history.redo();
+    } else {
+        history.undo();
+    }
+}
+
+/// Undo or redo one entry per frame. Snapshots are restored by loading, so entries
+/// after a snapshot have to wait until the respawned entities are known.
+pub fn apply_pending_steps(
+    mut history: ResMut<History>,
+    mut q: Query<&mut Transform>,
+    mut skeleton: ResMut<Skeleton>,
+    mut animations: ResMut<Animations>,
+    mut anim_state: ResMut<animation::State>,
+    mut load_evw: EventWriter<LoadEvent>,
+) {
+    if history.waiting_for_load || history.pending_steps == 0 {
+        return;
+    }
+
+    let is_undo = history.pending_steps < 0;
+    let opt_entry = if is_undo {
+        history.undo_stack.pop()
+    } else {
+        history.redo_stack.pop()
+    };
+    let entry = match opt_entry {
+        Some(entry) => entry,
+        None => {
+            history.pending_steps = 0;
+            return;
+        }
+    };
+    history.pending_steps += if is_undo { 1 } else { -1 };
+    history.baseline_is_outdated = true;
+
+    match &entry.command {
+        Command::Transforms(transforms) => {
+            for &(entity, before, after) in transforms.iter() {
+                if let Ok(mut transform) = q.get_mut(entity) {
+                    *transform = if is_undo { before } else { after };
+                }
+            }
+        }
+        Command::Weights { before, after } => {
+            skeleton.skin_mappings = if is_undo { before } else { after }.clone();
+        }
+        Command::Animations { before, after } => {
+            let snapshot = if is_undo { before } else { after };
+            snapshot.apply(&mut animations, &mut anim_state);
+        }
+        Command::Snapshot { before, after } => {
+            load_evw.send(LoadEvent {
+                data: if is_undo { before } else { after }.clone(),
+                from_history: true,
+            });
+            history.waiting_for_load = true;
+        }
+    }
+
+    if is_undo {
+        history.redo_stack.push(entry);
+    } else {
+        history.undo_stack.push(entry);
+    }
+}
+
+/// Creates pending snapshots and keeps the baseline up to date.
+///
+/// Runs after all changes of the current frame have been applied and once on startup, so changes
+/// in the first frame can be undone. While changes are merged into the last entry, the baseline is
+/// built in the first frame after they end.
+pub fn update_baseline(
+    mut history: ResMut<History>,
+    q_bones: Query<(Entity, &Bone, &Transform, Option<&Parent>, &Transformable)>,
+    q_skins: Query<(Entity, &Skin, Option<&Cloth>)>,
+    q_targets: Query<(Entity, &Target, &Transform)>,
+    q_poles: Query<&Transform, With<Pole>>,
+    q_paths: Query<(Entity, &PathConstraint)>,
+    q_path_points: Query<&Transform, With<PathPoint>>,
+    q_constraints: Query<&ConstraintStack>,
+    animations: Res<Animations>,
+    anim_state: Res<animation::State>,
+    skeleton: Res<Skeleton>,
+    time: Res<Time>,
+) {
+    history.time = time.seconds_since_startup();
+    // The baseline is only built once changes stop being merged, not in every frame of a drag
+    let is_merging = std::mem::take(&mut history.is_merging);
+    if history.waiting_for_load {
+        return;
+    }
+    if history.pending_snapshot.is_none() && (!history.baseline_is_outdated || is_merging) {
+        return;
+    }
+
+    let current = save_load::build_complete_json(
+        &q_bones,
+        &q_skins,
+        &q_targets,
+        &q_poles,
+        &q_paths,
+        &q_path_points,
+        &q_constraints,
+        &animations,
+        &anim_state,
+        &skeleton,
+    );
+    if let Some(name) = history.pending_snapshot.take() {
+        match history.baseline.take() {
+            Some(before) => history.push(
+                &name,
+                Command::Snapshot {
+                    before,
+                    after: current.clone(),
+                },
+            ),
+            // The baseline is built on startup, so this should not happen
+            None => println!("update_baseline: No state before '{}' to undo to", name),
+        }
+    }
+    history.baseline = Some(current);
+    history.baseline_is_outdated = false;
+}
@@ -27,6 +27,18 @@ impl Function {
         .iter()
         .copied()
     }
+    /// Apply the easing function to the progress `x` between two keyframes
+    pub fn apply(&self, x: f32) -> f32 {
+        match self {
+            Function::Linear => x,
+            Function::EaseInOut => ease_in_out(x),
+            Function::EaseIn => ease_in(x),
+            Function::EaseOut => ease_out(x),
+            Function::EaseOutElastic => ease_out_elastic(x),
+            Function::EaseInOutElastic => ease_in_out_elastic(x),
+            Function::EaseInOutBack => ease_in_out_back(x),
+        }
+    }
 }
 impl ToString for Function {
     fn to_string(&self) -> String {
@@ -1,20 +1,39 @@
 use std::f32::consts::PI;
 
 use crate::{animation::Animatable, *};
+use bevy::utils::{HashMap, HashSet};
 use bone::Bone;
 use serde::{Serialize, Deserialize};
 
 extern crate nalgebra as na;
 
+pub const DEFAULT_SOFT_LIMIT: f32 = 0.05;
+pub const DEFAULT_TOLERANCE: f32 = 0.01;
+pub const DEFAULT_DAMPING: f32 = 0.1;
+
+#[cfg(test)]
+#[path = "tests/inverse_kinematics_tests.rs"]
+mod inverse_kinematics_tests;
+
 #[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum IKMethod {
     CCD,
     Jacobian,
+    FABRIK,
+    TwoBone,
+    DampedLeastSquares,
 }
 impl IKMethod {
     /// Get a vector containing all interpolation functions
     pub fn all() -> impl ExactSizeIterator<Item = IKMethod> {
-        [Self::CCD, Self::Jacobian].iter().copied()
+        [
+            Self::CCD,
+            Self::Jacobian,
+            Self::FABRIK,
+            Self::TwoBone,
+            Self::DampedLeastSquares,
+        ]
+        .iter().copied()
     }
 }
 impl ToString for IKMethod {
@@ -22,6 +41,34 @@ impl ToString for IKMethod {
         match self {
             Self::CCD => String::from("Cyclic Coordinate Descent"),
             Self::Jacobian => String::from("Jacobian Pseudo Inverse"),
+            Self::FABRIK => String::from("Forward And Backward Reaching"),
+            Self::TwoBone => String::from("Analytic Two Bone"),
+            Self::DampedLeastSquares => String::from("Damped Least Squares"),
+        }
+    }
+}
+
+/// Direction in which the middle joint of a two bone chain bends
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum BendDirection {
+    Clockwise,
+    CounterClockwise,
+}
+impl Default for BendDirection {
+    fn default() -> Self {
+        Self::CounterClockwise
+    }
+}
+impl BendDirection {
+    pub fn all() -> impl ExactSizeIterator<Item = BendDirection> {
+        [Self::Clockwise, Self::CounterClockwise].iter().copied()
+    }
+}
+impl ToString for BendDirection {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Clockwise => String::from("clockwise"),
+            Self::CounterClockwise => String::from("counterclockwise"),
         }
     }
 }
@@ -31,23 +78,49 @@ pub struct Target {
     pub ik_method: IKMethod,
     pub bone: Entity,
     pub depth: u8,
+    /// Only used by [`IKMethod::TwoBone`]
+    pub bend_direction: BendDirection,
+    /// Fraction of the chain length, over which the two bone solver slows down before full extension
+    pub soft_limit: f32,
+    /// Distance of the end effector to the target, at which the solver stops
+    pub tolerance: f32,
+    pub max_iterations: usize,
+    /// Only used by [`IKMethod::DampedLeastSquares`], higher values are more stable near singularities but slower
+    pub damping: f32,
+    /// 0 for the animated pose only, 1 for the IK pose only, can be keyed in animations
+    pub weight: f32,
+    pub enabled: bool,
+    /// The last bone of the chain also matches the target's rotation
+    pub match_rotation: bool,
+    /// If chains of several targets share bones, targets with higher priority win
+    pub priority: u8,
+    /// Entity with a [`Pole`] component, the chain bends towards it
+    pub pole: Option<Entity>,
 }
 
+/// Marks the pole of a [`Target`]
+#[derive(Component)]
+pub struct Pole;
+
 pub fn system_set() -> SystemSet {
     SystemSet::new()
-        .with_system(add_target)
+        .with_system(despawn_orphaned_poles)
+        // Poles spawned in this frame don't exist yet, so they would count as deleted
+        .with_system(add_target.after(despawn_orphaned_poles))
         .with_system(reach_for_target)
 }
 
 pub fn add_target(
     mut commands: Commands,
     mut q: Query<(Entity, &mut Transformable), With<Bone>>,
+    mut q_targets: Query<(Entity, &mut Target, &mut Transformable), Without<Bone>>,
     cursor_pos: Res<CursorPos>,
     keys: Res<Input<KeyCode>>,
     mouse: Res<Input<MouseButton>>,
     mut transform_state: ResMut<transform::State>,
     egui_state: Res<egui::State>,
     asset_server: Res<AssetServer>,
+    mut history: ResMut<history::History>,
 ) {
     // Add IK Target only if Alt + Left Mouse was pressed
     if !keys.pressed(KeyCode::LAlt) || !mouse.just_pressed(MouseButton::Left) {
@@ -80,6 +153,16 @@ pub fn add_target(
                     ik_method: egui_state.ik_method,
                     bone: bone_entity,
                     depth: egui_state.ik_depth,
+                    bend_direction: BendDirection::default(),
+                    soft_limit: DEFAULT_SOFT_LIMIT,
+                    tolerance: DEFAULT_TOLERANCE,
+                    max_iterations: egui_state.ik_max_iterations,
+                    damping: DEFAULT_DAMPING,
+                    weight: 1.,
+                    enabled: true,
+                    match_rotation: false,
+                    priority: 0,
+                    pole: None,
                 })
                 .insert(Transformable {
                     collision_shape: transform::PhantomShape::Point,
@@ -88,6 +171,21 @@ pub fn add_target(
                 .insert(Animatable)
                 .id(),
         );
+        history.record_snapshot("Add target");
+    } else if let Some((_, mut target, mut transformable)) = q_targets
+        .iter_mut()
+        .find(|(_, _, transformable)| transformable.is_selected)
+    {
+        // If a target is selected instead, place its pole
+        if let Some(pole) = target.pole {
+            commands.entity(pole).despawn();
+        }
+        let pole = spawn_pole(&mut commands, &asset_server, cursor_pos.0.extend(500.));
+        target.pole = Some(pole);
+        transformable.is_selected = false;
+        transform_state.selected_entities.clear();
+        transform_state.selected_entities.insert(pole);
+        history.record_snapshot("Add pole");
     } else {
         return;
     }
@@ -97,6 +195,179 @@ pub fn add_target(
     transform_state.action = Action::Done;
 }
 
+pub fn spawn_pole(commands: &mut Commands, asset_server: &AssetServer, translation: Vec3) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(translation),
+            sprite: Sprite {
+                color: COLOR_DEFAULT,
+                custom_size: Some(Vec2::new(0.25, 0.25)),
+                anchor: bevy::sprite::Anchor::Center,
+                ..Default::default()
+            },
+            texture: asset_server.load("img/ccd_target.png"),
+            ..Default::default()
+        })
+        .insert(Pole)
+        .insert(Transformable {
+            collision_shape: transform::PhantomShape::Point,
+            ..Default::default()
+        })
+        .insert(Animatable)
+        .id()
+}
+
+/// Despawns poles, whose target was removed, and detaches poles, that were removed, from their target
+pub fn despawn_orphaned_poles(
+    mut commands: Commands,
+    mut q_targets: Query<&mut Target>,
+    q_poles: Query<Entity, With<Pole>>,
+) {
+    for mut target in q_targets.iter_mut() {
+        if target.pole.map_or(false, |pole| !q_poles.contains(pole)) {
+            target.pole = None;
+        }
+    }
+    for pole in q_poles.iter() {
+        if !q_targets.iter().any(|target| target.pole == Some(pole)) {
+            commands.entity(pole).despawn();
+        }
+    }
+}
+
+/// Returns `angle` in the range from -PI to PI
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2. * PI) - PI
+}
+
+/// Global rotation of the chain's leaf around the z-axis
+fn get_end_effector_angle(chain: &Vec<Transform>) -> f32 {
+    kinematic_chain::get_gl_transform(0, chain)
+        .rotation
+        .to_euler(EulerRot::XYZ)
+        .2
+}
+
+/// Returns `rotation`, or the closest end of the constraint, if `rotation` lies outside of it
+fn clamp_to_constraint(rotation: Quat, c: &bone::AngleConstraint) -> Quat {
+    if c.start == c.end {
+        return rotation;
+    }
+    let rot = (rotation.to_euler(EulerRot::XYZ).2 + (4. * PI)) % (2. * PI);
+    if (rot >= c.start && rot <= c.end) || (rot >= c.start - 2. * PI && rot <= c.end - 2. * PI) {
+        return rotation;
+    }
+    let start_dist = (rot - (c.start % (2. * PI))).abs();
+    let end_dist = (rot - (c.end % (2. * PI))).abs();
+    let fixed_angle = if start_dist <= end_dist {
+        c.start
+    } else {
+        c.end
+    };
+    Quat::from_rotation_z(fixed_angle)
+}
+
+/// Whether `delta` pushes a joint, that already sits at a limit of its angle constraint, further outward
+fn pushes_beyond_constraint(rotation: Quat, delta: f32, constraint: &Option<bone::AngleConstraint>) -> bool {
+    let c = match constraint {
+        Some(c) => c,
+        None => return false,
+    };
+    let updated = rotation * Quat::from_rotation_z(delta);
+    let clamped = clamp_to_constraint(updated, c);
+    clamped != updated && clamped.angle_between(rotation) <= 0.001
+}
+
+/// Returns the joints' rotation updates computed by `solve`, which gets the joints to leave out of the Jacobian.
+///
+/// Joints at a limit, that the update would push further outward, are left out and the update is solved again,
+/// so the remaining joints compensate. This is decided anew for every update, so joints leave their limit as
+/// soon as the update points back inside. Returns None, if `solve` fails or all joints are left out.
+fn solve_with_constraints(
+    rotations: &Vec<Quat>,
+    constraints: &Vec<Option<bone::AngleConstraint>>,
+    step: f32,
+    mut solve: impl FnMut(&Vec<bool>) -> Option<Vec<f32>>,
+) -> Option<Vec<f32>> {
+    let mut locked = vec![false; rotations.len()];
+    loop {
+        let delta_rotations = solve(&locked)?;
+        let opt_pushed = (0..rotations.len()).find(|&i| {
+            !locked[i] && pushes_beyond_constraint(rotations[i], delta_rotations[i] * step, &constraints[i])
+        });
+        match opt_pushed {
+            Some(i) => locked[i] = true,
+            None => return Some(delta_rotations),
+        }
+        if locked.iter().all(|&is_locked| is_locked) {
+            return None;
+        }
+    }
+}
+
+/// Returns the joint positions of `chain` ordered from root to leaf, followed by the tip of the chain
+pub fn get_joint_positions(chain: &Vec<Transform>) -> Vec<Vec2> {
+    let mut positions: Vec<Vec2> = (0..chain.len())
+        .rev()
+        .map(|i| {
+            kinematic_chain::get_gl_transform(i, chain)
+                .translation
+                .truncate()
+        })
+        .collect();
+    positions.push(kinematic_chain::get_tip_chain(chain).truncate());
+    positions
+}
+
+/// Solves `chain` with `solve`, so that the end effector reaches `target` with a global rotation of `goal_angle`.
+///
+/// The leaf bone is aligned with the goal in a final step, while the rest of the chain reaches for the
+/// position of the leaf's joint.
+fn solve_with_orientation(
+    chain: Vec<Transform>,
+    constraints: Vec<Option<bone::AngleConstraint>>,
+    target: Vec2,
+    goal_angle: f32,
+    solve: impl FnOnce(Vec<Transform>, Vec<Option<bone::AngleConstraint>>, Vec2) -> Vec<Quat>,
+) -> Vec<Quat> {
+    let leaf_length = kinematic_chain::get_gl_transform(0, &chain).scale.y;
+    let goal_dir = Vec2::from_angle(goal_angle).rotate(Vec2::Y);
+    let joint_target = target - goal_dir * leaf_length;
+
+    let mut solved_chain = chain.clone();
+    if chain.len() > 1 {
+        // Let the tip of the sub chain lie on the leaf's joint
+        let mut sub_chain = chain[1..].to_vec();
+        let offset = (sub_chain[0].scale * chain[0].translation).truncate();
+        if offset.length() > f32::EPSILON {
+            let offset_rotation = Quat::from_rotation_arc_2d(Vec2::Y, offset.normalize());
+            sub_chain[0].rotation = sub_chain[0].rotation * offset_rotation;
+            sub_chain[0].scale.y = offset.length();
+            let sub_rotations = solve(sub_chain, constraints[1..].to_vec(), joint_target);
+            for i in 1..chain.len() {
+                solved_chain[i].rotation = sub_rotations[i - 1];
+            }
+            solved_chain[1].rotation = solved_chain[1].rotation * offset_rotation.inverse();
+        }
+    }
+
+    // Align leaf with the goal
+    let parent_angle = if chain.len() > 1 {
+        kinematic_chain::get_gl_transform(1, &solved_chain)
+            .rotation
+            .to_euler(EulerRot::XYZ)
+            .2
+    } else {
+        0.
+    };
+    solved_chain[0].rotation = Quat::from_rotation_z(goal_angle - parent_angle);
+    if let Some(c) = &constraints[0] {
+        solved_chain[0].rotation = clamp_to_constraint(solved_chain[0].rotation, c);
+    }
+
+    solved_chain.iter().map(|transform| transform.rotation).collect()
+}
+
 /// Returns a vector of rotation quaternions, at which `chain` reaches target.
 ///
 /// The algorithm ends as soon as `eps` greater or equal to the distance of the end effector to `target`,
@@ -126,21 +397,7 @@ fn get_target_rotations_ccd(
             chain[i].rotation = (chain[i].rotation * delta_rot).normalize();
 
             if let Some(c) = &constraints[i] {
-                if c.start != c.end {
-                    let rot = (chain[i].rotation.to_euler(EulerRot::XYZ).2 + (4.*PI)) % (2.*PI);
-                    if !((rot >= c.start && rot <= c.end)
-                        || (rot >= c.start - 2. * PI && rot <= c.end - 2. * PI))
-                    {
-                        let start_dist = (rot - (c.start % (2. * PI))).abs();
-                        let end_dist = (rot - (c.end % (2. * PI))).abs();
-                        let fixed_angle = if start_dist <= end_dist {
-                            c.start
-                        } else {
-                            c.end
-                        };
-                        chain[i].rotation = Quat::from_rotation_z(fixed_angle);
-                    }
-                }
+                chain[i].rotation = clamp_to_constraint(chain[i].rotation, c);
             }
 
             end_effector_pos = kinematic_chain::get_tip_chain(&chain).truncate();
@@ -152,9 +409,171 @@ fn get_target_rotations_ccd(
     chain.iter().map(|transform| transform.rotation).collect()
 }
 
+/// Rotates the bones from root to leaf, so that each points at its child's position in `positions`.
+///
+/// `positions` is ordered like the result of [`get_joint_positions`].
+pub fn rotate_towards_positions(
+    chain: &mut Vec<Transform>,
+    positions: &Vec<Vec2>,
+    constraints: &Vec<Option<bone::AngleConstraint>>,
+) {
+    for j in 0..positions.len() - 1 {
+        let i = chain.len() - 1 - j;
+        let current_positions = get_joint_positions(chain);
+        let current_dir = (current_positions[j + 1] - current_positions[j]).normalize_or_zero();
+        let new_dir = (positions[j + 1] - current_positions[j]).normalize_or_zero();
+        if current_dir == Vec2::ZERO || new_dir == Vec2::ZERO {
+            continue;
+        }
+        let delta_rot = Quat::from_rotation_arc_2d(current_dir, new_dir);
+        chain[i].rotation = (chain[i].rotation * delta_rot).normalize();
+
+        if let Some(c) = &constraints[i] {
+            chain[i].rotation = clamp_to_constraint(chain[i].rotation, c);
+        }
+    }
+}
+
+/// Returns the rotations of `chain`, after it was bent towards `pole`.
+///
+/// If the joints between the root and the joint with index `end` (see [`get_joint_positions`]) mostly lie on
+/// the other side of the line connecting both than the pole, they are mirrored across that line. This keeps
+/// the lengths of all bones as well as the position of the end joint.
+fn bend_towards_pole(
+    mut chain: Vec<Transform>,
+    constraints: &Vec<Option<bone::AngleConstraint>>,
+    pole: Vec2,
+    end: usize,
+) -> Vec<Quat> {
+    if end < 2 {
+        return chain.iter().map(|transform| transform.rotation).collect();
+    }
+    let mut positions = get_joint_positions(&chain);
+    let root = positions[0];
+    let axis = (positions[end] - root).normalize_or_zero();
+    let side: f32 = positions[1..end]
+        .iter()
+        .map(|&position| axis.perp_dot(position - root))
+        .sum();
+    if axis == Vec2::ZERO || side * axis.perp_dot(pole - root) >= 0. {
+        return chain.iter().map(|transform| transform.rotation).collect();
+    }
+
+    for position in positions[1..end].iter_mut() {
+        let projected = root + axis * axis.dot(*position - root);
+        *position = 2. * projected - *position;
+    }
+    rotate_towards_positions(&mut chain, &positions, constraints);
+    chain.iter().map(|transform| transform.rotation).collect()
+}
+
+/// Returns a vector of rotation quaternions, at which `chain` reaches target, using FABRIK.
+///
+/// Each iteration moves the joint positions backwards from the target and forwards from the root,
+/// then rotates the bones towards the new positions, so that angle constraints can be applied.
+/// The algorithm ends as soon as `eps` greater or equal to the distance of the end effector to `target`,
+/// or `max_it` iterations have been executed.
+fn get_target_rotations_fabrik(
+    mut chain: Vec<Transform>,
+    constraints: Vec<Option<bone::AngleConstraint>>,
+    target: Vec2,
+    eps: f32,
+    max_it: usize,
+) -> Vec<Quat> {
+    let mut positions = get_joint_positions(&chain);
+    let lengths: Vec<f32> = positions.windows(2).map(|w| w[0].distance(w[1])).collect();
+    let root_pos = positions[0];
+    let n = positions.len();
+
+    for _ in 0..max_it {
+        if positions[n - 1].distance(target) <= eps {
+            break;
+        }
+
+        // Backward reaching, starting at the target
+        positions[n - 1] = target;
+        for j in (0..n - 1).rev() {
+            let dir = (positions[j] - positions[j + 1]).normalize_or_zero();
+            positions[j] = positions[j + 1] + dir * lengths[j];
+        }
+
+        // Forward reaching, starting at the root
+        positions[0] = root_pos;
+        for j in 1..n {
+            let dir = (positions[j] - positions[j - 1]).normalize_or_zero();
+            positions[j] = positions[j - 1] + dir * lengths[j - 1];
+        }
+
+        rotate_towards_positions(&mut chain, &positions, &constraints);
+        positions = get_joint_positions(&chain);
+    }
+    chain.iter().map(|transform| transform.rotation).collect()
+}
+
+/// Returns a vector of rotation quaternions, at which a chain of two bones reaches target.
+///
+/// The position of the middle joint is calculated with the law of cosines, so there is no jitter.
+/// Close to full extension the target is pulled towards the root, so that the chain doesn't snap straight.
+/// Chains of a different length are solved with FABRIK instead.
+fn get_target_rotations_two_bone(
+    mut chain: Vec<Transform>,
+    constraints: Vec<Option<bone::AngleConstraint>>,
+    target: Vec2,
+    bend_direction: BendDirection,
+    soft_limit: f32,
+    eps: f32,
+    max_it: usize,
+) -> Vec<Quat> {
+    if chain.len() != 2 {
+        return get_target_rotations_fabrik(chain, constraints, target, eps, max_it);
+    }
+
+    let positions = get_joint_positions(&chain);
+    let root_pos = positions[0];
+    let upper_length = positions[0].distance(positions[1]);
+    let lower_length = positions[1].distance(positions[2]);
+    let max_dist = upper_length + lower_length;
+    let min_dist = (upper_length - lower_length).abs();
+
+    let root_to_target = target - root_pos;
+    let dir = root_to_target.normalize_or_zero();
+    if dir == Vec2::ZERO || upper_length == 0. || lower_length == 0. {
+        return chain.iter().map(|transform| transform.rotation).collect();
+    }
+
+    // Approach full extension asymptotically
+    let mut dist = root_to_target.length();
+    let soft_dist = max_dist * soft_limit;
+    if soft_dist > 0. && dist > max_dist - soft_dist {
+        dist = max_dist - soft_dist + soft_dist * (1. - (-(dist - max_dist + soft_dist) / soft_dist).exp());
+    }
+    dist = dist.clamp(min_dist, max_dist);
+
+    // Angle between the line from root to target and the upper bone
+    let cos_angle = ((upper_length * upper_length + dist * dist - lower_length * lower_length)
+        / (2. * upper_length * dist))
+        .clamp(-1., 1.);
+    let angle = match bend_direction {
+        BendDirection::CounterClockwise => -cos_angle.acos(),
+        BendDirection::Clockwise => cos_angle.acos(),
+    };
+
+    let middle_pos = root_pos + dir.rotate(Vec2::from_angle(angle)) * upper_length;
+    let tip_pos = root_pos + dir * dist;
+    rotate_towards_positions(&mut chain, &vec![root_pos, middle_pos, tip_pos], &constraints);
+
+    chain.iter().map(|transform| transform.rotation).collect()
+}
+
+/// Returns a vector of rotation quaternions, at which `chain` reaches target, using the Jacobian pseudo inverse.
+///
+/// Joints are clamped to their angle constraint. While an update would push a joint beyond its limit, its column
+/// of the Jacobian is masked, so the remaining joints compensate.
 pub fn get_target_rotations_jacobian(
     mut chain: Vec<Transform>,
+    constraints: Vec<Option<bone::AngleConstraint>>,
     target: Vec2,
+    goal_angle: Option<f32>,
     eps: f32,
     step: f32,
     max_it: usize,
@@ -163,27 +582,36 @@ pub fn get_target_rotations_jacobian(
 
     // let get_pseudo_inverse = ||
 
-    let get_jacobian_pseudo_inverse = |v: Vec3, chain: &Vec<Transform>| -> na::DMatrix<f32> {
+    let get_jacobian_pseudo_inverse = |v: Vec3, chain: &Vec<Transform>, locked: &Vec<bool>| -> na::DMatrix<f32> {
         let rot_axis = Vec3::new(0.0, 0.0, 1.0);
 
         let mut res = na::DMatrix::<f32>::zeros(3, chain.len());
 
         for i in 0..chain.len() {
+            // Locked joints can't contribute to the movement of the end effector
+            if locked[i] {
+                continue;
+            }
             let end_effector_pos = kinematic_chain::get_tip_chain(&chain);
             let joint_pos = kinematic_chain::get_gl_transform(i, &chain).translation;
             let cross_product = animation::cross_product(rot_axis, end_effector_pos - joint_pos);
             res[(0, i)] = cross_product.x;
             res[(1, i)] = cross_product.y;
-            res[(2, i)] = cross_product.z;
+            // The z component is always zero, so it's used for the end effector's rotation instead
+            res[(2, i)] = if goal_angle.is_some() {
+                1.0
+            } else {
+                cross_product.z
+            };
         }
 
         res.pseudo_inverse(0.001).unwrap()
         // res.transpose()
     };
 
-    let get_delta_angles = |v: Vec3, chain: &Vec<Transform>| -> Vec<f32> {
+    let get_delta_angles = |v: Vec3, chain: &Vec<Transform>, locked: &Vec<bool>| -> Vec<f32> {
         let mut delta_rotations = vec![];
-        let jacobian_pseudo_inverse = get_jacobian_pseudo_inverse(v, chain);
+        let jacobian_pseudo_inverse = get_jacobian_pseudo_inverse(v, chain, locked);
 
         for jtp_row in jacobian_pseudo_inverse.row_iter() {
             let row = Vec3::new(jtp_row[0], jtp_row[1], jtp_row[2]);
@@ -216,105 +644,677 @@ pub fn get_target_rotations_jacobian(
         // }
 
         v = target_arranged.extend(0.0) - end_effector_pos;
+        if let Some(goal_angle) = goal_angle {
+            v.z = wrap_angle(goal_angle - get_end_effector_angle(&chain));
+        }
         if v.length() <= eps {
             break;
         }
 
-        let delta_rotations = get_delta_angles(v, &chain);
+        let rotations: Vec<Quat> = chain.iter().map(|transform| transform.rotation).collect();
+        let delta_rotations = match solve_with_constraints(&rotations, &constraints, step, |locked| {
+            Some(get_delta_angles(v, &chain, locked))
+        }) {
+            Some(delta_rotations) => delta_rotations,
+            None => break,
+        };
 
         for i in 0..chain.len() {
             chain[i].rotation *= Quat::from_rotation_z(delta_rotations[i] * step);
+
+            if let Some(c) = &constraints[i] {
+                chain[i].rotation = clamp_to_constraint(chain[i].rotation, c);
+            }
         }
     }
 
     chain.iter().map(|transform| transform.rotation).collect()
 }
 
-pub fn reach_for_target(
-    mut commands: Commands,
-    mut q_bones: Query<(&mut Transform, Option<&Parent>, &mut Bone)>,
-    egui_state: Res<egui::State>,
-    q_targets: Query<(Entity, &Transform, &Target), Without<Bone>>,
-) {
-    // Reset bone.is_ik_maneuvered
-    for (_, _, mut bone) in q_bones.iter_mut() {
-        bone.is_ik_maneuvered = false;
+/// Bones of several overlapping chains in an arbitrary order
+#[derive(Default, Clone)]
+struct JointTree {
+    transforms: Vec<Transform>,
+    /// Index of each bone's parent, if the parent is part of the tree
+    parents: Vec<Option<usize>>,
+    /// Global transform of each bone's parent, only used if the parent isn't part of the tree
+    parent_gl_transforms: Vec<Transform>,
+    constraints: Vec<Option<bone::AngleConstraint>>,
+}
+impl JointTree {
+    fn get_gl_transform(&self, index: usize) -> Transform {
+        let mut gl_transform = self.transforms[index];
+        let mut current = index;
+        while let Some(parent) = self.parents[current] {
+            gl_transform = combined_transform(&self.transforms[parent], &gl_transform);
+            current = parent;
+        }
+        combined_transform(&self.parent_gl_transforms[current], &gl_transform)
     }
-
-    // For each TARGET
-    for (entity, target_transform, target) in q_targets.iter() {
-        // Construct chain from bones query
-        let mut chain_transforms = vec![];
-        let mut chain_entities = vec![];
-        let mut chain_constraints = vec![];
-        let mut next_bone = target.bone;
-        let mut chain_has_parent_bone = true;
-        for _ in 0..target.depth {
-            if let Ok((_, _, mut bone)) = q_bones.get_mut(next_bone) {
-                // Mark bone as ik_maneuvered
-                bone.is_ik_maneuvered = true;
-            } else {
-                // If bone was removed, despawn target
-                commands.entity(entity).despawn();
-                return;
+    fn is_ancestor_or_self(&self, ancestor: usize, index: usize) -> bool {
+        let mut current = Some(index);
+        while let Some(i) = current {
+            if i == ancestor {
+                return true;
             }
+            current = self.parents[i];
+        }
+        false
+    }
+}
+
+/// End effector of a stacked Jacobian solve, the tip of the bone with index `joint` reaches for `target`
+struct Effector {
+    joint: usize,
+    target: Vec2,
+    goal_angle: Option<f32>,
+    priority: u8,
+}
 
-            let (transform, opt_parent, bone) = q_bones.get(next_bone).unwrap();
+/// Rows of targets with a higher priority are weighted stronger in the stacked Jacobian
+fn get_priority_weight(priority: u8) -> f32 {
+    2f32.powi(priority as i32)
+}
 
-            // Add bone to chain and continue with parent, if present
-            chain_transforms.push(transform.clone());
-            chain_entities.push(next_bone);
-            chain_constraints.push(bone.ik_angle_constraint.clone());
-            next_bone = if let Some(parent) = opt_parent {
-                parent.get()
-            } else {
-                chain_has_parent_bone = false;
-                break;
+/// Returns rotation quaternions of all joints, at which all effectors reach their targets as close as possible.
+///
+/// All effectors are solved at once with one stacked Jacobian, so chains sharing bones don't fight each other.
+/// Uses damped least squares if `damping` is set, the pseudo inverse otherwise.
+fn get_target_rotations_stacked(
+    mut tree: JointTree,
+    effectors: &Vec<Effector>,
+    damping: Option<f32>,
+    eps: f32,
+    max_it: usize,
+) -> Vec<Quat> {
+    let joint_count = tree.transforms.len();
+    let row_count: usize = effectors
+        .iter()
+        .map(|effector| if effector.goal_angle.is_some() { 3 } else { 2 })
+        .sum();
+    // Half the length of all links, like for a single chain
+    let mut tree_length: f32 = effectors
+        .iter()
+        .map(|effector| tree.get_gl_transform(effector.joint).scale.y)
+        .sum();
+    for i in 0..joint_count {
+        if let Some(parent) = tree.parents[i] {
+            tree_length += tree
+                .get_gl_transform(i)
+                .translation
+                .distance(tree.get_gl_transform(parent).translation);
+        }
+    }
+    let max_error = tree_length * 0.5;
+
+    // Weighted error of all effectors, the position error is clamped to `max_error` if `clamp` is set
+    let get_error = |tree: &JointTree, clamp: bool| -> Vec<f32> {
+        let mut e = vec![];
+        for effector in effectors.iter() {
+            let weight = get_priority_weight(effector.priority);
+            let gl_transform = tree.get_gl_transform(effector.joint);
+            let mut position_error = effector.target - kinematic_chain::get_tip(&gl_transform).truncate();
+            if clamp && position_error.length() > max_error {
+                position_error = position_error.normalize() * max_error;
+            }
+            e.push(position_error.x * weight);
+            e.push(position_error.y * weight);
+            if let Some(goal_angle) = effector.goal_angle {
+                let angle = gl_transform.rotation.to_euler(EulerRot::XYZ).2;
+                e.push(wrap_angle(goal_angle - angle) * weight);
             }
         }
+        e
+    };
+    let get_error_length = |e: &Vec<f32>| e.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    let mut step = 1.0;
+    let mut error = get_error_length(&get_error(&tree, false));
 
-        // Get root bone's global transform
-        let mut root_gl_transform = Transform::default();
-        loop {
-            // If chain has no parent, break
-            if !chain_has_parent_bone {
-                break;
+    for _ in 0..max_it {
+        if error <= eps || step < 0.001 {
+            break;
+        }
+
+        let gl_transforms: Vec<Transform> = (0..joint_count).map(|i| tree.get_gl_transform(i)).collect();
+        let e = na::DVector::from_vec(get_error(&tree, true));
+        let solve = |locked: &Vec<bool>| -> Option<Vec<f32>> {
+            let mut jacobian = na::DMatrix::<f32>::zeros(row_count, joint_count);
+            let mut row = 0;
+            for effector in effectors.iter() {
+                let weight = get_priority_weight(effector.priority);
+                let end_effector_pos = kinematic_chain::get_tip(&gl_transforms[effector.joint]).truncate();
+                for j in 0..joint_count {
+                    // Only ancestors of the effector move it
+                    if locked[j] || !tree.is_ancestor_or_self(j, effector.joint) {
+                        continue;
+                    }
+                    let r = end_effector_pos - gl_transforms[j].translation.truncate();
+                    jacobian[(row, j)] = -r.y * weight;
+                    jacobian[(row + 1, j)] = r.x * weight;
+                    if effector.goal_angle.is_some() {
+                        jacobian[(row + 2, j)] = weight;
+                    }
+                }
+                row += if effector.goal_angle.is_some() { 3 } else { 2 };
             }
 
-            // If bone was removed, despawn target
-            if q_bones.get(next_bone).is_err() {
-                commands.entity(entity).despawn();
-                return;
+            let delta_rotations = match damping {
+                Some(damping) => {
+                    let damped = &jacobian * jacobian.transpose()
+                        + na::DMatrix::<f32>::identity(row_count, row_count) * (damping * damping);
+                    jacobian.transpose() * damped.try_inverse()? * &e
+                }
+                None => jacobian.pseudo_inverse(0.001).ok()? * &e,
+            };
+            Some(delta_rotations.iter().copied().collect())
+        };
+        let rotations: Vec<Quat> = tree.transforms.iter().map(|transform| transform.rotation).collect();
+        let delta_rotations = match solve_with_constraints(&rotations, &tree.constraints, step, solve) {
+            Some(delta_rotations) => delta_rotations,
+            None => break,
+        };
+
+        let previous_transforms = tree.transforms.clone();
+        for j in 0..joint_count {
+            tree.transforms[j].rotation *= Quat::from_rotation_z(delta_rotations[j] * step);
+
+            if let Some(c) = &tree.constraints[j] {
+                tree.transforms[j].rotation = clamp_to_constraint(tree.transforms[j].rotation, c);
             }
+        }
 
-            let (transform, opt_parent, _) = q_bones.get(next_bone).unwrap();
+        // Adapt step size
+        let new_error = get_error_length(&get_error(&tree, false));
+        if new_error > error {
+            tree.transforms = previous_transforms;
+            step *= 0.5;
+        } else {
+            error = new_error;
+            step = (step * 1.5).min(1.0);
+        }
+    }
 
-            root_gl_transform = combined_transform(transform, &root_gl_transform);
-            next_bone = if let Some(parent) = opt_parent {
-                parent.get()
-            } else {
-                break;
+    tree.transforms.iter().map(|transform| transform.rotation).collect()
+}
+
+/// Returns a vector of rotation quaternions, at which `chain` reaches target, using damped least squares.
+///
+/// Unlike the pseudo inverse, the damping keeps joint velocities small near singularities, e.g. when the chain
+/// is fully extended. The step size is halved whenever an iteration increases the distance to the target,
+/// and grows again after successful iterations.
+fn get_target_rotations_dls(
+    mut chain: Vec<Transform>,
+    constraints: Vec<Option<bone::AngleConstraint>>,
+    target: Vec2,
+    goal_angle: Option<f32>,
+    damping: f32,
+    eps: f32,
+    max_it: usize,
+) -> Vec<Quat> {
+    // Limit the distance the end effector is moved per iteration, far targets would cause large jumps
+    let max_error = kinematic_chain::get_chain_length(&chain) * 0.5;
+    let mut step = 1.0;
+    // Third row of the Jacobian is the end effector's rotation
+    let row_count = if goal_angle.is_some() { 3 } else { 2 };
+    let get_error = |chain: &Vec<Transform>| -> Vec<f32> {
+        let e = target - kinematic_chain::get_tip_chain(chain).truncate();
+        match goal_angle {
+            Some(goal_angle) => vec![
+                e.x,
+                e.y,
+                wrap_angle(goal_angle - get_end_effector_angle(chain)),
+            ],
+            None => vec![e.x, e.y],
+        }
+    };
+    let get_error_length = |e: &Vec<f32>| e.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let mut error = get_error_length(&get_error(&chain));
+
+    for _ in 0..max_it {
+        if error <= eps || step < 0.001 {
+            break;
+        }
+
+        let end_effector_pos = kinematic_chain::get_tip_chain(&chain).truncate();
+        let mut e = get_error(&chain);
+        let position_error = Vec2::new(e[0], e[1]);
+        if position_error.length() > max_error {
+            let clamped = position_error.normalize() * max_error;
+            e[0] = clamped.x;
+            e[1] = clamped.y;
+        }
+
+        // Rotation axis is always z, so each column is the end effector's velocity when rotating one joint
+        let solve = |locked: &Vec<bool>| -> Option<Vec<f32>> {
+            let mut jacobian = na::DMatrix::<f32>::zeros(row_count, chain.len());
+            for i in 0..chain.len() {
+                if locked[i] {
+                    continue;
+                }
+                let joint_pos = kinematic_chain::get_gl_transform(i, &chain)
+                    .translation
+                    .truncate();
+                let r = end_effector_pos - joint_pos;
+                jacobian[(0, i)] = -r.y;
+                jacobian[(1, i)] = r.x;
+                if row_count == 3 {
+                    jacobian[(2, i)] = 1.0;
+                }
+            }
+
+            // delta = J^T (J J^T + damping^2 I)^-1 e
+            let damped = &jacobian * jacobian.transpose()
+                + na::DMatrix::<f32>::identity(row_count, row_count) * (damping * damping);
+            let delta_rotations =
+                jacobian.transpose() * damped.try_inverse()? * na::DVector::from_vec(e.clone());
+            Some(delta_rotations.iter().copied().collect())
+        };
+        let rotations: Vec<Quat> = chain.iter().map(|transform| transform.rotation).collect();
+        let delta_rotations = match solve_with_constraints(&rotations, &constraints, step, solve) {
+            Some(delta_rotations) => delta_rotations,
+            None => break,
+        };
+
+        let previous_chain = chain.clone();
+        for i in 0..chain.len() {
+            chain[i].rotation *= Quat::from_rotation_z(delta_rotations[i] * step);
+
+            if let Some(c) = &constraints[i] {
+                chain[i].rotation = clamp_to_constraint(chain[i].rotation, c);
             }
         }
 
-        // Get IK target relative to the root of the kinematic chain
-        let target_pos = get_relative_transform(&root_gl_transform, &target_transform)
-            .translation
-            .truncate();
+        // Adapt step size
+        let new_error = get_error_length(&get_error(&chain));
+        if new_error > error {
+            chain = previous_chain;
+            step *= 0.5;
+        } else {
+            error = new_error;
+            step = (step * 1.5).min(1.0);
+        }
+    }
+
+    chain.iter().map(|transform| transform.rotation).collect()
+}
+
+/// A target together with the bones of its kinematic chain
+struct TargetChain {
+    target: Target,
+    transform: Transform,
+    /// Ordered from leaf to root
+    bones: Vec<Entity>,
+    pole: Option<Transform>,
+}
+
+/// Returns the bones of the target's chain ordered from leaf to root, or `None` if a bone was removed
+fn get_chain_entities(
+    target: &Target,
+    q_bones: &Query<(&mut Transform, Option<&Parent>, &mut Bone)>,
+) -> Option<Vec<Entity>> {
+    let mut bones = vec![];
+    let mut next_bone = target.bone;
+    for _ in 0..target.depth {
+        let (_, opt_parent, _) = q_bones.get(next_bone).ok()?;
+        bones.push(next_bone);
+        next_bone = match opt_parent {
+            Some(parent) => parent.get(),
+            None => break,
+        };
+    }
+    Some(bones)
+}
+
+/// Returns the global transform of the parent of `bone`, or the identity for root bones
+pub fn get_parent_gl_transform(
+    bone: Entity,
+    q_bones: &Query<(&mut Transform, Option<&Parent>, &mut Bone)>,
+) -> Option<Transform> {
+    let (_, opt_parent, _) = q_bones.get(bone).ok()?;
+    let mut gl_transform = Transform::default();
+    let mut next_bone = opt_parent.map(|parent| parent.get());
+    while let Some(entity) = next_bone {
+        let (transform, opt_parent, _) = q_bones.get(entity).ok()?;
+        gl_transform = combined_transform(transform, &gl_transform);
+        next_bone = opt_parent.map(|parent| parent.get());
+    }
+    Some(gl_transform)
+}
 
-        let target_rotations = match target.ik_method {
-            IKMethod::CCD => {
-                get_target_rotations_ccd(chain_transforms, chain_constraints, target_pos, 0.01, egui_state.ik_max_iterations)
+/// Returns groups of indices of all chains that share at least one bone, directly or through other chains
+fn group_overlapping_chains(chains: &Vec<Vec<Entity>>) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(HashSet<Entity>, Vec<usize>)> = vec![];
+    for (i, chain) in chains.iter().enumerate() {
+        let mut merged: (HashSet<Entity>, Vec<usize>) = (chain.iter().copied().collect(), vec![i]);
+        // Merge all groups that overlap with the current chain
+        let mut k = 0;
+        while k < groups.len() {
+            if groups[k].0.is_disjoint(&merged.0) {
+                k += 1;
+                continue;
             }
-            IKMethod::Jacobian => {
-                get_target_rotations_jacobian(chain_transforms, target_pos, 0.01, 1.0, egui_state.ik_max_iterations)
+            let (bones, indices) = groups.remove(k);
+            merged.0.extend(bones);
+            merged.1.extend(indices);
+        }
+        groups.push(merged);
+    }
+    groups
+        .into_iter()
+        .map(|(_, mut indices)| {
+            indices.sort();
+            indices
+        })
+        .collect()
+}
+
+/// Returns the transforms and angle constraints of the target's chain, as well as the global transform of its
+/// root's parent
+fn get_chain(
+    target_chain: &TargetChain,
+    q_bones: &Query<(&mut Transform, Option<&Parent>, &mut Bone)>,
+) -> Option<(Vec<Transform>, Vec<Option<bone::AngleConstraint>>, Transform)> {
+    let mut chain_transforms = vec![];
+    let mut chain_constraints = vec![];
+    for &bone_entity in target_chain.bones.iter() {
+        let (transform, _, bone) = q_bones.get(bone_entity).ok()?;
+        chain_transforms.push(*transform);
+        chain_constraints.push(bone.ik_angle_constraint.clone());
+    }
+    let root_gl_transform = get_parent_gl_transform(*target_chain.bones.last()?, q_bones)?;
+    Some((chain_transforms, chain_constraints, root_gl_transform))
+}
+
+/// Solves a single chain with the target's IK method and returns the new rotations of its bones
+fn solve_chain(
+    target_chain: &TargetChain,
+    q_bones: &Query<(&mut Transform, Option<&Parent>, &mut Bone)>,
+    max_it: usize,
+) -> Option<Vec<Quat>> {
+    let target = &target_chain.target;
+    let (chain_transforms, chain_constraints, root_gl_transform) = get_chain(target_chain, q_bones)?;
+
+    // Get IK target relative to the root of the kinematic chain
+    let relative_target_transform = get_relative_transform(&root_gl_transform, &target_chain.transform);
+    let target_pos = relative_target_transform.translation.truncate();
+    let opt_goal_angle = if target.match_rotation {
+        Some(relative_target_transform.rotation.to_euler(EulerRot::XYZ).2)
+    } else {
+        None
+    };
+
+    let target_rotations = match target.ik_method {
+        IKMethod::Jacobian => get_target_rotations_jacobian(
+            chain_transforms.clone(),
+            chain_constraints.clone(),
+            target_pos,
+            opt_goal_angle,
+            target.tolerance,
+            1.0,
+            max_it,
+        ),
+        IKMethod::DampedLeastSquares => get_target_rotations_dls(
+            chain_transforms.clone(),
+            chain_constraints.clone(),
+            target_pos,
+            opt_goal_angle,
+            target.damping,
+            target.tolerance,
+            max_it,
+        ),
+        _ => {
+            let solve = |chain: Vec<Transform>,
+                         constraints: Vec<Option<bone::AngleConstraint>>,
+                         target_pos: Vec2| match target.ik_method {
+                IKMethod::CCD => get_target_rotations_ccd(
+                    chain,
+                    constraints,
+                    target_pos,
+                    target.tolerance,
+                    max_it,
+                ),
+                IKMethod::TwoBone => get_target_rotations_two_bone(
+                    chain,
+                    constraints,
+                    target_pos,
+                    target.bend_direction,
+                    target.soft_limit,
+                    target.tolerance,
+                    max_it,
+                ),
+                _ => get_target_rotations_fabrik(
+                    chain,
+                    constraints,
+                    target_pos,
+                    target.tolerance,
+                    max_it,
+                ),
+            };
+            match opt_goal_angle {
+                Some(goal_angle) => solve_with_orientation(
+                    chain_transforms.clone(),
+                    chain_constraints.clone(),
+                    target_pos,
+                    goal_angle,
+                    solve,
+                ),
+                None => solve(chain_transforms.clone(), chain_constraints.clone(), target_pos),
             }
+        }
+    };
+
+    let pole = match target_chain.pole {
+        Some(pole) => pole,
+        None => return Some(target_rotations),
+    };
+
+    // Bend towards the pole, unless this moves the end effector away from the target.
+    // With a rotation goal the leaf bone keeps its rotation.
+    let relative_pole = get_relative_transform(&root_gl_transform, &pole).translation.truncate();
+    let end = if opt_goal_angle.is_some() {
+        chain_transforms.len() - 1
+    } else {
+        chain_transforms.len()
+    };
+    let mut posed_chain = chain_transforms;
+    for (transform, &rotation) in posed_chain.iter_mut().zip(target_rotations.iter()) {
+        transform.rotation = rotation;
+    }
+    let bent_rotations = bend_towards_pole(posed_chain.clone(), &chain_constraints, relative_pole, end);
+    let distance = kinematic_chain::get_tip_chain(&posed_chain).truncate().distance(target_pos);
+    for (transform, &rotation) in posed_chain.iter_mut().zip(bent_rotations.iter()) {
+        transform.rotation = rotation;
+    }
+    let bent_distance = kinematic_chain::get_tip_chain(&posed_chain).truncate().distance(target_pos);
+    if bent_distance <= distance + target.tolerance {
+        Some(bent_rotations)
+    } else {
+        Some(target_rotations)
+    }
+}
+
+/// Solves the chains of a group of targets with one stacked Jacobian
+fn solve_group_stacked(
+    target_chains: &Vec<TargetChain>,
+    group: &Vec<usize>,
+    damping: Option<f32>,
+    q_bones: &mut Query<(&mut Transform, Option<&Parent>, &mut Bone)>,
+) {
+    // Bend chains towards their poles first, so that the solver converges to a pose on that side
+    for &i in group.iter() {
+        let target_chain = &target_chains[i];
+        let pole = match target_chain.pole {
+            Some(pole) => pole,
+            None => continue,
         };
+        let (chain_transforms, chain_constraints, root_gl_transform) = match get_chain(target_chain, q_bones) {
+            Some(chain) => chain,
+            None => continue,
+        };
+        let relative_pole = get_relative_transform(&root_gl_transform, &pole).translation.truncate();
+        let end = chain_transforms.len();
+        let rotations = bend_towards_pole(chain_transforms, &chain_constraints, relative_pole, end);
+        for (&bone_entity, rotation) in target_chain.bones.iter().zip(rotations) {
+            q_bones.get_mut(bone_entity).unwrap().0.rotation = rotation;
+        }
+    }
+
+    // Collect all bones of the group
+    let mut bones: Vec<Entity> = vec![];
+    for &i in group.iter() {
+        for &bone in target_chains[i].bones.iter() {
+            if !bones.contains(&bone) {
+                bones.push(bone);
+            }
+        }
+    }
+
+    let mut tree = JointTree::default();
+    for &bone_entity in bones.iter() {
+        let (transform, opt_parent, bone) = q_bones.get(bone_entity).unwrap();
+        tree.transforms.push(*transform);
+        tree.constraints.push(bone.ik_angle_constraint.clone());
+        let opt_parent_index =
+            opt_parent.and_then(|parent| bones.iter().position(|&b| b == parent.get()));
+        tree.parents.push(opt_parent_index);
+        tree.parent_gl_transforms.push(if opt_parent_index.is_some() {
+            Transform::default()
+        } else {
+            get_parent_gl_transform(bone_entity, q_bones).unwrap_or_default()
+        });
+    }
+
+    let effectors = group
+        .iter()
+        .map(|&i| {
+            let target_chain = &target_chains[i];
+            Effector {
+                joint: bones.iter().position(|&b| b == target_chain.bones[0]).unwrap(),
+                target: target_chain.transform.translation.truncate(),
+                goal_angle: if target_chain.target.match_rotation {
+                    Some(target_chain.transform.rotation.to_euler(EulerRot::XYZ).2)
+                } else {
+                    None
+                },
+                priority: target_chain.target.priority,
+            }
+        })
+        .collect::<Vec<Effector>>();
+    let eps = group
+        .iter()
+        .map(|&i| target_chains[i].target.tolerance)
+        .fold(f32::MAX, f32::min);
+    let max_it = group
+        .iter()
+        .map(|&i| target_chains[i].target.max_iterations)
+        .max()
+        .unwrap_or(0);
 
-        for i in 0..chain_entities.len() {
-            q_bones.get_mut(chain_entities[i]).unwrap().0.rotation = target_rotations[i];
+    let rotations = get_target_rotations_stacked(tree, &effectors, damping, eps, max_it);
+    for (i, &bone_entity) in bones.iter().enumerate() {
+        q_bones.get_mut(bone_entity).unwrap().0.rotation = rotations[i];
+    }
+}
+
+pub fn reach_for_target(
+    mut commands: Commands,
+    mut q_bones: Query<(&mut Transform, Option<&Parent>, &mut Bone)>,
+    q_targets: Query<(Entity, &Transform, &Target), Without<Bone>>,
+    q_poles: Query<&Transform, (With<Pole>, Without<Bone>)>,
+) {
+    // Restore the FK pose, unless the rotation was changed since the last frame, e.g. by an animation
+    for (mut transform, _, mut bone) in q_bones.iter_mut() {
+        if let Some(ik_override) = bone.ik_override.take() {
+            if transform.rotation == ik_override.ik_rotation {
+                transform.rotation = ik_override.fk_rotation;
+            }
         }
     }
+
+    // Collect chains of all active targets
+    let mut target_chains: Vec<TargetChain> = vec![];
+    for (entity, target_transform, target) in q_targets.iter() {
+        if !target.enabled || target.weight <= 0. {
+            continue;
+        }
+        match get_chain_entities(target, &q_bones) {
+            Some(bones) => target_chains.push(TargetChain {
+                target: target.clone(),
+                transform: *target_transform,
+                bones,
+                pole: target.pole.and_then(|pole| q_poles.get(pole).ok()).copied(),
+            }),
+            // If bone was removed, despawn target
+            None => commands.entity(entity).despawn(),
+        }
+    }
+
+    let mut fk_rotations: HashMap<Entity, Quat> = HashMap::new();
+    for target_chain in target_chains.iter() {
+        for &bone_entity in target_chain.bones.iter() {
+            if let Ok((transform, _, _)) = q_bones.get(bone_entity) {
+                fk_rotations.insert(bone_entity, transform.rotation);
+            }
+        }
+    }
+
+    // Chains that share bones are solved together, the method of the target with the highest priority is used
+    let chains: Vec<Vec<Entity>> = target_chains
+        .iter()
+        .map(|target_chain| target_chain.bones.clone())
+        .collect();
+    for mut group in group_overlapping_chains(&chains) {
+        group.sort_by_key(|&i| target_chains[i].target.priority);
+        let main_target = &target_chains[*group.last().unwrap()].target;
+
+        if group.len() > 1 && main_target.ik_method == IKMethod::Jacobian {
+            solve_group_stacked(&target_chains, &group, None, &mut q_bones);
+            continue;
+        }
+        if group.len() > 1 && main_target.ik_method == IKMethod::DampedLeastSquares {
+            solve_group_stacked(&target_chains, &group, Some(main_target.damping), &mut q_bones);
+            continue;
+        }
+
+        // Alternating passes, the target with the highest priority is solved last
+        let (pass_count, max_it) = if group.len() == 1 {
+            (1, main_target.max_iterations)
+        } else {
+            let pass_count = group
+                .iter()
+                .map(|&i| target_chains[i].target.max_iterations)
+                .max()
+                .unwrap_or(0);
+            (pass_count, 1)
+        };
+        for _ in 0..pass_count {
+            for &i in group.iter() {
+                let target_chain = &target_chains[i];
+                if let Some(rotations) = solve_chain(target_chain, &q_bones, max_it) {
+                    for (&bone_entity, rotation) in target_chain.bones.iter().zip(rotations) {
+                        q_bones.get_mut(bone_entity).unwrap().0.rotation = rotation;
+                    }
+                }
+            }
+        }
+    }
+
+    // Blend with the FK pose, using the highest weight of all targets moving a bone
+    for (&bone_entity, &fk_rotation) in fk_rotations.iter() {
+        let weight = target_chains
+            .iter()
+            .filter(|target_chain| target_chain.bones.contains(&bone_entity))
+            .map(|target_chain| target_chain.target.weight.min(1.))
+            .fold(0., f32::max);
+        let (mut transform, _, mut bone) = q_bones.get_mut(bone_entity).unwrap();
+        let ik_rotation = fk_rotation.slerp(transform.rotation, weight).normalize();
+        transform.rotation = ik_rotation;
+        bone.ik_override = Some(bone::IKOverride {
+            fk_rotation,
+            ik_rotation,
+        });
+    }
 }
 
 // fn get_delta_orientation_jacobian(v: Vec3) -> Vec<f32> {
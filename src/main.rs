@@ -1,27 +1,38 @@
 mod animation;
+mod autosave;
 mod bevy_image;
 mod bevy_ui;
 mod bone;
 mod inverse_kinematics;
 mod cloth;
+mod constraints;
 mod debug;
 mod egui;
+mod gpu_skinning;
+mod heat_weights;
+mod history;
 mod interpolate;
 mod mesh;
 mod mesh_gen;
 mod misc;
+mod motion_path;
+mod onion_skin;
+mod path_constraint;
+mod pose;
 mod save_load;
 mod skeleton;
 mod skin;
 mod transform;
 mod kinematic_chain;
+mod weight_editor;
+mod weight_paint;
 
 #[cfg(test)]
 #[path = "tests/assert.rs"]
 mod assert;
 
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
-use bevy::{prelude::*, render::mesh::*, sprite::Mesh2dHandle};
+use bevy::{prelude::*, render::mesh::*, sprite::{Material2dPlugin, Mesh2dHandle}};
 use bevy_common_assets::json::JsonAssetPlugin;
 use bevy_egui::EguiPlugin;
 use bevy_prototype_lyon::prelude::*;
@@ -82,6 +93,15 @@ fn main() {
     .insert_resource(egui::OpenWindows::default())
     .insert_resource(cloth::State::default())
     .insert_resource(save_load::State::default())
+    .insert_resource(onion_skin::State::default())
+    .insert_resource(motion_path::State::default())
+    .insert_resource(pose::State::default())
+    .insert_resource(path_constraint::State::default())
+    .insert_resource(history::History::default())
+    .insert_resource(autosave::State::default())
+    .insert_resource(gpu_skinning::State::default())
+    .insert_resource(weight_paint::State::default())
+    .insert_resource(weight_editor::State::default())
     // EVENTS
     .add_event::<animation::ShowKeyframeEvent>()
     .add_event::<save_load::SaveEvent>()
@@ -90,6 +110,8 @@ fn main() {
     .add_plugins(DefaultPlugins)
     .add_plugin(ShapePlugin)
     .add_plugin(EguiPlugin)
+    .add_plugin(Material2dPlugin::<gpu_skinning::SkinningMaterial>::default())
+    .add_plugin(Material2dPlugin::<weight_paint::HeatmapMaterial>::default())
     .add_plugin(JsonAssetPlugin::<save_load::CompleteJson>::new(&["anim"]))
     // LOG DIAGNOSTICS
     // .add_plugin(LogDiagnosticsPlugin::default())
@@ -97,13 +119,33 @@ fn main() {
     // STARTUP SYSTEMS
     .add_startup_system(misc::setup)
     .add_startup_system(bevy_ui::spawn_ui_elements)
+    .add_startup_system(autosave::check_for_unclean_shutdown)
+    .add_startup_system_to_stage(StartupStage::PostStartup, history::update_baseline)
     // SYSTEMS
     .add_system(misc::get_mouse_position.label("input_handling"))
     .add_system_set(bevy_ui::system_set())
     .add_system_set(egui::system_set().label("ui_action"))
     .add_system_set(skin::system_set().label("skin_systems"))
     .add_system_set(mesh::system_set().label("mesh_systems"))
+    .add_system_set(
+        motion_path::system_set()
+            .after("ui_action")
+            .before("bone_systems")
+            .before("transform_systems"),
+    )
     .add_system_set(bone::system_set().label("bone_systems").after("ui_action"))
+    .add_system_set(
+        weight_editor::system_set()
+            .label("weight_editor_systems")
+            .after("ui_action")
+            .before("transform_systems"),
+    )
+    .add_system_set(
+        weight_paint::system_set()
+            .after("ui_action")
+            .after("weight_editor_systems")
+            .before("transform_systems"),
+    )
     .add_system_set(animation::system_set().label("animation_systems"))
     .add_system_set(
         transform::system_set()
@@ -124,22 +166,71 @@ fn main() {
             .after("transform_systems")
             .after("animation_systems"),
     )
+    .add_system_set(
+        path_constraint::system_set()
+            .label("path_systems")
+            .after("ui_action")
+            .after("ccd_systems"),
+    )
+    .add_system(
+        constraints::restore_unconstrained
+            .after("transform_systems")
+            .after("animation_systems")
+            .before("ccd_systems"),
+    )
+    .add_system_set(
+        constraints::system_set()
+            .label("constraint_systems")
+            .after("ccd_systems")
+            .after("path_systems"),
+    )
+    .add_system_set(
+        gpu_skinning::system_set()
+            .label("gpu_skinning_systems")
+            .after("ccd_systems")
+            .after("path_systems")
+            .after("constraint_systems")
+            .after("animation_systems")
+            .after("update_cloth")
+            .before("mesh_systems"),
+    )
     .add_system_set(
         skeleton::system_set()
             .after("mesh_systems")
             .after("ccd_systems")
+            .after("path_systems")
+            .after("constraint_systems")
             .after("animation_systems")
+            .after("gpu_skinning_systems")
             .label("skeleton_systems"),
     )
+    .add_system_set(
+        onion_skin::system_set()
+            .after("skeleton_systems")
+            .after("ccd_systems")
+            .before("debug_systems"),
+    )
+    .add_system(
+        weight_paint::update_heatmaps
+            .after("skeleton_systems")
+            .before("debug_systems"),
+    )
     .add_system_set(
         debug::system_set()
             .after("bone_systems")
             .after("update_cloth")
             .after("ccd_systems")
+            .after("path_systems")
+            .after("constraint_systems")
             .after("skeleton_systems")
             .label("debug_systems"),
     )
-    .add_system_set(save_load::system_set());
+    .add_system_set(save_load::system_set())
+    .add_system_set(pose::system_set().after("ui_action"))
+    .add_system_set(history::system_set().after("ui_action"))
+    .add_system_to_stage(CoreStage::PostUpdate, history::update_baseline)
+    .add_system_set(autosave::system_set())
+    .add_system_to_stage(CoreStage::Last, autosave::end_session);
 
     // Only execute on Web
     #[cfg(target_arch = "wasm32")]
@@ -55,8 +55,12 @@ pub fn update_loose_skin(
     skeleton: Res<skeleton::Skeleton>,
     mut meshes: ResMut<Assets<Mesh>>,
     q: Query<(&GlobalTransform, &skin::Skin, Entity)>,
+    gpu_state: Res<gpu_skinning::State>,
 ) {
     for (gl_transform, skin, entity) in q.iter() {
+        if gpu_state.gpu_skins.contains_key(&entity) {
+            continue;
+        }
         let mut is_part_of_skeleton = false;
         for mapping in skeleton.skin_mappings.iter() {
             if mapping.skin.is_none() {
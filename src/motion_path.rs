@@ -0,0 +1,283 @@
+use crate::{
+    animation::{Animation, Animations, ShowKeyframeEvent},
+    bone::Bone,
+    inverse_kinematics::Target,
+    *,
+};
+use bevy::utils::HashMap;
+
+#[cfg(test)]
+#[path = "tests/motion_path_tests.rs"]
+mod motion_path_tests;
+
+// Maximum distance of the cursor to a keyframe point, so that it can be dragged
+const GRAB_DISTANCE: f32 = 10. / PIXELS_PER_UNIT as f32;
+const MAX_SAMPLES: usize = 2000;
+
+pub struct State {
+    pub enabled: bool,
+    pub samples_per_second: f64,
+    /// Sampled positions of the whole animation
+    pub path: Vec<Vec2>,
+    /// Position at each keyframe, index equals keyframe index
+    pub keyframe_points: Vec<Vec2>,
+    dragged_keyframe: Option<usize>,
+    /// Animations before the current drag, which is added to the history as one entry when it ends
+    animations_before_drag: Option<history::AnimationsSnapshot>,
+}
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            samples_per_second: 30.,
+            path: vec![],
+            keyframe_points: vec![],
+            dragged_keyframe: None,
+            animations_before_drag: None,
+        }
+    }
+}
+
+/// The point whose trajectory is shown
+#[derive(Clone, Copy)]
+enum PathOwner {
+    BoneTip(Entity),
+    Target(Entity),
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new()
+        .with_system(drag_keyframe_points)
+        .with_system(update_motion_path.after(drag_keyframe_points))
+}
+
+fn get_path_owner(
+    transform_state: &transform::State,
+    q_bones: &Query<(&Transform, Option<&Parent>), With<Bone>>,
+    q_targets: &Query<Entity, With<Target>>,
+) -> Option<PathOwner> {
+    let &entity = transform_state.selected_entities.iter().next()?;
+    if q_bones.get(entity).is_ok() {
+        Some(PathOwner::BoneTip(entity))
+    } else if q_targets.get(entity).is_ok() {
+        Some(PathOwner::Target(entity))
+    } else {
+        None
+    }
+}
+
+/// Returns the position of the path owner, if the animation is in the given pose
+fn get_position(
+    owner: PathOwner,
+    pose: &HashMap<Entity, Transform>,
+    q_bones: &Query<(&Transform, Option<&Parent>), With<Bone>>,
+) -> Option<Vec2> {
+    match owner {
+        PathOwner::BoneTip(bone) => {
+            let gl_transform = bone::get_bone_gl_transform_with_overrides(bone, q_bones, pose)?;
+            Some(Bone::get_tip(&GlobalTransform::from(gl_transform)))
+        }
+        PathOwner::Target(target) => pose.get(&target).map(|t| t.translation.truncate()),
+    }
+}
+
+/// Times at which the path is sampled, from the first keyframe up to the last one
+fn get_sample_times(anim: &Animation, samples_per_second: f64) -> Vec<f64> {
+    let length = anim.length();
+    let sample_count = usize::min((length * samples_per_second).ceil() as usize, MAX_SAMPLES);
+    (0..sample_count)
+        .map(|i| anim.keyframes[0] + length * i as f64 / sample_count as f64)
+        .collect()
+}
+
+/// Index of the keyframe point closest to `cursor_pos`, if it is close enough to be dragged
+fn get_grabbed_keyframe(keyframe_points: &Vec<Vec2>, cursor_pos: Vec2) -> Option<usize> {
+    let mut grabbed = None;
+    let mut shortest_distance = GRAB_DISTANCE;
+    for (i, &point) in keyframe_points.iter().enumerate() {
+        let distance = point.distance(cursor_pos);
+        if distance < shortest_distance {
+            shortest_distance = distance;
+            grabbed = Some(i);
+        }
+    }
+    grabbed
+}
+
+/// Returns the local `rotation` of a bone, turned so that its tip points towards `point`.
+///
+/// All rotations are around the z-axis, so rotating the local transform rotates the global one.
+fn rotate_tip_towards(rotation: Quat, gl_transform: &Transform, point: Vec2) -> Option<Quat> {
+    let base = gl_transform.translation.truncate();
+    let tip = Bone::get_tip_global(gl_transform);
+    if base.distance(tip) < 0.0001 || base.distance(point) < 0.0001 {
+        return None;
+    }
+    let delta_rot =
+        Quat::from_rotation_arc_2d((tip - base).normalize(), (point - base).normalize());
+    Some((delta_rot * rotation).normalize())
+}
+
+/// Changes the keyframe at `index`, so that the path owner is located at `point`.
+///
+/// Targets are moved to `point`, bones are rotated so that their tip points towards `point`.
+fn set_keyframe_point(
+    owner: PathOwner,
+    anim: &mut Animation,
+    index: usize,
+    point: Vec2,
+    q_bones: &Query<(&Transform, Option<&Parent>), With<Bone>>,
+) {
+    match owner {
+        PathOwner::BoneTip(bone) => {
+            let pose = anim.pose_at_keyframe(index);
+            let gl_transform =
+                match bone::get_bone_gl_transform_with_overrides(bone, q_bones, &pose) {
+                    Some(gl_transform) => gl_transform,
+                    None => return,
+                };
+            if let Some(comp_animation) = anim.comp_animations.get_mut(&bone) {
+                if let Some(transform) = comp_animation.transforms.get_mut(index) {
+                    if let Some(rotation) =
+                        rotate_tip_towards(transform.rotation, &gl_transform, point)
+                    {
+                        transform.rotation = rotation;
+                    }
+                }
+            }
+        }
+        PathOwner::Target(target) => {
+            if let Some(comp_animation) = anim.comp_animations.get_mut(&target) {
+                if let Some(transform) = comp_animation.transforms.get_mut(index) {
+                    transform.translation = point.extend(transform.translation.z);
+                }
+            }
+        }
+    }
+}
+
+pub fn drag_keyframe_points(
+    mut state: ResMut<State>,
+    mut transform_state: ResMut<transform::State>,
+    mut egui_state: ResMut<egui::State>,
+    mut animations: ResMut<Animations>,
+    anim_state: Res<animation::State>,
+    mut show_keyframe_evw: EventWriter<ShowKeyframeEvent>,
+    mouse: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    cursor_pos: Res<CursorPos>,
+    q_bones: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    q_targets: Query<Entity, With<Target>>,
+    mut history: ResMut<history::History>,
+) {
+    if !state.enabled {
+        state.dragged_keyframe = None;
+        state.animations_before_drag = None;
+        return;
+    }
+
+    // Start dragging the closest keyframe point
+    if state.dragged_keyframe.is_none()
+        && mouse.just_pressed(MouseButton::Left)
+        && transform_state.action == transform::Action::None
+        && !egui_state.ui_hover
+        && !keys.pressed(KeyCode::LControl)
+        && !keys.pressed(KeyCode::LAlt)
+    {
+        state.dragged_keyframe = get_grabbed_keyframe(&state.keyframe_points, cursor_pos.0);
+        if state.dragged_keyframe.is_some() {
+            state.animations_before_drag =
+                Some(history::AnimationsSnapshot::new(&animations, &anim_state));
+        }
+    }
+
+    let index = match state.dragged_keyframe {
+        Some(index) => index,
+        None => return,
+    };
+    let is_drag_finished = !mouse.pressed(MouseButton::Left);
+    if is_drag_finished {
+        state.dragged_keyframe = None;
+    }
+
+    // Prevent selecting or adding anything while dragging
+    transform_state.action = transform::Action::Done;
+
+    let owner = match get_path_owner(&transform_state, &q_bones, &q_targets) {
+        Some(owner) => owner,
+        None => return,
+    };
+    let edit_plot = egui_state.edit_plot;
+    let plot = &mut egui_state.plots[edit_plot];
+    let anim = match animations.map.get_mut(&plot.name) {
+        Some(anim) => anim,
+        None => return,
+    };
+    set_keyframe_point(owner, anim, index, cursor_pos.0, &q_bones);
+
+    if is_drag_finished {
+        if let Some(before) = state.animations_before_drag.take() {
+            let after = history::AnimationsSnapshot::new(&animations, &anim_state);
+            if after != before {
+                history.push(
+                    "Move keyframe",
+                    history::Command::Animations { before, after },
+                );
+            }
+        }
+    }
+
+    plot.selected_keyframe_index = index;
+    if !anim_state.running {
+        show_keyframe_evw.send(ShowKeyframeEvent {
+            animation_name: plot.name.clone(),
+            keyframe_index: index,
+        });
+    }
+}
+
+pub fn update_motion_path(
+    mut state: ResMut<State>,
+    transform_state: Res<transform::State>,
+    egui_state: Res<egui::State>,
+    animations: Res<Animations>,
+    q_bones: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    q_targets: Query<Entity, With<Target>>,
+) {
+    state.path.clear();
+    state.keyframe_points.clear();
+    if !state.enabled {
+        return;
+    }
+
+    let anim = match animations
+        .map
+        .get(&egui_state.plots[egui_state.edit_plot].name)
+    {
+        Some(anim) if !anim.keyframes.is_empty() => anim,
+        _ => return,
+    };
+    let owner = match get_path_owner(&transform_state, &q_bones, &q_targets) {
+        Some(owner) => owner,
+        None => return,
+    };
+
+    // Positions at keyframes
+    let keyframe_points = (0..anim.keyframes.len())
+        .map(|i| get_position(owner, &anim.pose_at_keyframe(i), &q_bones))
+        .collect::<Option<Vec<Vec2>>>();
+    state.keyframe_points = match keyframe_points {
+        Some(points) => points,
+        None => return,
+    };
+
+    // Positions in between keyframes
+    let mut path = vec![];
+    for time in get_sample_times(anim, state.samples_per_second) {
+        if let Some(point) = get_position(owner, &anim.pose_at_time(time), &q_bones) {
+            path.push(point);
+        }
+    }
+    path.push(*state.keyframe_points.last().unwrap());
+    state.path = path;
+}
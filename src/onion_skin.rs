@@ -0,0 +1,238 @@
+use crate::{animation::Animation, bone::Bone, skin::Skin, *};
+use bevy::{sprite::MaterialMesh2dBundle, utils::HashMap};
+
+#[cfg(test)]
+#[path = "tests/onion_skin_tests.rs"]
+mod onion_skin_tests;
+
+// Ghost meshes are drawn slightly behind the skins
+const GHOST_DEPTH: f32 = -0.05;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    Keyframes,
+    TimeOffsets,
+}
+impl ToString for Mode {
+    fn to_string(&self) -> String {
+        match self {
+            Mode::Keyframes => String::from("keyframes"),
+            Mode::TimeOffsets => String::from("time offsets"),
+        }
+    }
+}
+
+pub struct State {
+    pub enabled: bool,
+    pub mode: Mode,
+    pub show_bones: bool,
+    pub show_skins: bool,
+    pub count_previous: usize,
+    pub count_next: usize,
+    /// Time between two ghosts in seconds, only used in [`Mode::TimeOffsets`]
+    pub time_offset: f64,
+    pub tint_previous: [f32; 4],
+    pub tint_next: [f32; 4],
+    ghosts: Vec<Ghost>,
+}
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: Mode::Keyframes,
+            show_bones: true,
+            show_skins: true,
+            count_previous: 1,
+            count_next: 1,
+            time_offset: 0.1,
+            tint_previous: [1.0, 0.2, 0.2, 0.4],
+            tint_next: [0.2, 1.0, 0.2, 0.4],
+            ghosts: vec![],
+        }
+    }
+}
+
+/// A semi-transparent copy of a skin, reused every frame
+struct Ghost {
+    entity: Entity,
+    mesh_handle: Handle<Mesh>,
+    material_handle: Handle<ColorMaterial>,
+}
+
+#[derive(Component)]
+pub struct OnionSkinGhost;
+
+/// A pose of the animation that is drawn as onion skin
+struct GhostPose {
+    local_transforms: HashMap<Entity, Transform>,
+    color: Color,
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new().with_system(draw_onion_skins)
+}
+
+/// Returns the poses of the previous and next keyframes or time offsets relative to the selected keyframe.
+fn get_ghost_poses(state: &State, anim: &Animation, selected_keyframe_index: usize) -> Vec<GhostPose> {
+    let mut poses = vec![];
+    if selected_keyframe_index >= anim.keyframes.len() {
+        return poses;
+    }
+
+    for (count, direction, tint) in [
+        (state.count_previous, -1, state.tint_previous),
+        (state.count_next, 1, state.tint_next),
+    ] {
+        for i in 1..=count {
+            let local_transforms = match state.mode {
+                Mode::Keyframes => {
+                    let index = selected_keyframe_index as i32 + direction * i as i32;
+                    if index < 0 || index as usize >= anim.keyframes.len() {
+                        break;
+                    }
+                    anim.pose_at_keyframe(index as usize)
+                }
+                Mode::TimeOffsets => {
+                    let length = anim.length();
+                    if length == 0.0 {
+                        break;
+                    }
+                    let time = anim.keyframes[selected_keyframe_index]
+                        + direction as f64 * i as f64 * state.time_offset;
+                    anim.pose_at_time(anim.keyframes[0] + (time - anim.keyframes[0]).rem_euclid(length))
+                }
+            };
+
+            // Ghosts further away from the selected keyframe are more transparent
+            let alpha = tint[3] * (count + 1 - i) as f32 / count as f32;
+            poses.push(GhostPose {
+                local_transforms,
+                color: Color::rgba(tint[0], tint[1], tint[2], alpha),
+            });
+        }
+    }
+    poses
+}
+
+pub fn draw_onion_skins(
+    mut commands: Commands,
+    mut state: ResMut<State>,
+    mut debug_drawer: ResMut<DebugDrawer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
+    animations: Res<animation::Animations>,
+    egui_state: Res<egui::State>,
+    skeleton: Res<skeleton::Skeleton>,
+    q_bones: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    q_bone_entities: Query<(Entity, &Bone)>,
+    q_skins: Query<&Skin>,
+) {
+    let plot = &egui_state.plots[egui_state.edit_plot];
+    let poses = match animations.map.get(&plot.name) {
+        Some(anim) if state.enabled => get_ghost_poses(&state, anim, plot.selected_keyframe_index),
+        _ => vec![],
+    };
+
+    let bind_gl_transforms = skeleton::get_bind_gl_transforms(&q_bones, &q_bone_entities);
+    let mut ghost_count = 0;
+    for pose in poses.iter() {
+        // Compute global transform of each bone in this pose
+        let mut bone_gl_transforms: HashMap<Entity, Transform> = HashMap::new();
+        for (entity, _) in q_bone_entities.iter() {
+            if let Some(gl_transform) = bone::get_bone_gl_transform_with_overrides(
+                entity,
+                &q_bones,
+                &pose.local_transforms,
+            ) {
+                bone_gl_transforms.insert(entity, gl_transform);
+            }
+        }
+
+        // Draw BONES
+        if state.show_bones && debug_drawer.bone_debug_enabled {
+            for gl_transform in bone_gl_transforms.values() {
+                debug::draw_bone(&mut debug_drawer, gl_transform, pose.color);
+            }
+        }
+
+        // Draw SKINS
+        if !state.show_skins {
+            continue;
+        }
+        let skinning_transforms =
+            skeleton::get_skinning_transforms(&bone_gl_transforms, &bind_gl_transforms);
+        for skin_mapping in skeleton.skin_mappings.iter() {
+            if skin_mapping.vertex_mappings.is_empty() {
+                continue;
+            }
+            let skin = match skin_mapping.skin.map(|entity| q_skins.get(entity)) {
+                Some(Ok(skin)) => skin,
+                _ => continue,
+            };
+            let vertices = match meshes.get(&skin.mesh_handle.clone().unwrap().0) {
+                Some(mesh) => skeleton::get_skinned_vertices(
+                    skin_mapping,
+                    skin,
+                    mesh,
+                    skin.skinning_method,
+                    &skinning_transforms,
+                ),
+                None => continue,
+            };
+
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_NORMAL,
+                vec![[0., 0., 1.]; vertices.len()],
+            );
+            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, skin.uvs.clone());
+            mesh.set_indices(Some(Indices::U16(skin.indices.clone())));
+            let texture: Handle<Image> = asset_server.load(&skin.path);
+
+            // Reuse a ghost from the last frame if possible
+            if let Some(ghost) = state.ghosts.get(ghost_count) {
+                if let Some(ghost_mesh) = meshes.get_mut(&ghost.mesh_handle) {
+                    *ghost_mesh = mesh;
+                }
+                if let Some(material) = materials.get_mut(&ghost.material_handle) {
+                    if material.color != pose.color || material.texture.as_ref() != Some(&texture)
+                    {
+                        material.color = pose.color;
+                        material.texture = Some(texture);
+                    }
+                }
+            } else {
+                let mesh_handle = meshes.add(mesh);
+                let material_handle = materials.add(ColorMaterial {
+                    color: pose.color,
+                    texture: Some(texture),
+                });
+                let entity = commands
+                    .spawn_bundle(MaterialMesh2dBundle {
+                        mesh: mesh_handle.clone().into(),
+                        material: material_handle.clone(),
+                        transform: Transform::from_translation(Vec3::new(0., 0., GHOST_DEPTH)),
+                        ..default()
+                    })
+                    .insert(OnionSkinGhost)
+                    .id();
+                state.ghosts.push(Ghost {
+                    entity,
+                    mesh_handle,
+                    material_handle,
+                });
+            }
+            ghost_count += 1;
+        }
+    }
+
+    // Remove ghosts that aren't needed anymore
+    let unused_ghosts: Vec<Ghost> = state.ghosts.drain(ghost_count..).collect();
+    for ghost in unused_ghosts {
+        commands.entity(ghost.entity).despawn();
+        meshes.remove(ghost.mesh_handle);
+        materials.remove(ghost.material_handle);
+    }
+}
@@ -0,0 +1,462 @@
+use crate::{
+    animation::Animatable,
+    bone::Bone,
+    inverse_kinematics::{get_joint_positions, get_parent_gl_transform, rotate_towards_positions},
+    *,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+#[path = "tests/path_constraint_tests.rs"]
+mod path_constraint_tests;
+
+/// Number of line segments, each segment of the spline is approximated with
+const SAMPLES_PER_SEGMENT: usize = 16;
+
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum SplineType {
+    /// Cubic segments, each using four control points, where the last point is the first of the next segment
+    Bezier,
+    /// Passes through all control points
+    CatmullRom,
+}
+impl Default for SplineType {
+    fn default() -> Self {
+        Self::CatmullRom
+    }
+}
+impl SplineType {
+    pub fn all() -> impl ExactSizeIterator<Item = SplineType> {
+        [Self::Bezier, Self::CatmullRom].iter().copied()
+    }
+}
+impl ToString for SplineType {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Bezier => String::from("Bezier"),
+            Self::CatmullRom => String::from("Catmull-Rom"),
+        }
+    }
+}
+
+/// Lays the chain ending at this bone along a spline
+#[derive(Component, Clone)]
+pub struct PathConstraint {
+    pub spline_type: SplineType,
+    /// Number of bones in the chain, starting with this bone and continuing with its parents
+    pub depth: u8,
+    /// Entities with a [`PathPoint`] component
+    pub points: Vec<Entity>,
+    /// Start of the chain on the path, from 0 at the start of the path to 1, where the chain ends at the
+    /// end of the path. Can be keyed in animations. Only used if the chain starts at a root bone.
+    pub position: f32,
+}
+
+/// Marks a control point of a [`PathConstraint`]
+#[derive(Component)]
+pub struct PathPoint;
+
+#[derive(Clone, Copy)]
+pub enum Action {
+    /// Adds a path to the bone, that follows the current pose of its chain
+    Add(Entity),
+    /// Appends a control point to the path of the bone
+    AddPoint(Entity),
+    Remove(Entity),
+}
+
+#[derive(Default)]
+pub struct State {
+    pub pending_action: Option<Action>,
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new()
+        .with_system(despawn_orphaned_points)
+        // Points spawned in this frame don't exist yet, so they would count as deleted
+        .with_system(apply_pending_action.after(despawn_orphaned_points))
+        .with_system(follow_path)
+}
+
+pub fn spawn_point(commands: &mut Commands, asset_server: &AssetServer, translation: Vec3) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(translation),
+            sprite: Sprite {
+                color: COLOR_DEFAULT,
+                custom_size: Some(Vec2::new(0.2, 0.2)),
+                anchor: bevy::sprite::Anchor::Center,
+                ..Default::default()
+            },
+            texture: asset_server.load("img/circle.png"),
+            ..Default::default()
+        })
+        .insert(PathPoint)
+        .insert(Transformable {
+            is_selected: false,
+            collision_shape: transform::PhantomShape::Point,
+            ..Default::default()
+        })
+        .insert(Animatable)
+        .id()
+}
+
+pub fn apply_pending_action(
+    mut commands: Commands,
+    mut state: ResMut<State>,
+    egui_state: Res<egui::State>,
+    asset_server: Res<AssetServer>,
+    mut history: ResMut<history::History>,
+    q_bones: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    mut q_paths: Query<&mut PathConstraint>,
+    q_points: Query<&Transform, With<PathPoint>>,
+) {
+    let action = match state.pending_action.take() {
+        Some(action) => action,
+        None => return,
+    };
+
+    match action {
+        Action::Add(bone) => {
+            // Joints of the chain from root to tip
+            let mut polyline = vec![];
+            let mut next_bone = Some(bone);
+            for _ in 0..egui_state.ik_depth {
+                let entity = match next_bone {
+                    Some(entity) => entity,
+                    None => break,
+                };
+                let gl_transform = match bone::get_bone_gl_transform(entity, &q_bones) {
+                    Some(gl_transform) => gl_transform,
+                    None => break,
+                };
+                if polyline.is_empty() {
+                    polyline.push(Bone::get_tip_global(&gl_transform));
+                }
+                polyline.push(gl_transform.translation.truncate());
+                next_bone = q_bones
+                    .get(entity)
+                    .ok()
+                    .and_then(|(_, opt_parent)| opt_parent.map(|parent| parent.get()));
+            }
+            if polyline.len() < 2 {
+                println!("apply_pending_action: bone doesn't exist");
+                return;
+            }
+            polyline.reverse();
+
+            let length = get_polyline_length(&polyline);
+            let points = (0..4)
+                .map(|i| {
+                    let (_, point) = get_point_at_length(&polyline, length * i as f32 / 3.);
+                    spawn_point(&mut commands, &asset_server, point.extend(500.))
+                })
+                .collect();
+            commands.entity(bone).insert(PathConstraint {
+                spline_type: SplineType::default(),
+                depth: egui_state.ik_depth,
+                points,
+                position: 0.,
+            });
+            history.record_snapshot("Add path");
+        }
+        Action::AddPoint(bone) => {
+            let mut path = match q_paths.get_mut(bone) {
+                Ok(path) => path,
+                Err(_) => return,
+            };
+            // Continue in the direction of the last two points
+            let positions: Vec<Vec2> = path
+                .points
+                .iter()
+                .filter_map(|&point| q_points.get(point).ok())
+                .map(|transform| transform.translation.truncate())
+                .collect();
+            let translation = match positions.len() {
+                0 => return,
+                1 => positions[0] + Vec2::Y,
+                n => positions[n - 1] * 2. - positions[n - 2],
+            };
+            let point = spawn_point(&mut commands, &asset_server, translation.extend(500.));
+            path.points.push(point);
+            history.record_snapshot("Add path point");
+        }
+        Action::Remove(bone) => {
+            if q_paths.get(bone).is_err() {
+                return;
+            }
+            // Points are despawned by despawn_orphaned_points
+            commands.entity(bone).remove::<PathConstraint>();
+            history.record_snapshot("Remove path");
+        }
+    }
+}
+
+/// Despawns points, whose path was removed, and removes points, that were deleted, from their path
+pub fn despawn_orphaned_points(
+    mut commands: Commands,
+    mut q_paths: Query<&mut PathConstraint>,
+    q_points: Query<Entity, With<PathPoint>>,
+) {
+    for mut path in q_paths.iter_mut() {
+        if path.points.iter().any(|&point| !q_points.contains(point)) {
+            path.points.retain(|&point| q_points.contains(point));
+        }
+    }
+    for point in q_points.iter() {
+        if !q_paths.iter().any(|path| path.points.contains(&point)) {
+            commands.entity(point).despawn();
+        }
+    }
+}
+
+/// Returns points along the spline through `points`
+pub fn sample_spline(points: &Vec<Vec2>, spline_type: SplineType) -> Vec<Vec2> {
+    if points.len() < 2 {
+        return points.clone();
+    }
+    let mut polyline = vec![];
+    match spline_type {
+        SplineType::Bezier => {
+            // Remaining points are ignored until they form a complete segment
+            if points.len() < 4 {
+                return points.clone();
+            }
+            for segment in 0..(points.len() - 1) / 3 {
+                let p = &points[segment * 3..segment * 3 + 4];
+                for i in 0..SAMPLES_PER_SEGMENT {
+                    let t = i as f32 / SAMPLES_PER_SEGMENT as f32;
+                    let u = 1. - t;
+                    polyline.push(
+                        p[0] * u * u * u + p[1] * 3. * u * u * t + p[2] * 3. * u * t * t + p[3] * t * t * t,
+                    );
+                }
+            }
+            polyline.push(points[(points.len() - 1) / 3 * 3]);
+        }
+        SplineType::CatmullRom => {
+            for segment in 0..points.len() - 1 {
+                // First and last point are repeated
+                let p0 = points[segment.saturating_sub(1)];
+                let p1 = points[segment];
+                let p2 = points[segment + 1];
+                let p3 = points[usize::min(segment + 2, points.len() - 1)];
+                for i in 0..SAMPLES_PER_SEGMENT {
+                    let t = i as f32 / SAMPLES_PER_SEGMENT as f32;
+                    polyline.push(
+                        0.5 * (p1 * 2.
+                            + (p2 - p0) * t
+                            + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * t * t
+                            + (p1 * 3. - p0 - p2 * 3. + p3) * t * t * t),
+                    );
+                }
+            }
+            polyline.push(*points.last().unwrap());
+        }
+    }
+    polyline
+}
+
+pub fn get_polyline_length(polyline: &Vec<Vec2>) -> f32 {
+    polyline.windows(2).map(|w| w[0].distance(w[1])).sum()
+}
+
+/// Returns the index of the segment and the point at arc length `length` of `polyline`.
+///
+/// The segment with index `i` connects the points `i` and `i + 1`.
+pub fn get_point_at_length(polyline: &Vec<Vec2>, length: f32) -> (usize, Vec2) {
+    let mut remaining = length.max(0.);
+    for i in 0..polyline.len().saturating_sub(1) {
+        let segment_length = polyline[i].distance(polyline[i + 1]);
+        if remaining <= segment_length {
+            let t = if segment_length == 0. { 0. } else { remaining / segment_length };
+            return (i, polyline[i].lerp(polyline[i + 1], t));
+        }
+        remaining -= segment_length;
+    }
+    (
+        polyline.len().saturating_sub(2),
+        *polyline.last().unwrap_or(&Vec2::ZERO),
+    )
+}
+
+/// Returns the arc length of the point on `polyline`, that is closest to `point`
+pub fn get_closest_length(polyline: &Vec<Vec2>, point: Vec2) -> f32 {
+    let mut closest_length = 0.;
+    let mut shortest_distance = f32::MAX;
+    let mut length = 0.;
+    for w in polyline.windows(2) {
+        let segment = w[1] - w[0];
+        let segment_length = segment.length();
+        let t = if segment_length == 0. {
+            0.
+        } else {
+            ((point - w[0]).dot(segment) / (segment_length * segment_length)).clamp(0., 1.)
+        };
+        let distance = point.distance(w[0] + segment * t);
+        if distance < shortest_distance {
+            shortest_distance = distance;
+            closest_length = length + t * segment_length;
+        }
+        length += segment_length;
+    }
+    closest_length
+}
+
+/// Returns the joint positions of a chain with bones of the given `lengths`, that is laid along `polyline`.
+///
+/// The first joint lies at `start`, the search for the following joints starts at arc length `start_length`
+/// of the path. Each joint is the first point on the path, that lies a bone length away from the previous
+/// joint. Joints behind the end of the path continue in the direction of its last segment.
+pub fn get_path_joint_positions(
+    polyline: &Vec<Vec2>,
+    start: Vec2,
+    start_length: f32,
+    lengths: &Vec<f32>,
+) -> Vec<Vec2> {
+    let mut positions = vec![start];
+    if polyline.len() < 2 {
+        for &length in lengths.iter() {
+            positions.push(*positions.last().unwrap() + Vec2::Y * length);
+        }
+        return positions;
+    }
+    let (mut segment, mut cursor) = get_point_at_length(polyline, start_length);
+    let last_dir = (polyline[polyline.len() - 1] - polyline[polyline.len() - 2]).normalize_or_zero();
+
+    for &length in lengths.iter() {
+        let joint = *positions.last().unwrap();
+        let mut opt_next = None;
+        while segment < polyline.len() - 1 {
+            // Intersect the circle around the joint with the rest of the segment
+            let d = polyline[segment + 1] - cursor;
+            let f = cursor - joint;
+            let a = d.dot(d);
+            let b = 2. * f.dot(d);
+            let c = f.dot(f) - length * length;
+            let discriminant = b * b - 4. * a * c;
+            if a > 0. && discriminant >= 0. {
+                let t1 = (-b - discriminant.sqrt()) / (2. * a);
+                let t2 = (-b + discriminant.sqrt()) / (2. * a);
+                if let Some(t) = [t1, t2].iter().copied().find(|&t| t > 0. && t <= 1.) {
+                    cursor += d * t;
+                    opt_next = Some(cursor);
+                    break;
+                }
+            }
+            segment += 1;
+            cursor = polyline[segment];
+        }
+        positions.push(match opt_next {
+            Some(next) => next,
+            None => joint + last_dir * length,
+        });
+    }
+    positions
+}
+
+/// Returns the joint positions of a chain, that currently has its joints at `joint_positions`, laid along
+/// `polyline`.
+///
+/// A root chain is moved onto the path at `position`, see [`PathConstraint::position`]. A chain attached
+/// to a parent can't be moved, so it follows the path from the point closest to its first joint.
+pub fn lay_chain_along_path(
+    polyline: &Vec<Vec2>,
+    joint_positions: &Vec<Vec2>,
+    position: f32,
+    is_root: bool,
+) -> Vec<Vec2> {
+    let lengths: Vec<f32> = joint_positions
+        .windows(2)
+        .map(|w| w[0].distance(w[1]))
+        .collect();
+    let (start, start_length) = if is_root {
+        let slack = (get_polyline_length(polyline) - lengths.iter().sum::<f32>()).max(0.);
+        let start_length = position.clamp(0., 1.) * slack;
+        (get_point_at_length(polyline, start_length).1, start_length)
+    } else {
+        (joint_positions[0], get_closest_length(polyline, joint_positions[0]))
+    };
+    get_path_joint_positions(polyline, start, start_length, &lengths)
+}
+
+/// Rotates the bones of each chain with a [`PathConstraint`] along its path.
+///
+/// Like IK, the rotations are stored as an override of the animated pose. If the chain starts at a root
+/// bone, the root is moved onto the path as well.
+pub fn follow_path(
+    mut q_bones: Query<(&mut Transform, Option<&Parent>, &mut Bone)>,
+    q_paths: Query<(Entity, &PathConstraint)>,
+    q_points: Query<&Transform, (With<PathPoint>, Without<Bone>)>,
+) {
+    for (bone_entity, path) in q_paths.iter() {
+        // Construct chain from leaf to root
+        let mut bones = vec![];
+        let mut next_bone = Some(bone_entity);
+        for _ in 0..path.depth {
+            let entity = match next_bone {
+                Some(entity) => entity,
+                None => break,
+            };
+            let (_, opt_parent, _) = match q_bones.get(entity) {
+                Ok(bone) => bone,
+                Err(_) => break,
+            };
+            bones.push(entity);
+            next_bone = opt_parent.map(|parent| parent.get());
+        }
+        let root = match bones.last() {
+            Some(&root) => root,
+            None => continue,
+        };
+        let root_gl_transform = match get_parent_gl_transform(root, &q_bones) {
+            Some(gl_transform) => gl_transform,
+            None => continue,
+        };
+        let mut chain: Vec<Transform> = bones.iter().map(|&bone| *q_bones.get(bone).unwrap().0).collect();
+        let constraints = bones
+            .iter()
+            .map(|&bone| q_bones.get(bone).unwrap().2.ik_angle_constraint.clone())
+            .collect();
+
+        // Sample the spline relative to the root of the chain
+        let points = path
+            .points
+            .iter()
+            .filter_map(|&point| q_points.get(point).ok())
+            .map(|transform| {
+                get_relative_transform(&root_gl_transform, transform)
+                    .translation
+                    .truncate()
+            })
+            .collect();
+        let polyline = sample_spline(&points, path.spline_type);
+        if polyline.len() < 2 {
+            continue;
+        }
+
+        let is_root = q_bones.get(root).unwrap().1.is_none();
+        let positions =
+            lay_chain_along_path(&polyline, &get_joint_positions(&chain), path.position, is_root);
+        if is_root {
+            let chain_len = chain.len();
+            chain[chain_len - 1].translation.x = positions[0].x;
+            chain[chain_len - 1].translation.y = positions[0].y;
+            q_bones.get_mut(root).unwrap().0.translation = chain[chain_len - 1].translation;
+        }
+        rotate_towards_positions(&mut chain, &positions, &constraints);
+
+        for (&entity, transform) in bones.iter().zip(chain.iter()) {
+            let (mut bone_transform, _, mut bone) = q_bones.get_mut(entity).unwrap();
+            // Keep the original FK rotation, if IK already moved this bone
+            let fk_rotation = bone
+                .ik_override
+                .map_or(bone_transform.rotation, |ik_override| ik_override.fk_rotation);
+            bone_transform.rotation = transform.rotation;
+            bone.ik_override = Some(bone::IKOverride {
+                fk_rotation,
+                ik_rotation: transform.rotation,
+            });
+        }
+    }
+}
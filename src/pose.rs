@@ -0,0 +1,273 @@
+use crate::{
+    animation::{Animations, ComponentAnimation, ShowKeyframeEvent},
+    bone::Bone,
+    *,
+};
+
+#[cfg(test)]
+#[path = "tests/pose_tests.rs"]
+mod pose_tests;
+
+// Pairs of words that mark the two sides of a symmetric skeleton, e.g. `left_arm` or `upperArmLeft`
+const SIDE_WORDS: [(&str, &str); 3] = [("left", "right"), ("Left", "Right"), ("LEFT", "RIGHT")];
+// Pairs of letters that mark the two sides as prefix or suffix, e.g. `l_arm` or `arm.L`
+const SIDE_LETTERS: [(char, char); 2] = [('l', 'r'), ('L', 'R')];
+const SEPARATORS: [char; 4] = ['_', '.', '-', ' '];
+
+/// Axis across which a pose is mirrored
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum MirrorAxis {
+    X,
+    Y,
+}
+impl MirrorAxis {
+    pub fn all() -> impl ExactSizeIterator<Item = MirrorAxis> {
+        [Self::X, Self::Y].iter().copied()
+    }
+}
+impl ToString for MirrorAxis {
+    fn to_string(&self) -> String {
+        match self {
+            MirrorAxis::X => String::from("x-axis"),
+            MirrorAxis::Y => String::from("y-axis"),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    Copy,
+    Paste,
+    PasteMirrored,
+}
+
+struct CopiedBone {
+    entity: Entity,
+    name: String,
+    transform: Transform,
+    is_root: bool,
+}
+
+pub struct State {
+    pub mirror_axis: MirrorAxis,
+    pub pending_action: Option<Action>,
+    clipboard: Vec<CopiedBone>,
+}
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            mirror_axis: MirrorAxis::Y,
+            pending_action: None,
+            clipboard: vec![],
+        }
+    }
+}
+impl State {
+    pub fn clipboard_len(&self) -> usize {
+        self.clipboard.len()
+    }
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new()
+        .with_system(copy_paste_on_key_press)
+        .with_system(apply_pending_action.after(copy_paste_on_key_press))
+}
+
+/// Returns the name of the bone on the opposite side, e.g. `right_arm` for `left_arm`. Side words and letters
+/// only count at a name boundary, so `tail_base` or `cleft` have no counterpart.
+pub fn mirrored_name(name: &str) -> Option<String> {
+    for (left, right) in SIDE_WORDS {
+        for (word, other) in [(left, right), (right, left)] {
+            if let Some((start, _)) = name
+                .match_indices(word)
+                .find(|&(start, _)| is_whole_word(name, start, word))
+            {
+                return Some(format!("{}{}{}", &name[..start], other, &name[start + word.len()..]));
+            }
+        }
+    }
+
+    let chars: Vec<char> = name.chars().collect();
+    if chars.len() < 2 {
+        return None;
+    }
+    let swap_letter = |c: char| {
+        SIDE_LETTERS.iter().find_map(|&(left, right)| {
+            if c == left {
+                Some(right)
+            } else if c == right {
+                Some(left)
+            } else {
+                None
+            }
+        })
+    };
+    // Prefix, e.g. `l_arm`
+    if let (Some(other), true) = (swap_letter(chars[0]), SEPARATORS.contains(&chars[1])) {
+        return Some(std::iter::once(other).chain(chars[1..].iter().copied()).collect());
+    }
+    // Suffix, e.g. `arm.L`
+    let last = chars.len() - 1;
+    if let (Some(other), true) = (swap_letter(chars[last]), SEPARATORS.contains(&chars[last - 1])) {
+        return Some(chars[..last].iter().copied().chain(std::iter::once(other)).collect());
+    }
+    None
+}
+
+/// Whether `word` at byte `start` of `name` is separated from the rest of the name, by a non-letter or a change
+/// of case like in `upperArmLeft`
+fn is_whole_word(name: &str, start: usize, word: &str) -> bool {
+    let is_upper_word = word.chars().all(|c| c.is_uppercase());
+    let starts_upper = word.starts_with(char::is_uppercase);
+    let is_start_ok = match name[..start].chars().next_back() {
+        Some(c) => !c.is_alphabetic() || (starts_upper && c.is_lowercase()),
+        None => true,
+    };
+    let is_end_ok = match name[start + word.len()..].chars().next() {
+        Some(c) => !c.is_alphabetic() || (!is_upper_word && c.is_uppercase()),
+        None => true,
+    };
+    is_start_ok && is_end_ok
+}
+
+/// Mirrors a local bone transform.
+///
+/// Only root bones are located in world space. All other bones are mirrored relative to their
+/// parent, which is mirrored as well, so their rotations are negated and they are flipped along
+/// the parent's x-axis.
+pub fn mirror_transform(transform: &Transform, axis: MirrorAxis, is_root: bool) -> Transform {
+    let angle = transform.rotation.to_euler(EulerRot::XYZ).2;
+    let mut res = *transform;
+    if is_root && axis == MirrorAxis::X {
+        res.rotation = Quat::from_rotation_z(std::f32::consts::PI - angle);
+        res.translation.y = -transform.translation.y;
+    } else {
+        res.rotation = Quat::from_rotation_z(-angle);
+        res.translation.x = -transform.translation.x;
+    }
+    res
+}
+
+pub fn copy_paste_on_key_press(
+    keys: Res<Input<KeyCode>>,
+    egui_state: Res<egui::State>,
+    mut state: ResMut<State>,
+) {
+    if !keys.pressed(KeyCode::LControl) || egui_state.ui_hover {
+        return;
+    }
+    if keys.just_pressed(KeyCode::C) {
+        state.pending_action = Some(Action::Copy);
+    } else if keys.just_pressed(KeyCode::V) {
+        state.pending_action = Some(if keys.pressed(KeyCode::LShift) {
+            Action::PasteMirrored
+        } else {
+            Action::Paste
+        });
+    }
+}
+
+/// Copies the pose of the selected bones, or pastes the copied pose into the selected keyframe.
+///
+/// If the current animation has no keyframes, the pose is applied to the bones directly.
+pub fn apply_pending_action(
+    mut state: ResMut<State>,
+    transform_state: Res<transform::State>,
+    egui_state: Res<egui::State>,
+    mut animations: ResMut<Animations>,
+    anim_state: Res<animation::State>,
+    mut history: ResMut<history::History>,
+    mut show_keyframe_evw: EventWriter<ShowKeyframeEvent>,
+    mut q_bones: Query<(Entity, &Bone, &mut Transform, Option<&Parent>)>,
+) {
+    let action = match state.pending_action.take() {
+        Some(action) => action,
+        None => return,
+    };
+
+    if action == Action::Copy {
+        state.clipboard = q_bones
+            .iter()
+            .filter(|(entity, _, _, _)| transform_state.selected_entities.contains(entity))
+            .map(|(entity, bone, transform, opt_parent)| CopiedBone {
+                entity,
+                name: bone.name.clone(),
+                transform: *transform,
+                is_root: opt_parent.is_none(),
+            })
+            .collect();
+        return;
+    }
+
+    // Find target bone and transform of each copied bone
+    let mut pasted: Vec<(Entity, Transform)> = vec![];
+    for copied in state.clipboard.iter() {
+        if action == Action::Paste {
+            pasted.push((copied.entity, copied.transform));
+            continue;
+        }
+        // Bones without a counterpart, e.g. the spine, are mirrored onto themselves
+        let opt_mirrored_name = mirrored_name(&copied.name);
+        let entity = q_bones
+            .iter()
+            .find(|(_, bone, _, _)| Some(&bone.name) == opt_mirrored_name.as_ref())
+            .map_or(copied.entity, |(entity, _, _, _)| entity);
+        pasted.push((
+            entity,
+            mirror_transform(&copied.transform, state.mirror_axis, copied.is_root),
+        ));
+    }
+
+    let plot = &egui_state.plots[egui_state.edit_plot];
+    let keyframe_index = plot.selected_keyframe_index;
+    let before = history::AnimationsSnapshot::new(&animations, &anim_state);
+    match animations.map.get_mut(&plot.name) {
+        Some(anim) if keyframe_index < anim.keyframes.len() => {
+            for &(entity, transform) in pasted.iter() {
+                let current_transform = match q_bones.get(entity) {
+                    Ok((_, _, &current_transform, _)) => current_transform,
+                    Err(_) => continue,
+                };
+                let comp_animation = anim
+                    .comp_animations
+                    .entry(entity)
+                    .or_insert_with(ComponentAnimation::default);
+                // Fill any missing keyframes with current pose
+                while comp_animation.transforms.len() < anim.keyframes.len() {
+                    comp_animation.transforms.push(current_transform);
+                    comp_animation
+                        .interpolation_functions
+                        .push(egui_state.interpolation_function);
+                }
+                comp_animation.transforms[keyframe_index] = transform;
+            }
+        }
+        _ => {
+            for &(entity, transform) in pasted.iter() {
+                if let Ok((_, _, mut current_transform, _)) = q_bones.get_mut(entity) {
+                    *current_transform = transform;
+                }
+            }
+            return;
+        }
+    }
+
+    let after = history::AnimationsSnapshot::new(&animations, &anim_state);
+    if after != before {
+        history.push(
+            if action == Action::Paste {
+                "Paste pose"
+            } else {
+                "Paste mirrored pose"
+            },
+            history::Command::Animations { before, after },
+        );
+    }
+    if !anim_state.running {
+        show_keyframe_evw.send(ShowKeyframeEvent {
+            animation_name: plot.name.clone(),
+            keyframe_index,
+        });
+    }
+}
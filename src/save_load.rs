@@ -1,8 +1,10 @@
 use crate::animation::{Animatable, Animation, Animations, ComponentAnimation};
-use crate::bone::Bone;
+use crate::bone::{AngleConstraint, Bone};
 use crate::cloth::Cloth;
-use crate::inverse_kinematics::{IKMethod, Target};
-use crate::skeleton::{Skeleton, SkinMapping};
+use crate::constraints::{Constraint, ConstraintStack};
+use crate::inverse_kinematics::{BendDirection, IKMethod, Pole, Target};
+use crate::path_constraint::{PathConstraint, PathPoint, SplineType};
+use crate::skeleton::{Skeleton, SkinMapping, SkinningMethod};
 use crate::skin::Skin;
 use crate::*;
 use bevy::sprite::MaterialMesh2dBundle;
@@ -12,6 +14,10 @@ use std::fs;
 use std::io::Write;
 use wasm_bindgen::JsValue;
 
+#[cfg(test)]
+#[path = "tests/save_load_tests.rs"]
+mod save_load_tests;
+
 #[derive(Default)]
 pub struct State {
     pub opt_load_path: Option<String>,
@@ -27,9 +33,53 @@ pub struct CompleteJson {
     blending_style: animation::BlendingStyle,
 }
 
+impl CompleteJson {
+    /// Replace all entities, according to `entity_map`
+    pub fn remap(&mut self, entity_map: &HashMap<Entity, Entity>) {
+        let remap_entity = |entity: Entity| *entity_map.get(&entity).unwrap_or(&entity);
+        for bone in self.skeleton.bones.iter_mut() {
+            bone.entity = remap_entity(bone.entity);
+            bone.parent = bone.parent.map(remap_entity);
+            for constraint in bone.constraints.iter_mut() {
+                constraint.target = constraint.target.map(remap_entity);
+            }
+        }
+        for skin in self.skeleton.skins.iter_mut() {
+            skin.entity = remap_entity(skin.entity);
+        }
+        for target in self.skeleton.targets.iter_mut() {
+            target.entity = remap_entity(target.entity);
+            target.bone = remap_entity(target.bone);
+            if let Some(pole) = target.pole.as_mut() {
+                pole.entity = remap_entity(pole.entity);
+            }
+        }
+        for path in self.skeleton.paths.iter_mut() {
+            path.bone = remap_entity(path.bone);
+            for point in path.points.iter_mut() {
+                point.entity = remap_entity(point.entity);
+            }
+        }
+        for skin_mapping in self.skeleton.skin_mappings.iter_mut() {
+            skin_mapping.remap(entity_map);
+        }
+        for anim in self.animations.map.values_mut() {
+            anim.comp_animations = anim
+                .comp_animations
+                .drain()
+                .map(|(entity, comp_animation)| (remap_entity(entity), comp_animation))
+                .collect();
+        }
+    }
+}
+
 pub struct SaveEvent(pub String);
 
-pub struct LoadEvent(CompleteJson);
+pub struct LoadEvent {
+    pub data: CompleteJson,
+    /// Loads caused by undo/redo must not be recorded in the history again
+    pub from_history: bool,
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct AnimationsJson {
@@ -105,6 +155,10 @@ struct ComponentAnimationJson {
     scales: Vec<Vec3>,
     rotations: Vec<Quat>,
     interpolation_functions: Vec<interpolate::Function>,
+    #[serde(default)]
+    ik_weights: Vec<f32>,
+    #[serde(default)]
+    path_positions: Vec<f32>,
 }
 impl ComponentAnimationJson {
     fn from_component_animation(comp_anim: &ComponentAnimation) -> Self {
@@ -121,6 +175,8 @@ impl ComponentAnimationJson {
             scales,
             rotations,
             interpolation_functions: comp_anim.interpolation_functions.clone(),
+            ik_weights: comp_anim.ik_weights.clone(),
+            path_positions: comp_anim.path_positions.clone(),
         }
     }
     fn as_component_animation(&self) -> ComponentAnimation {
@@ -135,6 +191,8 @@ impl ComponentAnimationJson {
         ComponentAnimation {
             transforms,
             interpolation_functions: self.interpolation_functions.clone(),
+            ik_weights: self.ik_weights.clone(),
+            path_positions: self.path_positions.clone(),
         }
     }
 }
@@ -145,6 +203,77 @@ struct SkeletonJson {
     skins: Vec<SkinJson>,
     targets: Vec<TargetJson>,
     skin_mappings: Vec<SkinMapping>,
+    #[serde(default)]
+    paths: Vec<PathJson>,
+}
+impl SkeletonJson {
+    /// Older saves store vertex positions relative to each bone instead of bind vertices. The pose they were
+    /// saved in becomes the bind pose of bones without one.
+    fn add_missing_bind_vertices(&mut self) {
+        if self
+            .skin_mappings
+            .iter()
+            .all(|mapping| mapping.vertex_mappings.is_empty() || !mapping.bind_vertices.is_empty())
+        {
+            return;
+        }
+        for bone in self.bones.iter_mut() {
+            if bone.bind_pose.is_none() {
+                bone.bind_pose = Some(BindPoseJson {
+                    translation: bone.translation,
+                    rotation: bone.rotation,
+                    scale: bone.scale,
+                });
+            }
+        }
+        let bind_gl_transforms: HashMap<Entity, Transform> = self
+            .bones
+            .iter()
+            .filter_map(|bone| {
+                self.get_bind_gl_transform(bone.entity)
+                    .map(|gl_transform| (bone.entity, gl_transform))
+            })
+            .collect();
+
+        for skin_mapping in self.skin_mappings.iter_mut() {
+            if !skin_mapping.bind_vertices.is_empty() {
+                continue;
+            }
+            skin_mapping.bind_vertices = skin_mapping
+                .vertex_mappings
+                .iter()
+                .map(|mapping| {
+                    let total_weight: f32 = mapping.weights.iter().sum();
+                    let mut bind_vertex = Vec2::ZERO;
+                    if total_weight == 0. {
+                        return bind_vertex;
+                    }
+                    for i in 0..mapping.bones.len() {
+                        if let Some(gl_transform) = bind_gl_transforms.get(&mapping.bones[i]) {
+                            let vertex_rel_transform =
+                                Transform::from_translation(mapping.rel_positions[i].extend(0.));
+                            bind_vertex += mapping.weights[i] / total_weight
+                                * combined_transform(gl_transform, &vertex_rel_transform)
+                                    .translation
+                                    .truncate();
+                        }
+                    }
+                    bind_vertex
+                })
+                .collect();
+        }
+    }
+    fn get_bind_gl_transform(&self, entity: Entity) -> Option<Transform> {
+        let mut gl_transform = Transform::default();
+        let mut next_bone = Some(entity);
+        while let Some(bone_entity) = next_bone {
+            let bone = self.bones.iter().find(|bone| bone.entity == bone_entity)?;
+            let bind_pose = bone.bind_pose.map(Transform::from)?;
+            gl_transform = combined_transform(&bind_pose, &gl_transform);
+            next_bone = bone.parent;
+        }
+        Some(gl_transform)
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -153,10 +282,22 @@ struct ID(Entity);
 #[derive(Serialize, Deserialize, Clone)]
 struct BoneJson {
     entity: Entity,
+    #[serde(default)]
+    name: String,
     parent: Option<Entity>,
     translation: Vec3,
     scale: Vec3,
     rotation: Quat,
+    #[serde(default)]
+    constraints: Vec<Constraint>,
+    #[serde(default)]
+    angle_constraint: AngleConstraint,
+    #[serde(default)]
+    is_hidden: bool,
+    #[serde(default)]
+    is_locked: bool,
+    #[serde(default)]
+    bind_pose: Option<BindPoseJson>,
 }
 impl PartialEq for BoneJson {
     fn eq(&self, other: &Self) -> bool {
@@ -164,6 +305,31 @@ impl PartialEq for BoneJson {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct BindPoseJson {
+    translation: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+}
+impl From<Transform> for BindPoseJson {
+    fn from(transform: Transform) -> Self {
+        Self {
+            translation: transform.translation,
+            rotation: transform.rotation,
+            scale: transform.scale,
+        }
+    }
+}
+impl From<BindPoseJson> for Transform {
+    fn from(bind_pose: BindPoseJson) -> Self {
+        Transform {
+            translation: bind_pose.translation,
+            rotation: bind_pose.rotation,
+            scale: bind_pose.scale,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SkinJson {
     entity: Entity,
@@ -173,6 +339,8 @@ pub struct SkinJson {
     indices: Vec<u16>,
     depth: f32,
     cloth: Option<Cloth>,
+    #[serde(default)]
+    skinning_method: SkinningMethod,
 }
 impl SkinJson {
     fn as_skin(&self) -> Skin {
@@ -182,6 +350,7 @@ impl SkinJson {
             uvs: self.uvs.clone(),
             indices: self.indices.clone(),
             mesh_handle: None,
+            skinning_method: self.skinning_method,
         }
     }
 }
@@ -193,6 +362,68 @@ struct TargetJson {
     bone: Entity,
     depth: u8,
     translation: Vec3,
+    #[serde(default)]
+    bend_direction: BendDirection,
+    #[serde(default = "default_soft_limit")]
+    soft_limit: f32,
+    #[serde(default = "default_tolerance")]
+    tolerance: f32,
+    #[serde(default = "default_max_iterations")]
+    max_iterations: usize,
+    #[serde(default = "default_damping")]
+    damping: f32,
+    #[serde(default = "default_weight")]
+    weight: f32,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    #[serde(default)]
+    match_rotation: bool,
+    #[serde(default)]
+    rotation: Quat,
+    #[serde(default)]
+    priority: u8,
+    #[serde(default)]
+    pole: Option<PoleJson>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct PoleJson {
+    entity: Entity,
+    translation: Vec3,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct PathJson {
+    bone: Entity,
+    spline_type: SplineType,
+    depth: u8,
+    position: f32,
+    points: Vec<PathPointJson>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct PathPointJson {
+    entity: Entity,
+    translation: Vec3,
+}
+
+fn default_soft_limit() -> f32 {
+    inverse_kinematics::DEFAULT_SOFT_LIMIT
+}
+fn default_tolerance() -> f32 {
+    inverse_kinematics::DEFAULT_TOLERANCE
+}
+fn default_max_iterations() -> usize {
+    10
+}
+fn default_damping() -> f32 {
+    inverse_kinematics::DEFAULT_DAMPING
+}
+fn default_weight() -> f32 {
+    1.
+}
+fn default_enabled() -> bool {
+    true
 }
 
 pub fn system_set() -> SystemSet {
@@ -215,11 +446,13 @@ fn call_save_event(keys: Res<Input<KeyCode>>, mut save_evw: EventWriter<SaveEven
 }
 
 fn save(
-    mut set: ParamSet<(
-        Query<(Entity, &Transform, Option<&Parent>), With<Bone>>,
-        Query<(Entity, &Skin, Option<&Cloth>)>,
-        Query<(Entity, &Target, &Transform)>,
-    )>,
+    q_bones: Query<(Entity, &Bone, &Transform, Option<&Parent>, &Transformable)>,
+    q_skins: Query<(Entity, &Skin, Option<&Cloth>)>,
+    q_targets: Query<(Entity, &Target, &Transform)>,
+    q_poles: Query<&Transform, With<Pole>>,
+    q_paths: Query<(Entity, &PathConstraint)>,
+    q_path_points: Query<&Transform, With<PathPoint>>,
+    q_constraints: Query<&ConstraintStack>,
     animations: Res<Animations>,
     anim_state: Res<animation::State>,
     skeleton: Res<Skeleton>,
@@ -227,65 +460,134 @@ fn save(
 ) {
     for e in save_evr.iter() {
         let filename = e.0.clone();
-        let bones = set
-            .p0()
-            .iter()
-            .map(|(entity, transform, opt_parent)| BoneJson {
-                entity,
-                parent: if let Some(parent) = opt_parent {
-                    Some(parent.get())
-                } else {
-                    None
-                },
-                translation: transform.translation,
-                scale: transform.scale,
-                rotation: transform.rotation,
-            })
-            .collect::<Vec<BoneJson>>();
-        let skins = set
-            .p1()
-            .iter()
-            .map(|(entity, skin, opt_cloth)| SkinJson {
-                entity,
-                filename: skin.path.clone(),
-                uvs: skin.uvs.clone(),
-                vertices: skin.vertices.clone(),
-                indices: skin.indices.clone(),
-                depth: 0.,
-                cloth: if let Some(cloth) = opt_cloth {
-                    Some(cloth.clone())
-                } else {
-                    None
-                },
-            })
-            .collect::<Vec<SkinJson>>();
-        let targets = set
-            .p2()
-            .iter()
-            .map(|(entity, target, transform)| TargetJson {
-                entity,
-                opt_ik_method: Some(target.ik_method),
-                bone: target.bone,
-                depth: target.depth,
-                translation: transform.translation,
-            })
-            .collect::<Vec<TargetJson>>();
-        let serialized = serde_json::to_string(&CompleteJson {
-            skeleton: SkeletonJson {
-                bones,
-                skins,
-                targets,
-                skin_mappings: skeleton.skin_mappings.clone(),
-            },
-            animations: AnimationsJson::from_animations(&animations),
-            animation_layers: anim_state.layers.clone(),
-            blending_style: anim_state.blending_style,
-        })
+        let serialized = serde_json::to_string(&build_complete_json(
+            &q_bones,
+            &q_skins,
+            &q_targets,
+            &q_poles,
+            &q_paths,
+            &q_path_points,
+            &q_constraints,
+            &animations,
+            &anim_state,
+            &skeleton,
+        ))
         .unwrap();
         save_to_file(&serialized, filename);
     }
 }
 
+/// Collects the current state of the editor, i.e. everything that is written to a save file
+pub fn build_complete_json(
+    q_bones: &Query<(Entity, &Bone, &Transform, Option<&Parent>, &Transformable)>,
+    q_skins: &Query<(Entity, &Skin, Option<&Cloth>)>,
+    q_targets: &Query<(Entity, &Target, &Transform)>,
+    q_poles: &Query<&Transform, With<Pole>>,
+    q_paths: &Query<(Entity, &PathConstraint)>,
+    q_path_points: &Query<&Transform, With<PathPoint>>,
+    q_constraints: &Query<&ConstraintStack>,
+    animations: &Animations,
+    anim_state: &animation::State,
+    skeleton: &Skeleton,
+) -> CompleteJson {
+    let bones = q_bones
+        .iter()
+        .map(|(entity, bone, transform, opt_parent, transformable)| BoneJson {
+            entity,
+            name: bone.name.clone(),
+            parent: if let Some(parent) = opt_parent {
+                Some(parent.get())
+            } else {
+                None
+            },
+            translation: transform.translation,
+            scale: transform.scale,
+            rotation: transform.rotation,
+            constraints: q_constraints
+                .get(entity)
+                .map_or(vec![], |stack| stack.constraints.clone()),
+            angle_constraint: bone.ik_angle_constraint.clone().unwrap_or_default(),
+            is_hidden: transformable.is_hidden,
+            is_locked: transformable.is_locked,
+            bind_pose: bone.bind_pose.map(BindPoseJson::from),
+        })
+        .collect::<Vec<BoneJson>>();
+    let skins = q_skins
+        .iter()
+        .map(|(entity, skin, opt_cloth)| SkinJson {
+            entity,
+            filename: skin.path.clone(),
+            uvs: skin.uvs.clone(),
+            vertices: skin.vertices.clone(),
+            indices: skin.indices.clone(),
+            depth: 0.,
+            cloth: if let Some(cloth) = opt_cloth {
+                Some(cloth.clone())
+            } else {
+                None
+            },
+            skinning_method: skin.skinning_method,
+        })
+        .collect::<Vec<SkinJson>>();
+    let targets = q_targets
+        .iter()
+        .map(|(entity, target, transform)| TargetJson {
+            entity,
+            opt_ik_method: Some(target.ik_method),
+            bone: target.bone,
+            depth: target.depth,
+            translation: transform.translation,
+            bend_direction: target.bend_direction,
+            soft_limit: target.soft_limit,
+            tolerance: target.tolerance,
+            max_iterations: target.max_iterations,
+            damping: target.damping,
+            weight: target.weight,
+            enabled: target.enabled,
+            match_rotation: target.match_rotation,
+            rotation: transform.rotation,
+            priority: target.priority,
+            pole: target.pole.and_then(|pole| {
+                q_poles.get(pole).ok().map(|transform| PoleJson {
+                    entity: pole,
+                    translation: transform.translation,
+                })
+            }),
+        })
+        .collect::<Vec<TargetJson>>();
+    let paths = q_paths
+        .iter()
+        .map(|(bone, path)| PathJson {
+            bone,
+            spline_type: path.spline_type,
+            depth: path.depth,
+            position: path.position,
+            points: path
+                .points
+                .iter()
+                .filter_map(|&point| {
+                    q_path_points.get(point).ok().map(|transform| PathPointJson {
+                        entity: point,
+                        translation: transform.translation,
+                    })
+                })
+                .collect(),
+        })
+        .collect::<Vec<PathJson>>();
+    CompleteJson {
+        skeleton: SkeletonJson {
+            bones,
+            skins,
+            targets,
+            skin_mappings: skeleton.skin_mappings.clone(),
+            paths,
+        },
+        animations: AnimationsJson::from_animations(animations),
+        animation_layers: anim_state.layers.clone(),
+        blending_style: anim_state.blending_style,
+    }
+}
+
 fn save_to_file(serialized: &str, filename: String) {
     #[cfg(not(target_arch = "wasm32"))]
     {
@@ -333,9 +635,16 @@ fn load_bone_recursive_no_parent(
             visibility: Visibility { is_visible: false },
             ..Default::default()
         })
-        .insert(Bone::default())
+        .insert(Bone {
+            name: current_bone.name.clone(),
+            ik_angle_constraint: Some(current_bone.angle_constraint.clone()),
+            bind_pose: current_bone.bind_pose.map(Transform::from),
+            ..Default::default()
+        })
         .insert(Transformable {
             is_selected: false,
+            is_hidden: current_bone.is_hidden,
+            is_locked: current_bone.is_locked,
             ..Default::default()
         })
         .insert(Animatable)
@@ -368,9 +677,16 @@ fn load_bone_recursive_with_parent(
             visibility: Visibility { is_visible: false },
             ..Default::default()
         })
-        .insert(Bone::default())
+        .insert(Bone {
+            name: current_bone.name.clone(),
+            ik_angle_constraint: Some(current_bone.angle_constraint.clone()),
+            bind_pose: current_bone.bind_pose.map(Transform::from),
+            ..Default::default()
+        })
         .insert(Transformable {
             is_selected: false,
+            is_hidden: current_bone.is_hidden,
+            is_locked: current_bone.is_locked,
             ..Default::default()
         })
         .insert(Animatable)
@@ -404,7 +720,10 @@ fn call_load_event(
             let opt_data = savefile_assets.get(&anim_handle);
 
             if let Some(data) = opt_data {
-                load_evw.send(LoadEvent(data.clone()));
+                load_evw.send(LoadEvent {
+                    data: data.clone(),
+                    from_history: false,
+                });
                 state.opt_load_path = None
             }
         }
@@ -418,7 +737,10 @@ fn call_load_event(
                 state.load_count = count_i32;
                 let data_string = local_storage.get("loaded_anim").unwrap().unwrap();
                 let data = serde_json::from_str::<CompleteJson>(&data_string).unwrap();
-                load_evw.send(LoadEvent(data));
+                load_evw.send(LoadEvent {
+                    data,
+                    from_history: false,
+                });
             }
         }
     }
@@ -433,6 +755,8 @@ fn load(
         Query<Entity, With<Bone>>,
         Query<(Entity, &skin::Skin)>,
         Query<(Entity, &Target)>,
+        Query<Entity, With<Pole>>,
+        Query<Entity, With<PathPoint>>,
     )>,
     mut commands: Commands,
     mut animations: ResMut<Animations>,
@@ -440,9 +764,11 @@ fn load(
     mut egui_state: ResMut<egui::State>,
     mut anim_state: ResMut<animation::State>,
     mut load_evr: EventReader<LoadEvent>,
+    mut history: ResMut<history::History>,
 ) {
     for e in load_evr.iter() {
-        let mut data = e.0.clone();
+        let mut data = e.data.clone();
+        data.skeleton.add_missing_bind_vertices();
 
         for entity in q.p0().iter() {
             commands.entity(entity).despawn();
@@ -454,6 +780,12 @@ fn load(
         for (entity, target) in q.p2().iter() {
             commands.entity(entity).despawn();
         }
+        for entity in q.p3().iter() {
+            commands.entity(entity).despawn();
+        }
+        for entity in q.p4().iter() {
+            commands.entity(entity).despawn();
+        }
 
         // Json ID to spawned entity ID, necessary because Game Engines assigns IDs automatically
         let mut spawned_entities: HashMap<Entity, Entity> = HashMap::new();
@@ -513,9 +845,16 @@ fn load(
         // Spawn Targets
         for i in 0..data.skeleton.targets.len() {
             let target = data.skeleton.targets[i].clone();
+            let opt_pole_entity = target.pole.as_ref().map(|pole| {
+                let pole_entity =
+                    inverse_kinematics::spawn_pole(&mut commands, &asset_server, pole.translation);
+                spawned_entities.insert(pole.entity, pole_entity);
+                pole_entity
+            });
             let target_entity = commands
                 .spawn_bundle(SpriteBundle {
-                    transform: Transform::from_translation(target.translation),
+                    transform: Transform::from_translation(target.translation)
+                        .with_rotation(target.rotation),
                     sprite: Sprite {
                         color: COLOR_DEFAULT,
                         custom_size: Some(Vec2::new(0.4, 0.4)),
@@ -532,6 +871,16 @@ fn load(
                     },
                     bone: *spawned_entities.get(&target.bone).unwrap(),
                     depth: target.depth,
+                    bend_direction: target.bend_direction,
+                    soft_limit: target.soft_limit,
+                    tolerance: target.tolerance,
+                    max_iterations: target.max_iterations,
+                    damping: target.damping,
+                    weight: target.weight,
+                    enabled: target.enabled,
+                    match_rotation: target.match_rotation,
+                    priority: target.priority,
+                    pole: opt_pole_entity,
                 })
                 .insert(Transformable::default())
                 .insert(Animatable)
@@ -539,6 +888,44 @@ fn load(
             spawned_entities.insert(target.entity, target_entity);
         }
 
+        // Spawn Paths
+        for path in data.skeleton.paths.iter() {
+            let bone = match spawned_entities.get(&path.bone) {
+                Some(&bone) => bone,
+                None => continue,
+            };
+            let mut points = vec![];
+            for point in path.points.iter() {
+                let point_entity =
+                    path_constraint::spawn_point(&mut commands, &asset_server, point.translation);
+                spawned_entities.insert(point.entity, point_entity);
+                points.push(point_entity);
+            }
+            commands.entity(bone).insert(PathConstraint {
+                spline_type: path.spline_type,
+                depth: path.depth,
+                points,
+                position: path.position,
+            });
+        }
+
+        // Add Constraints, after all entities they might refer to were spawned
+        for bone in data.skeleton.bones.iter() {
+            let bone_entity = match spawned_entities.get(&bone.entity) {
+                Some(&bone_entity) if !bone.constraints.is_empty() => bone_entity,
+                _ => continue,
+            };
+            let mut constraints = bone.constraints.clone();
+            for constraint in constraints.iter_mut() {
+                constraint.target = constraint
+                    .target
+                    .and_then(|target| spawned_entities.get(&target).copied());
+            }
+            commands
+                .entity(bone_entity)
+                .insert(ConstraintStack::new(constraints));
+        }
+
         // Build Skeleton
         skeleton.bones = spawned_entities.values().into_iter().map(|&e| e).collect();
         for skin_mapping in data.skeleton.skin_mappings.iter_mut() {
@@ -577,6 +964,13 @@ fn load(
         // Load Blending Style
         anim_state.blending_style = data.blending_style;
 
+        // All entities have been respawned, so the history has to refer to the new ones
+        history.remap(&spawned_entities);
+        if e.from_history {
+            continue;
+        }
+        history.record_snapshot("Load");
+
         // Select first Animation
         egui_state.plots[0].name = if let Some(name) = animations.map.keys().next() {
             name.clone()
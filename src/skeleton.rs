@@ -1,12 +1,16 @@
 use std::{cmp, f32::consts::E};
 
 use crate::{skin::START_SCALE, *};
-use bevy::{input::mouse::{MouseWheel, MouseScrollUnit}, math::Vec3A, sprite::MaterialMesh2dBundle};
+use bevy::{math::Vec3A, sprite::MaterialMesh2dBundle, utils::HashMap};
 use bone::Bone;
 use cloth::Cloth;
 use serde::*;
 use skin::Skin;
 
+#[cfg(test)]
+#[path = "tests/skeleton_tests.rs"]
+mod skeleton_tests;
+
 const VERTEX_BONE_MAX_DISTANCE: f32 = 1.;
 
 #[derive(Default)]
@@ -23,10 +27,77 @@ impl Skeleton {
     }
 }
 
+/// How the transforms of a vertex's bones are blended
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SkinningMethod {
+    /// Blends the skinning matrices, which makes twisting joints collapse like a candy wrapper
+    Linear,
+    /// Blends the bones' rotations and translations as dual quaternions, which preserves volume
+    DualQuaternion,
+}
+impl SkinningMethod {
+    pub fn all() -> impl ExactSizeIterator<Item = SkinningMethod> {
+        [Self::Linear, Self::DualQuaternion].iter().copied()
+    }
+}
+impl Default for SkinningMethod {
+    fn default() -> Self {
+        Self::Linear
+    }
+}
+impl ToString for SkinningMethod {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Linear => String::from("Linear"),
+            Self::DualQuaternion => String::from("Dual Quaternion"),
+        }
+    }
+}
+
+/// How automatic weights are computed when binding skins
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum WeightingMethod {
+    /// Weights fall off with the distance to the bones, even across gaps in the mesh
+    Distance,
+    /// Weights diffuse along the mesh from the nearest bones, see [`heat_weights::compute_heat_weights`]
+    Heat,
+}
+impl WeightingMethod {
+    pub fn all() -> impl ExactSizeIterator<Item = WeightingMethod> {
+        [Self::Distance, Self::Heat].iter().copied()
+    }
+}
+impl ToString for WeightingMethod {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Distance => String::from("Distance"),
+            Self::Heat => String::from("Heat Diffusion"),
+        }
+    }
+}
+
+/// Skinning transform of a bone, split into a scale relative to the bone in the bind pose and a rigid
+/// transform, stored as dual quaternion
+#[derive(Clone, Copy)]
+pub struct DualQuaternionTransform {
+    pub scale: Mat4,
+    pub real: Quat,
+    pub dual: Quat,
+}
+
+/// Transforms of all bones from the bind pose into the current pose, see [`get_skinning_transforms`]
+pub struct SkinningTransforms {
+    pub matrices: HashMap<Entity, Mat4>,
+    pub dual_quaternions: HashMap<Entity, DualQuaternionTransform>,
+}
+
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct SkinMapping {
     pub skin: Option<Entity>,
     pub vertex_mappings: Vec<VertexMapping>,
+    /// Global positions of the vertices in the bind pose
+    #[serde(default)]
+    pub bind_vertices: Vec<Vec2>,
 }
 impl SkinMapping {
     pub fn remove_vertex(&mut self) {}
@@ -42,6 +113,17 @@ impl SkinMapping {
             }
         }
     }
+    /// Replace the skin and bone entities, according to `entity_map`
+    pub fn remap(&mut self, entity_map: &HashMap<Entity, Entity>) {
+        if let Some(skin) = self.skin {
+            self.skin = Some(*entity_map.get(&skin).unwrap_or(&skin));
+        }
+        for mapping in self.vertex_mappings.iter_mut() {
+            for bone in mapping.bones.iter_mut() {
+                *bone = *entity_map.get(bone).unwrap_or(bone);
+            }
+        }
+    }
     pub fn remove_bone_at(&mut self, index: usize) {
         for mapping in self.vertex_mappings.iter_mut() {
             mapping.bones.swap_remove(index);
@@ -50,6 +132,28 @@ impl SkinMapping {
             mapping.normalize();
         }
     }
+    pub fn normalize_weights(&mut self) {
+        for mapping in self.vertex_mappings.iter_mut().filter(|mapping| !mapping.is_free) {
+            mapping.normalize();
+        }
+    }
+    pub fn prune_weights(&mut self, threshold: f32) {
+        for mapping in self.vertex_mappings.iter_mut().filter(|mapping| !mapping.is_free) {
+            mapping.prune(threshold);
+        }
+    }
+    pub fn limit_influences(&mut self, max_influences: usize) {
+        for mapping in self.vertex_mappings.iter_mut().filter(|mapping| !mapping.is_free) {
+            mapping.limit_influences(max_influences);
+        }
+    }
+    /// Number of vertices influenced by more than `max_influences` bones
+    pub fn count_exceeding_influences(&self, max_influences: usize) -> usize {
+        self.vertex_mappings
+            .iter()
+            .filter(|mapping| !mapping.is_free && mapping.influence_count() > max_influences)
+            .count()
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -60,17 +164,52 @@ pub struct VertexMapping {
     pub rel_positions: Vec<Vec2>,
 }
 impl VertexMapping {
-    fn normalize(&mut self) {
+    /// Scales the weights to sum up to 1, vertices without any weight are left unchanged
+    pub fn normalize(&mut self) {
         let mut total = 0.;
         for weight in self.weights.iter() {
             total += *weight;
         }
+        if total == 0. {
+            return;
+        }
         for weight in self.weights.iter_mut() {
             if *weight != 0.0 {
                 *weight /= total;
             }
         }
     }
+    /// Number of bones with a weight above zero
+    pub fn influence_count(&self) -> usize {
+        self.weights.iter().filter(|&&weight| weight > 0.).count()
+    }
+    /// Sets weights below `threshold` to zero and normalizes the rest. The largest weight is always kept.
+    pub fn prune(&mut self, threshold: f32) {
+        let max_weight = self.weights.iter().copied().fold(0., f32::max);
+        let threshold = f32::min(threshold, max_weight);
+        for weight in self.weights.iter_mut() {
+            if *weight < threshold {
+                *weight = 0.;
+            }
+        }
+        self.normalize();
+    }
+    /// Keeps the `max_influences` largest weights, sets the others to zero and normalizes
+    pub fn limit_influences(&mut self, max_influences: usize) {
+        if self.influence_count() <= max_influences {
+            return;
+        }
+        let mut order: Vec<usize> = (0..self.weights.len()).collect();
+        order.sort_by(|&a, &b| {
+            self.weights[b]
+                .partial_cmp(&self.weights[a])
+                .unwrap_or(cmp::Ordering::Equal)
+        });
+        for &i in order.iter().skip(max_influences) {
+            self.weights[i] = 0.;
+        }
+        self.normalize();
+    }
     fn refine(&mut self) {
         let mut min = 9999999.;
         for weight in self.weights.iter() {
@@ -105,16 +244,17 @@ impl VertexMapping {
 
 pub fn system_set() -> SystemSet {
     SystemSet::new()
+        .with_system(set_bind_pose.before(apply_mesh_to_skeleton))
         .with_system(apply_mesh_to_skeleton)
         .with_system(free_skins)
         .with_system(assign_skins_to_bones)
-        .with_system(adjust_vertex_weights)
 }
 
 pub fn free_skins(
     keys: Res<Input<KeyCode>>,
     mut skeleton: ResMut<Skeleton>,
     mut q: Query<(&Transformable, &mut Transform), With<Skin>>,
+    mut history: ResMut<history::History>,
 ) {
     // assign skins to bones when A is pressed
     if !(keys.pressed(KeyCode::LControl) && keys.just_pressed(KeyCode::A)) {
@@ -131,6 +271,7 @@ pub fn free_skins(
             q.get_mut(skeleton.skin_mappings[i].skin.unwrap())
         {
             if transformable.is_selected {
+                history.record_snapshot("Unbind skin");
                 skeleton.skin_mappings.swap_remove(i);
                 transform.translation = Vec3::new(0., 0., 0.);
                 transform.rotation = Quat::IDENTITY;
@@ -153,7 +294,10 @@ pub fn assign_skins_to_bones(
         ),
         Without<Bone>,
     >,
-    q1: Query<(&GlobalTransform, &Transformable), With<Bone>>,
+    mut q1: Query<(Entity, &mut Bone, &Transformable)>,
+    q_bone_transforms: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    mut history: ResMut<history::History>,
+    egui_state: Res<egui::State>,
 ) {
     // assign skins to bones when A is pressed
     if !(!keys.pressed(KeyCode::LControl) && keys.just_pressed(KeyCode::A)) {
@@ -174,6 +318,8 @@ pub fn assign_skins_to_bones(
             }
             if skeleton.skin_mappings[i].skin.unwrap() == entity {
                 new_skin = false;
+                // Rebinding replaces the old mapping
+                skeleton.skin_mappings[i].vertex_mappings.clear();
                 relevant_skins.push(i);
             }
         }
@@ -181,11 +327,28 @@ pub fn assign_skins_to_bones(
             skeleton.skin_mappings.push(SkinMapping {
                 skin: Some(entity),
                 vertex_mappings: vec![],
+                bind_vertices: vec![],
             });
             relevant_skins.push(skeleton.skin_mappings.len() - 1);
         }
     }
 
+    if relevant_skins.is_empty() {
+        return;
+    }
+    history.record_snapshot("Bind skin");
+
+    // Skins are bound to the bind pose, bones that don't have one yet use their current pose
+    for (entity, mut bone, _) in q1.iter_mut() {
+        if bone.bind_pose.is_none() {
+            bone.bind_pose = q_bone_transforms.get(entity).ok().map(|(&transform, _)| transform);
+        }
+    }
+    let bind_poses: HashMap<Entity, Transform> = q1
+        .iter()
+        .filter_map(|(entity, bone, _)| bone.bind_pose.map(|bind_pose| (entity, bind_pose)))
+        .collect();
+
     // For each SKIN
     for skin_index in relevant_skins {
         match q0.get(skeleton.skin_mappings[skin_index].skin.unwrap()) {
@@ -195,6 +358,42 @@ pub fn assign_skins_to_bones(
                 }
 
                 let skin_vertices = skin.gl_vertices(gl_transform); // get vertex global position
+                skeleton.skin_mappings[skin_index].bind_vertices = skin_vertices
+                    .iter()
+                    .map(|&vertex| Vec2::from_slice(&vertex))
+                    .collect();
+
+                // Only selected bones are weighted, in the order of the skeleton
+                let weighted_bones: Vec<(Entity, Transform)> = skeleton
+                    .bones
+                    .iter()
+                    .filter(|&&bone| match q1.get(bone) {
+                        Ok((_, _, transformable)) => transformable.is_selected,
+                        Err(_) => false,
+                    })
+                    .filter_map(|&bone| {
+                        bone::get_bone_gl_transform_with_overrides(
+                            bone,
+                            &q_bone_transforms,
+                            &bind_poses,
+                        )
+                        .map(|gl_transform| (bone, gl_transform))
+                    })
+                    .collect();
+                let opt_heat_weights = match egui_state.weighting_method {
+                    WeightingMethod::Distance => None,
+                    WeightingMethod::Heat => heat_weights::compute_heat_weights(
+                        &skeleton.skin_mappings[skin_index].bind_vertices,
+                        &skin.indices,
+                        &weighted_bones
+                            .iter()
+                            .map(|(_, gl_transform)| {
+                                let start = gl_transform.translation.truncate();
+                                (start, Bone::get_tip_global(gl_transform))
+                            })
+                            .collect(),
+                    ),
+                };
 
                 // Add a WEIGHTING for each vertex
                 for i in 0..skin.vertices.len() {
@@ -206,47 +405,44 @@ pub fn assign_skins_to_bones(
                         mapping.is_free = true;
                     } else {
                         // Assign a weight for each bone
-                        for bone_index in 0..skeleton.bones.len() {
-                            match q1.get(skeleton.bones[bone_index]) {
-                                Ok((bone_gl_transform, bone_transformable)) => {
-                                    // Only consider selected bones
-                                    if !bone_transformable.is_selected {
-                                        continue;
-                                    }
-
-                                    // Calculate distance from vertex to bone
-                                    let v = Vec2::from_slice(&skin_vertices[i]);
-                                    let start = bone_gl_transform.affine().translation.truncate();
-                                    let end = Bone::get_tip(bone_gl_transform);
-                                    let distance = transform::distance_segment_point(start, end, v);
-                                    // let distance_scaled = distance / bone_gl_transform.scale.y;
-
-                                    // Calculate vertex position relative to bone
-                                    let (bone_gl_scale, bone_gl_rotation, bone_gl_translation) =
-                                        bone_gl_transform.to_scale_rotation_translation();
-                                    let mut rel_position = Vec3::from_slice(&skin_vertices[i]);
-                                    rel_position -= bone_gl_translation;
-                                    rel_position =
-                                        Quat::mul_vec3(bone_gl_rotation.inverse(), rel_position);
-                                    if bone_gl_scale.x != 0.
-                                        && bone_gl_scale.y != 0.
-                                        && bone_gl_scale.z != 0.
-                                    {
-                                        rel_position /= bone_gl_scale;
-                                    } else {
-                                        println!("assign_skin_to_bones: Failed to compute relative position, because origin's scale is 0");
-                                    }
-
-                                    mapping.bones.push(skeleton.bones[bone_index]);
-                                    mapping.weights.push(distance);
-                                    mapping.rel_positions.push(rel_position.truncate());
-                                }
-                                Err(_) => continue,
-                            };
+                        for &(bone_entity, bone_gl_transform) in weighted_bones.iter() {
+                            // Calculate distance from vertex to bone
+                            let v = Vec2::from_slice(&skin_vertices[i]);
+                            let start = bone_gl_transform.translation.truncate();
+                            let end = Bone::get_tip_global(&bone_gl_transform);
+                            let distance = transform::distance_segment_point(start, end, v);
+                            // let distance_scaled = distance / bone_gl_transform.scale.y;
+
+                            // Calculate vertex position relative to bone
+                            let (bone_gl_scale, bone_gl_rotation, bone_gl_translation) = (
+                                bone_gl_transform.scale,
+                                bone_gl_transform.rotation,
+                                bone_gl_transform.translation,
+                            );
+                            let mut rel_position = Vec3::from_slice(&skin_vertices[i]);
+                            rel_position -= bone_gl_translation;
+                            rel_position = Quat::mul_vec3(bone_gl_rotation.inverse(), rel_position);
+                            if bone_gl_scale.x != 0.
+                                && bone_gl_scale.y != 0.
+                                && bone_gl_scale.z != 0.
+                            {
+                                rel_position /= bone_gl_scale;
+                            } else {
+                                println!("assign_skin_to_bones: Failed to compute relative position, because origin's scale is 0");
+                            }
+
+                            mapping.bones.push(bone_entity);
+                            mapping.weights.push(distance);
+                            mapping.rel_positions.push(rel_position.truncate());
                         }
-                        mapping.refine(); // Set weight to zero for bones too far from vertex
-                        mapping.invert();
-                        mapping.normalize(); // normalize weighting
+                        if let Some(heat_weights) = &opt_heat_weights {
+                            mapping.weights = heat_weights[i].clone();
+                        } else {
+                            mapping.refine(); // Set weight to zero for bones too far from vertex
+                            mapping.invert();
+                            mapping.normalize(); // normalize weighting
+                        }
+                        mapping.limit_influences(egui_state.max_influences);
                     }
 
                     skeleton.skin_mappings[skin_index]
@@ -264,177 +460,372 @@ pub fn apply_mesh_to_skeleton(
     mut meshes: ResMut<Assets<Mesh>>,
     mut skeleton: ResMut<Skeleton>,
     q_bones: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    q_bone_entities: Query<(Entity, &Bone)>,
     q_skins: Query<&Skin>,
+    gpu_state: Res<gpu_skinning::State>,
 ) {
     if skeleton.skin_mappings.is_empty() {
         return;
     }
 
+    // Global transforms are computed once per bone, not once per vertex
+    let mut bone_gl_transforms: HashMap<Entity, Transform> = HashMap::new();
+    for (entity, _) in q_bone_entities.iter() {
+        if let Some(gl_transform) = bone::get_bone_gl_transform(entity, &q_bones) {
+            bone_gl_transforms.insert(entity, gl_transform);
+        }
+    }
+    let bind_gl_transforms = get_bind_gl_transforms(&q_bones, &q_bone_entities);
+    let skinning_transforms = get_skinning_transforms(&bone_gl_transforms, &bind_gl_transforms);
+
+    // Remove bones, that don't exist anymore
+    let mut removed_bones: Vec<Entity> = vec![];
+    for skin_mapping in skeleton.skin_mappings.iter() {
+        for mapping in skin_mapping.vertex_mappings.iter() {
+            for bone in mapping.bones.iter() {
+                if !bone_gl_transforms.contains_key(bone) && !removed_bones.contains(bone) {
+                    removed_bones.push(*bone);
+                }
+            }
+        }
+    }
+    for bone in removed_bones {
+        skeleton.remove_bone(bone);
+    }
+
     // for each SKIN
     for i in (0..skeleton.skin_mappings.len()).rev() {
         // If vertices haven't been mapped for this skin
         if skeleton.skin_mappings[i].vertex_mappings.is_empty() {
             continue;
         }
-        let mut vertices: Vec<[f32; 3]> = vec![];
 
-        // if skin doesn't exist, continue
-        if skeleton.skin_mappings[i].skin.is_none() {
-            skeleton.skin_mappings.swap_remove(i);
-            continue;
-        }
-        let opt_skin = q_skins.get(skeleton.skin_mappings[i].skin.unwrap());
-        if opt_skin.is_err() {
-            continue;
-        }
-        let skin = opt_skin.unwrap();
-
-        // if mesh doesn't exist, continue
-        let opt_mesh = meshes.get_mut(&skin.mesh_handle.clone().unwrap().0);
-        if opt_mesh.is_none() {
-            continue;
-        }
-        let mesh = opt_mesh.unwrap();
-
-        // for each VERTEX
-        for v_i in 0..skin.vertices.len() {
-            // CONFUSION!!! TODO: Fix! After removing bone confusion!!
-            if i >= skeleton.skin_mappings.len() {
-                vertices.push(mesh::get_vertex(mesh, v_i));
-                continue;
-            }
-            // if vertex is free keep old position and continue to next vertex
-            if skeleton.skin_mappings[i].vertex_mappings[v_i].is_free == true {
-                vertices.push(mesh::get_vertex(mesh, v_i));
+        // if skin doesn't exist or is deformed on the GPU, continue
+        let skin = match skeleton.skin_mappings[i].skin {
+            Some(skin_entity) if gpu_state.gpu_skins.contains_key(&skin_entity) => continue,
+            Some(skin_entity) => match q_skins.get(skin_entity) {
+                Ok(skin) => skin,
+                Err(_) => continue,
+            },
+            None => {
+                skeleton.skin_mappings.swap_remove(i);
                 continue;
             }
-            let mut v_gl_position = Vec3::new(0., 0., 0.);
-            // for each BONE
-            'outer: for b_i in (0..skeleton.skin_mappings[i].vertex_mappings[v_i].bones.len()).rev()
-            {
-                let bone = skeleton.skin_mappings[i].vertex_mappings[v_i].bones[b_i];
-                let opt_bone_gl_transform = bone::get_bone_gl_transform(bone, &q_bones);
-                let bone_gl_transform = if opt_bone_gl_transform.is_some() {
-                    opt_bone_gl_transform.unwrap()
-                } else {
-                    skeleton.remove_bone(bone);
-                    continue 'outer;
-                };
-
-                // Calculate total of all weights
-                let mut total_weight: f32 = 0.0;
-                skeleton.skin_mappings[i].vertex_mappings[v_i]
-                    .weights
-                    .iter()
-                    .for_each(|&weight| total_weight += weight);
-
-                let weight = skeleton.skin_mappings[i].vertex_mappings[v_i].weights[b_i];
-                if weight == 0.0 {
-                    skeleton.skin_mappings[i].vertex_mappings[v_i]
-                        .bones
-                        .swap_remove(b_i);
-                    skeleton.skin_mappings[i].vertex_mappings[v_i]
-                        .weights
-                        .swap_remove(b_i);
-                }
-
-                let translation =
-                    skeleton.skin_mappings[i].vertex_mappings[v_i].rel_positions[b_i].extend(0.);
-                let vertex_rel_transform = Transform::from_translation(translation);
-                let vertex_gl_transform =
-                    combined_transform(&bone_gl_transform, &vertex_rel_transform);
-
-                v_gl_position += weight / total_weight * vertex_gl_transform.translation;
-            }
+        };
 
-            if skeleton.skin_mappings[i].vertex_mappings[v_i].bones.len() == 0 {
-                v_gl_position = Vec3::from_slice(
-                    &q_skins
-                        .get(skeleton.skin_mappings[i].skin.unwrap())
-                        .unwrap()
-                        .vertices[v_i],
-                );
-            }
-            vertices.push([v_gl_position.x, v_gl_position.y, 0.]);
-        }
+        // if mesh doesn't exist, continue
+        let mesh = match meshes.get_mut(&skin.mesh_handle.clone().unwrap().0) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
 
-        // update mesh vertices
+        let vertices = get_skinned_vertices(
+            &skeleton.skin_mappings[i],
+            skin,
+            mesh,
+            skin.skinning_method,
+            &skinning_transforms,
+        );
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
     }
 }
 
-fn adjust_vertex_weights(
-    meshes: Res<Assets<Mesh>>,
-    q: Query<(&skin::Skin, Entity)>,
-    mut skeleton: ResMut<skeleton::Skeleton>,
-    transform_state: Res<transform::State>,
+/// Makes the current pose the bind pose, when requested in the UI. Bound skins are re-bound, so they keep
+/// their current shape instead of jumping.
+pub fn set_bind_pose(
     mut egui_state: ResMut<egui::State>,
-    cursor_pos: Res<CursorPos>,
-    keys: Res<Input<KeyCode>>,
-    mut mouse_wheel_evr: EventReader<MouseWheel>,
+    mut skeleton: ResMut<Skeleton>,
+    mut history: ResMut<history::History>,
+    q_bones: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    mut q_bone_entities: Query<(Entity, &mut Bone)>,
+    q_skins: Query<&Skin>,
 ) {
-    if keys.just_pressed(KeyCode::W) {
-        egui_state.adjust_vertex_weights_mode = !egui_state.adjust_vertex_weights_mode;
+    if !egui_state.set_bind_pose {
+        return;
+    }
+    egui_state.set_bind_pose = false;
+
+    let bind_poses: HashMap<Entity, Transform> = q_bone_entities
+        .iter()
+        .filter_map(|(entity, bone)| bone.bind_pose.map(|bind_pose| (entity, bind_pose)))
+        .collect();
+    let mut bone_gl_transforms: HashMap<Entity, Transform> = HashMap::new();
+    let mut bind_gl_transforms: HashMap<Entity, Transform> = HashMap::new();
+    for (entity, _) in q_bone_entities.iter() {
+        if let Some(gl_transform) = bone::get_bone_gl_transform(entity, &q_bones) {
+            bone_gl_transforms.insert(entity, gl_transform);
+        }
+        if let Some(gl_transform) =
+            bone::get_bone_gl_transform_with_overrides(entity, &q_bones, &bind_poses)
+        {
+            bind_gl_transforms.insert(entity, gl_transform);
+        }
     }
+    let skinning_transforms = get_skinning_transforms(&bone_gl_transforms, &bind_gl_transforms);
 
-    for mouse_wheel in mouse_wheel_evr.iter() {
-        let change_value = mouse_wheel.y * {if mouse_wheel.unit == MouseScrollUnit::Line {0.05} else {0.0005}};
-        egui_state.brush_size = f32::max(0.05, egui_state.brush_size + change_value);
+    for skin_mapping in skeleton.skin_mappings.iter_mut() {
+        let skinning_method = match skin_mapping.skin.and_then(|skin| q_skins.get(skin).ok()) {
+            Some(skin) => skin.skinning_method,
+            None => continue,
+        };
+        rebind_vertices(
+            skin_mapping,
+            skinning_method,
+            &skinning_transforms,
+            &bone_gl_transforms,
+        );
+    }
+    for (entity, mut bone) in q_bone_entities.iter_mut() {
+        if let Ok((&transform, _)) = q_bones.get(entity) {
+            bone.bind_pose = Some(transform);
+        }
     }
+    history.record_snapshot("Set bind pose");
+}
 
-    let increase = if keys.just_pressed(KeyCode::E) {
-        true
-    } else if keys.just_pressed(KeyCode::Q) {
-        false
-    } else {
-        return;
-    };
+/// Global transforms of all bones in the bind pose. Bones without bind pose use their current transform.
+pub fn get_bind_gl_transforms(
+    q_bones: &Query<(&Transform, Option<&Parent>), With<Bone>>,
+    q_bone_entities: &Query<(Entity, &Bone)>,
+) -> HashMap<Entity, Transform> {
+    let bind_poses: HashMap<Entity, Transform> = q_bone_entities
+        .iter()
+        .filter_map(|(entity, bone)| bone.bind_pose.map(|bind_pose| (entity, bind_pose)))
+        .collect();
+    q_bone_entities
+        .iter()
+        .filter_map(|(entity, _)| {
+            bone::get_bone_gl_transform_with_overrides(entity, q_bones, &bind_poses)
+                .map(|gl_transform| (entity, gl_transform))
+        })
+        .collect()
+}
 
-    for (skin, entity) in q.iter() {
-        let opt_mesh = meshes.get(&skin.mesh_handle.clone().unwrap().0);
-        if opt_mesh.is_none() {
+/// Returns the matrices, that move vertices from the bind pose into the current pose, i.e. the current
+/// global transform of each bone times its inverse bind matrix
+pub fn get_skinning_matrices(
+    bone_gl_transforms: &HashMap<Entity, Transform>,
+    bind_gl_transforms: &HashMap<Entity, Transform>,
+) -> HashMap<Entity, Mat4> {
+    let mut matrices: HashMap<Entity, Mat4> = HashMap::new();
+    for (&entity, gl_transform) in bone_gl_transforms.iter() {
+        let bind_gl_transform = match bind_gl_transforms.get(&entity) {
+            Some(bind_gl_transform) => bind_gl_transform,
+            None => continue,
+        };
+        if bind_gl_transform.scale.x == 0. || bind_gl_transform.scale.y == 0. {
+            println!("get_skinning_matrices: Failed to invert bind matrix, because its scale is 0");
             continue;
         }
-        let mesh = opt_mesh.unwrap();
+        let inverse_bind_matrix = bind_gl_transform.compute_matrix().inverse();
+        matrices.insert(entity, gl_transform.compute_matrix() * inverse_bind_matrix);
+    }
+    matrices
+}
 
-        let vertices: Vec<Vec3> = mesh::get_vertices(mesh);
+/// Returns the skinning transforms of both [`SkinningMethod`]s
+pub fn get_skinning_transforms(
+    bone_gl_transforms: &HashMap<Entity, Transform>,
+    bind_gl_transforms: &HashMap<Entity, Transform>,
+) -> SkinningTransforms {
+    SkinningTransforms {
+        matrices: get_skinning_matrices(bone_gl_transforms, bind_gl_transforms),
+        dual_quaternions: get_dual_quaternion_transforms(bone_gl_transforms, bind_gl_transforms),
+    }
+}
 
-        let mut skin_mapping_index: Option<usize> = None;
-        for i in 0..skeleton.skin_mappings.len() {
-            if let Some(skin_entity) = skeleton.skin_mappings[i].skin {
-                if skin_entity == entity {
-                    skin_mapping_index = Some(i);
-                }
+/// Returns the transforms, that move vertices from the bind pose into the current pose, as scale and
+/// dual quaternion. Together they are equal to the skinning matrix of [`get_skinning_matrices`].
+pub fn get_dual_quaternion_transforms(
+    bone_gl_transforms: &HashMap<Entity, Transform>,
+    bind_gl_transforms: &HashMap<Entity, Transform>,
+) -> HashMap<Entity, DualQuaternionTransform> {
+    let mut transforms: HashMap<Entity, DualQuaternionTransform> = HashMap::new();
+    for (&entity, gl_transform) in bone_gl_transforms.iter() {
+        let bind_gl_transform = match bind_gl_transforms.get(&entity) {
+            Some(bind_gl_transform) => bind_gl_transform,
+            None => continue,
+        };
+        if bind_gl_transform.scale.x == 0. || bind_gl_transform.scale.y == 0. {
+            println!("get_dual_quaternion_transforms: Failed to invert bind scale, because it is 0");
+            continue;
+        }
+        // Scale along the axes of the bone in the bind pose
+        let bind_rigid_matrix = Mat4::from_rotation_translation(
+            bind_gl_transform.rotation,
+            bind_gl_transform.translation,
+        );
+        let scale = bind_rigid_matrix
+            * Mat4::from_scale(gl_transform.scale / bind_gl_transform.scale)
+            * bind_rigid_matrix.inverse();
+
+        // Rotate and translate from the bind pose into the current pose
+        let real = (gl_transform.rotation * bind_gl_transform.rotation.inverse()).normalize();
+        let translation = gl_transform.translation - real.mul_vec3(bind_gl_transform.translation);
+        transforms.insert(
+            entity,
+            DualQuaternionTransform {
+                scale,
+                real,
+                dual: get_dual_part(real, translation),
+            },
+        );
+    }
+    transforms
+}
+
+/// Dual part of the unit dual quaternion, that rotates by `real` and then translates by `translation`,
+/// i.e. 0.5 * translation * real
+fn get_dual_part(real: Quat, translation: Vec3) -> Quat {
+    let real_vector = Vec3::new(real.x, real.y, real.z);
+    let vector = 0.5 * (real.w * translation + translation.cross(real_vector));
+    Quat::from_xyzw(vector.x, vector.y, vector.z, -0.5 * translation.dot(real_vector))
+}
+
+/// Translation of a unit dual quaternion, i.e. 2 * dual * conjugate(real)
+fn get_translation(real: Quat, dual: Quat) -> Vec3 {
+    let real_vector = Vec3::new(real.x, real.y, real.z);
+    let dual_vector = Vec3::new(dual.x, dual.y, dual.z);
+    2. * (real.w * dual_vector - dual.w * real_vector + real_vector.cross(dual_vector))
+}
+
+/// Moves the bind vertices of a skin to their skinned position, so the skin keeps its shape, when the
+/// current pose becomes the bind pose. `gl_transforms` are the global transforms of the bones in the
+/// current pose, the positions relative to the bones are updated accordingly.
+pub fn rebind_vertices(
+    skin_mapping: &mut SkinMapping,
+    skinning_method: SkinningMethod,
+    skinning_transforms: &SkinningTransforms,
+    gl_transforms: &HashMap<Entity, Transform>,
+) {
+    for (mapping, bind_vertex) in skin_mapping
+        .vertex_mappings
+        .iter_mut()
+        .zip(skin_mapping.bind_vertices.iter_mut())
+    {
+        if mapping.is_free || mapping.bones.is_empty() {
+            continue;
+        }
+        *bind_vertex = match skinning_method {
+            SkinningMethod::Linear => {
+                skin_vertex(mapping, *bind_vertex, &skinning_transforms.matrices)
+            }
+            SkinningMethod::DualQuaternion => skin_vertex_dual_quaternion(
+                mapping,
+                *bind_vertex,
+                &skinning_transforms.dual_quaternions,
+            ),
+        };
+        let vertex_gl_transform = Transform::from_translation(bind_vertex.extend(0.));
+        for (bone, rel_position) in mapping.bones.iter().zip(mapping.rel_positions.iter_mut()) {
+            if let Some(gl_transform) = gl_transforms.get(bone) {
+                *rel_position = get_relative_transform(gl_transform, &vertex_gl_transform)
+                    .translation
+                    .truncate();
             }
         }
+    }
+}
+
+/// Deforms `bind_vertex` with the blended dual quaternions of the vertex's bones. The bones' scales are
+/// blended linearly and applied first.
+pub fn skin_vertex_dual_quaternion(
+    mapping: &VertexMapping,
+    bind_vertex: Vec2,
+    transforms: &HashMap<Entity, DualQuaternionTransform>,
+) -> Vec2 {
+    let total_weight: f32 = mapping.weights.iter().sum();
+    if total_weight == 0. {
+        return bind_vertex;
+    }
+    let mut scaled_vertex = Vec3::ZERO;
+    let mut real = Quat::from_xyzw(0., 0., 0., 0.);
+    let mut dual = Quat::from_xyzw(0., 0., 0., 0.);
+    let mut opt_pivot: Option<Quat> = None;
+    for (bone, &weight) in mapping.bones.iter().zip(mapping.weights.iter()) {
+        let transform = match transforms.get(bone) {
+            Some(transform) => transform,
+            None => continue,
+        };
+        let weight = weight / total_weight;
+        scaled_vertex += weight * transform.scale.transform_point3(bind_vertex.extend(0.));
+
+        // q and -q are the same rotation, use the one closer to the first bone to take the shortest path
+        let pivot = *opt_pivot.get_or_insert(transform.real);
+        let weight = if transform.real.dot(pivot) < 0. {
+            -weight
+        } else {
+            weight
+        };
+        real = real + transform.real * weight;
+        dual = dual + transform.dual * weight;
+    }
+    let length = real.length();
+    if length == 0. {
+        return bind_vertex;
+    }
+    let (real, dual) = (real / length, dual / length);
+    (real.mul_vec3(scaled_vertex) + get_translation(real, dual)).truncate()
+}
 
-        // Adjust weight for each vertex
-        for i in 0..vertices.len() {
-            if skin_mapping_index.is_some() && egui_state.adjust_vertex_weights_mode {
-                if transform_state.selected_entities.len() > 0 {
-                    // Currently selected bone
-                    let bone_entity = *transform_state.selected_entities.iter().next().unwrap();
-                    let v_mapping =
-                        &mut skeleton.skin_mappings[skin_mapping_index.unwrap()].vertex_mappings[i];
+/// Deforms `bind_vertex` with the weighted sum of the skinning matrices of the vertex's bones
+pub fn skin_vertex(
+    mapping: &VertexMapping,
+    bind_vertex: Vec2,
+    skinning_matrices: &HashMap<Entity, Mat4>,
+) -> Vec2 {
+    let total_weight: f32 = mapping.weights.iter().sum();
+    if total_weight == 0. {
+        return bind_vertex;
+    }
+    let mut matrix = Mat4::ZERO;
+    for (bone, &weight) in mapping.bones.iter().zip(mapping.weights.iter()) {
+        if let Some(&skinning_matrix) = skinning_matrices.get(bone) {
+            matrix = matrix + skinning_matrix * (weight / total_weight);
+        }
+    }
+    matrix.transform_point3(bind_vertex.extend(0.)).truncate()
+}
 
-                    // Weight of current bone for current vertex
-                    for j in 0..v_mapping.bones.len() {
-                        if v_mapping.bones[j] == bone_entity {
-                            if vertices[i].truncate().distance(cursor_pos.0) < egui_state.brush_size
-                            {
-                                let value_change = 0.1;
-                                if increase {
-                                    v_mapping.weights[j] =
-                                        f32::min(1.0, v_mapping.weights[j] + value_change);
-                                } else {
-                                    v_mapping.weights[j] =
-                                        f32::max(0.0001, v_mapping.weights[j] - value_change);
-                                }
-                            }
-                        }
-                    }
-                }
+/// Returns the positions of a bound skin's vertices for the given skinning transforms, see
+/// [`get_skinning_transforms`].
+///
+/// Free vertices keep their current position in `mesh`.
+pub fn get_skinned_vertices(
+    skin_mapping: &SkinMapping,
+    skin: &Skin,
+    mesh: &Mesh,
+    skinning_method: SkinningMethod,
+    skinning_transforms: &SkinningTransforms,
+) -> Vec<[f32; 3]> {
+    let mut vertices: Vec<[f32; 3]> = vec![];
+    for v_i in 0..skin.vertices.len() {
+        let (mapping, bind_vertex) = match (
+            skin_mapping.vertex_mappings.get(v_i),
+            skin_mapping.bind_vertices.get(v_i),
+        ) {
+            (Some(mapping), Some(&bind_vertex)) if !mapping.is_free => (mapping, bind_vertex),
+            _ => {
+                vertices.push(mesh::get_vertex(mesh, v_i));
+                continue;
             }
+        };
+        if mapping.bones.is_empty() {
+            vertices.push(skin.vertices[v_i]);
+            continue;
         }
+        let position = match skinning_method {
+            SkinningMethod::Linear => {
+                skin_vertex(mapping, bind_vertex, &skinning_transforms.matrices)
+            }
+            SkinningMethod::DualQuaternion => skin_vertex_dual_quaternion(
+                mapping,
+                bind_vertex,
+                &skinning_transforms.dual_quaternions,
+            ),
+        };
+        vertices.push([position.x, position.y, 0.]);
     }
+    vertices
 }
+
@@ -33,6 +33,8 @@ pub const AVAILABLE_IMAGES: [&str; 13] = [
 #[derive(Default)]
 pub struct State {
     pub queued_skins: Vec<AddSkinOrder>,
+    /// Draws the mesh of bound skins with the other skinning method on top
+    pub compare_skinning_methods: bool,
 }
 
 #[derive(Clone)]
@@ -58,6 +60,7 @@ pub struct Skin {
     pub uvs: Vec<[f32; 2]>,
     pub indices: Vec<u16>,
     pub mesh_handle: Option<Mesh2dHandle>,
+    pub skinning_method: skeleton::SkinningMethod,
 }
 impl Skin {
     fn from_contour(contour: Contour, triangle_size: f32) -> Option<Skin> {
@@ -69,6 +72,7 @@ impl Skin {
             uvs,
             indices,
             mesh_handle: None,
+            skinning_method: skeleton::SkinningMethod::default(),
         };
 
         Some(skin)
@@ -150,6 +154,7 @@ impl Skin {
                 uvs,
                 indices,
                 mesh_handle: None,
+                skinning_method: skeleton::SkinningMethod::default(),
             };
             // // Remove reduntant vertices and corresponding uvs and indices
             if cut_out {
@@ -334,6 +339,7 @@ pub fn add_skins(
     mut state: ResMut<State>,
     asset_server: Res<AssetServer>,
     image_assets: Res<Assets<Image>>,
+    mut history: ResMut<history::History>,
 ) {
     for i in (0..state.queued_skins.len()).rev() {
         let event = &state.queued_skins[i];
@@ -348,6 +354,7 @@ pub fn add_skins(
         .is_some()
         {
             state.queued_skins.swap_remove(i);
+            history.record_snapshot("Add skin");
         }
     }
 }
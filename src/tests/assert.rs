@@ -14,6 +14,19 @@ pub fn assert_translation_eq(a: Vec3, b: Vec3) {
     }
 }
 
+pub fn assert_f32_eq(a: f32, b: f32) {
+    if (a - b).abs() > 0.0001 {
+        panic!("Values aren't equal: \n{}, \n{}", a, b);
+    }
+}
+
+pub fn assert_vec2_eq(a: Vec2, b: Vec2) {
+    // Positions of skinned vertices and paths pass through several transforms, so they are compared less strictly
+    if a.distance(b) > 0.0001 {
+        panic!("Vectors aren't equal: \n{}, \n{}", a, b);
+    }
+}
+
 pub fn assert_quat_eq(a: &Quat, b: &Quat) {
     let a_negated = Quat::from_xyzw(-a.x, -a.y, -a.z, -a.w);
     let a_eq_b = (a.x - b.x).abs() < EPSILON
@@ -35,3 +48,13 @@ pub fn assert_transform_eq(a: &Transform, b: &Transform) {
     assert_translation_eq(a.translation, b.translation);
     assert_quat_eq(&a.rotation, &b.rotation);
 }
+
+/// Vertex mapping with one bone per weight, the bones are numbered from 0
+pub fn mapping_with_weights(weights: Vec<f32>) -> skeleton::VertexMapping {
+    skeleton::VertexMapping {
+        is_free: false,
+        bones: (0..weights.len() as u32).map(Entity::from_raw).collect(),
+        rel_positions: vec![Vec2::ZERO; weights.len()],
+        weights,
+    }
+}
@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn slots_are_rotated() {
+        let mut state = State {
+            slot_count: 3,
+            ..Default::default()
+        };
+
+        let slots: Vec<usize> = (0..5).map(|_| state.take_slot()).collect();
+
+        assert_eq!(slots, vec![0, 1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn rotation_continues_after_newest_slot() {
+        let mut state = State {
+            slot_count: 3,
+            ..Default::default()
+        };
+
+        state.continue_after(2);
+        assert_eq!(state.take_slot(), 0);
+
+        state.continue_after(0);
+        assert_eq!(state.take_slot(), 1);
+
+        // Slot count was lowered since the last save
+        state.slot_count = 1;
+        assert_eq!(state.take_slot(), 0);
+    }
+
+
+    /// Empty directory that is only used by one test
+    fn get_directory(name: &str) -> String {
+        let directory = std::env::temp_dir().join(format!("autosave_tests_{}", name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn newest_slot_is_read() {
+        let directory = get_directory("newest_slot");
+        let mut state = State {
+            slot_count: 3,
+            ..Default::default()
+        };
+
+        for i in 0..4 {
+            write_slot(&directory, state.take_slot(), &i.to_string());
+            // Make sure the modification times differ
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        assert_eq!(read_newest_slot(&directory, 3), Some((0, String::from("3"))));
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn no_slot_is_read_without_autosaves() {
+        let directory = get_directory("no_slot");
+
+        assert_eq!(read_newest_slot(&directory, 3), None);
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn unclean_shutdown_is_detected() {
+        let directory = get_directory("unclean_shutdown");
+
+        assert!(!start_session(&directory));
+        // Lock file wasn't removed, so the editor crashed
+        assert!(start_session(&directory));
+
+        remove_lock_file(&directory);
+        assert!(!start_session(&directory));
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,37 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use assert::*;
+
+    #[test]
+    fn normalize_keeps_end_after_start() {
+        let mut constraint = AngleConstraint {
+            start: -PI / 2.0,
+            end: PI / 2.0,
+        };
+
+        constraint.normalize();
+
+        assert!((constraint.start - 1.5 * PI).abs() <= 0.0001);
+        assert!((constraint.end - 2.5 * PI).abs() <= 0.0001);
+    }
+
+    #[test]
+    fn handles_are_relative_to_parent() {
+        let constraint = AngleConstraint {
+            start: 0.0,
+            end: PI / 2.0,
+        };
+        let gl_transform = Transform {
+            translation: Vec3::new(1.0, 0.0, 0.0),
+            scale: Vec3::new(1.0, 3.0, 1.0),
+            ..Default::default()
+        };
+
+        let (start, end) =
+            get_angle_constraint_handles(&constraint, &gl_transform, Quat::from_rotation_z(PI / 2.0));
+
+        assert_vec2_eq(start, Vec2::new(0.0, 0.0));
+        assert_vec2_eq(end, Vec2::new(1.0, -1.0));
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use assert::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn look_at_points_bone_at_target() {
+        let constraint = Constraint::new(ConstraintType::LookAt);
+        let local = Transform::from_xyz(1.0, 0.0, 0.0);
+        let target = Transform::from_xyz(0.0, -2.0, 0.0);
+        let parent = Transform::from_rotation(Quat::from_rotation_z(PI / 2.0));
+
+        let constrained = apply_constraint(&constraint, local, &parent, &target);
+
+        // bone lies at (0, 1) globally and has to point down
+        assert_transform_eq(
+            &constrained,
+            &Transform {
+                rotation: Quat::from_rotation_z(PI / 2.0),
+                ..local
+            },
+        );
+    }
+
+    #[test]
+    fn copy_rotation_is_blended_by_influence() {
+        let mut constraint = Constraint::new(ConstraintType::CopyRotation);
+        constraint.influence = 0.5;
+        let target = Transform::from_rotation(Quat::from_rotation_z(PI / 2.0));
+
+        let constrained = apply_constraint(&constraint, Transform::default(), &Transform::default(), &target);
+
+        assert_transform_eq(&constrained, &Transform::from_rotation(Quat::from_rotation_z(PI / 4.0)));
+    }
+
+    #[test]
+    fn limit_distance_only_moves_bones_that_are_too_far_away() {
+        let mut constraint = Constraint::new(ConstraintType::LimitDistance);
+        constraint.distance = 2.0;
+        let target = Transform::from_xyz(5.0, 0.0, 0.0);
+
+        let near = apply_constraint(&constraint, Transform::from_xyz(4.0, 0.0, 1.0), &Transform::default(), &target);
+        let far = apply_constraint(&constraint, Transform::from_xyz(0.0, 0.0, 1.0), &Transform::default(), &target);
+
+        assert_transform_eq(&near, &Transform::from_xyz(4.0, 0.0, 1.0));
+        assert_transform_eq(&far, &Transform::from_xyz(3.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn stretch_to_reaches_target() {
+        let constraint = Constraint::new(ConstraintType::StretchTo);
+        let target = Transform::from_xyz(0.0, 3.0, 0.0);
+
+        let constrained = apply_constraint(&constraint, Transform::default(), &Transform::default(), &target);
+
+        assert_transform_eq(&constrained, &Transform::from_scale(Vec3::new(1.0, 3.0, 1.0)));
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use assert::*;
+    use crate::skeleton::{get_skinning_matrices, skin_vertex, VertexMapping};
+    use std::f32::consts::PI;
+
+    fn vertex_mapping(bones: Vec<Entity>, weights: Vec<f32>) -> VertexMapping {
+        VertexMapping {
+            is_free: false,
+            weights,
+            bones,
+            rel_positions: vec![],
+        }
+    }
+
+    #[test]
+    fn shader_matches_cpu_skinning() {
+        let bones: Vec<Entity> = (0..3).map(Entity::from_raw).collect();
+        let bind_gl_transforms: HashMap<Entity, Transform> = [
+            (bones[0], Transform::from_xyz(0.0, 0.0, 0.0)),
+            (
+                bones[1],
+                Transform {
+                    translation: Vec3::new(0.0, 2.0, 0.0),
+                    rotation: Quat::from_rotation_z(0.4),
+                    scale: Vec3::new(1.0, 1.5, 1.0),
+                },
+            ),
+            (bones[2], Transform::from_xyz(-1.0, 3.0, 0.0)),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        let bone_gl_transforms: HashMap<Entity, Transform> = [
+            (
+                bones[0],
+                Transform::from_rotation(Quat::from_rotation_z(PI / 4.0)),
+            ),
+            (
+                bones[1],
+                Transform {
+                    translation: Vec3::new(1.0, 2.5, 0.0),
+                    rotation: Quat::from_rotation_z(-0.7),
+                    scale: Vec3::new(1.0, 0.5, 1.0),
+                },
+            ),
+            (bones[2], Transform::from_xyz(-2.0, 1.0, 0.0)),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        let matrices = get_skinning_matrices(&bone_gl_transforms, &bind_gl_transforms);
+        let skin_mapping = SkinMapping {
+            skin: None,
+            vertex_mappings: vec![
+                vertex_mapping(vec![bones[0]], vec![1.0]),
+                vertex_mapping(vec![bones[1], bones[0]], vec![0.3, 0.9]),
+                vertex_mapping(vec![bones[2], bones[1], bones[0]], vec![0.2, 0.2, 0.6]),
+            ],
+            bind_vertices: vec![
+                Vec2::new(0.5, 1.0),
+                Vec2::new(-0.5, 2.5),
+                Vec2::new(-1.0, 2.0),
+            ],
+        };
+
+        let attributes = get_vertex_attributes(&skin_mapping).unwrap();
+        let mut bone_matrices = [Mat4::ZERO; MAX_BONES];
+        for (i, bone) in attributes.bones.iter().enumerate() {
+            bone_matrices[i] = matrices[bone];
+        }
+
+        for v_i in 0..skin_mapping.vertex_mappings.len() {
+            let gpu_position = skin_vertex_like_shader(
+                attributes.positions[v_i],
+                attributes.bone_indices[v_i],
+                attributes.bone_weights[v_i],
+                &bone_matrices,
+            );
+            assert_vec2_eq(
+                gpu_position.truncate(),
+                skin_vertex(
+                    &skin_mapping.vertex_mappings[v_i],
+                    skin_mapping.bind_vertices[v_i],
+                    &matrices,
+                ),
+            );
+        }
+    }
+
+    #[test]
+    fn too_many_bones_per_vertex_fall_back_to_cpu() {
+        let bones: Vec<Entity> = (0..5).map(Entity::from_raw).collect();
+        let skin_mapping = SkinMapping {
+            skin: None,
+            vertex_mappings: vec![vertex_mapping(bones, vec![0.2; 5])],
+            bind_vertices: vec![Vec2::ZERO],
+        };
+
+        assert!(get_vertex_attributes(&skin_mapping).is_none());
+    }
+}
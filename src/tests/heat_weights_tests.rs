@@ -0,0 +1,73 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    const CELL_SIZE: f32 = 0.5;
+
+    /// Grid of 5 x 8 cells with a gap of one cell between two legs, that are connected at the top
+    fn get_legs_mesh() -> (Vec<Vec2>, Vec<u16>) {
+        let (cols, rows) = (5, 8);
+        let mut vertices: Vec<Vec2> = vec![];
+        for y in 0..=rows {
+            for x in 0..=cols {
+                vertices.push(Vec2::new(x as f32, y as f32) * CELL_SIZE);
+            }
+        }
+        let mut indices: Vec<u16> = vec![];
+        for y in 0..rows {
+            for x in 0..cols {
+                if x == 2 && y < 6 {
+                    continue;
+                }
+                let i = (y * (cols + 1) + x) as u16;
+                let above = i + cols as u16 + 1;
+                indices.extend([i, i + 1, above + 1, i, above + 1, above]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    #[test]
+    fn weights_are_normalized() {
+        let (vertices, indices) = get_legs_mesh();
+        let bones = vec![
+            (Vec2::new(0.25, 0.0), Vec2::new(0.25, 3.0)),
+            (Vec2::new(1.6, 0.0), Vec2::new(1.6, 3.0)),
+            (Vec2::new(0.25, 3.5), Vec2::new(2.25, 3.5)),
+        ];
+
+        let weights = compute_heat_weights(&vertices, &indices, &bones).unwrap();
+
+        assert_eq!(weights.len(), vertices.len());
+        for vertex_weights in weights.iter() {
+            assert_eq!(vertex_weights.len(), bones.len());
+            assert!((vertex_weights.iter().sum::<f32>() - 1.0).abs() <= 0.0001);
+            assert!(vertex_weights.iter().all(|&weight| (0.0..=1.0).contains(&weight)));
+        }
+    }
+
+    #[test]
+    fn weights_dont_bleed_across_gaps() {
+        let (vertices, indices) = get_legs_mesh();
+        // The right bone is closer to the left leg's inner edge than the left bone
+        let bones = vec![
+            (Vec2::new(0.25, 0.0), Vec2::new(0.25, 3.0)),
+            (Vec2::new(1.6, 0.0), Vec2::new(1.6, 3.0)),
+        ];
+        let find_vertex = |position: Vec2| vertices.iter().position(|&v| v == position).unwrap();
+        let inner_vertex = find_vertex(Vec2::new(1.0, 0.0));
+        let right_vertex = find_vertex(Vec2::new(2.5, 0.0));
+
+        let weights = compute_heat_weights(&vertices, &indices, &bones).unwrap();
+
+        assert!(weights[inner_vertex][0] > 0.9, "{:?}", weights[inner_vertex]);
+        assert!(weights[right_vertex][1] > 0.9, "{:?}", weights[right_vertex]);
+    }
+
+    #[test]
+    fn no_bones_no_weights() {
+        let (vertices, indices) = get_legs_mesh();
+
+        assert!(compute_heat_weights(&vertices, &indices, &vec![]).is_none());
+    }
+}
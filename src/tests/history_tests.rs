@@ -0,0 +1,171 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn transforms_command(entity: u32) -> Command {
+        Command::Transforms(vec![(
+            Entity::from_raw(entity),
+            Transform::default(),
+            Transform::from_xyz(1., 0., 0.),
+        )])
+    }
+
+    fn animations_command(layers_before: Vec<&str>, layers_after: Vec<&str>) -> Command {
+        let snapshot = |layers: Vec<&str>| AnimationsSnapshot {
+            map: HashMap::new(),
+            layers: layers.into_iter().map(String::from).collect(),
+            blending_style: BlendingStyle::Layering,
+        };
+        Command::Animations {
+            before: snapshot(layers_before),
+            after: snapshot(layers_after),
+        }
+    }
+
+    fn get_layers(command: &Command) -> (Vec<String>, Vec<String>) {
+        match command {
+            Command::Animations { before, after } => (before.layers.clone(), after.layers.clone()),
+            _ => panic!("not an animations command"),
+        }
+    }
+
+    #[test]
+    fn push_merges_quick_changes_of_same_name() {
+        let mut history = History::default();
+
+        history.push("Edit layers", animations_command(vec![], vec!["a"]));
+        history.time = MERGE_INTERVAL / 2.;
+        history.push("Edit layers", animations_command(vec!["a"], vec!["a", "b"]));
+
+        assert_eq!(history.undo_entries().len(), 1);
+        let (before, after) = get_layers(&history.undo_entries()[0].command);
+        assert!(before.is_empty());
+        assert_eq!(after, vec!["a", "b"]);
+        assert!(history.is_merging);
+    }
+
+    #[test]
+    fn push_keeps_separate_changes() {
+        let mut history = History::default();
+
+        history.push("Edit layers", animations_command(vec![], vec!["a"]));
+        // Different name
+        history.push("Add layer", animations_command(vec!["a"], vec!["a", "b"]));
+        // Too late
+        history.time = 1.;
+        history.push("Add layer", animations_command(vec!["a", "b"], vec!["a", "b", "c"]));
+        // Transforms are never merged
+        history.push("Move", transforms_command(0));
+        history.push("Move", transforms_command(0));
+
+        assert_eq!(history.undo_entries().len(), 5);
+        assert!(!history.is_merging);
+    }
+
+    #[test]
+    fn push_keeps_quick_brush_strokes_separate() {
+        let mut history = History::default();
+
+        for _ in 0..2 {
+            history.push(
+                "Paint weights",
+                Command::Weights {
+                    before: vec![],
+                    after: vec![],
+                },
+            );
+        }
+
+        assert_eq!(history.undo_entries().len(), 2);
+    }
+
+    #[test]
+    fn push_clears_redo_stack() {
+        let mut history = History::default();
+        history.push("Move", transforms_command(0));
+        let entry = history.undo_stack.pop().unwrap();
+        history.redo_stack.push(entry);
+
+        history.push("Move", transforms_command(1));
+
+        assert!(history.redo_entries().is_empty());
+    }
+
+    #[test]
+    fn memory_budget_drops_oldest_entries() {
+        let mut history = History::default();
+        let entry_size = transforms_command(0).size();
+        history.memory_budget = 2 * entry_size;
+
+        for i in 0..3 {
+            history.push("Move", transforms_command(i));
+        }
+
+        assert_eq!(history.memory_usage(), 2 * entry_size);
+        let entities: Vec<Entity> = history
+            .undo_entries()
+            .iter()
+            .map(|entry| match &entry.command {
+                Command::Transforms(transforms) => transforms[0].0,
+                _ => panic!("not a transforms command"),
+            })
+            .collect();
+        assert_eq!(entities, vec![Entity::from_raw(1), Entity::from_raw(2)]);
+    }
+
+    #[test]
+    fn memory_budget_keeps_newest_entry() {
+        let mut history = History::default();
+        history.memory_budget = 0;
+
+        history.push("Move", transforms_command(0));
+        history.push("Move", transforms_command(1));
+
+        assert_eq!(history.undo_entries().len(), 1);
+    }
+
+    #[test]
+    fn go_to_sets_pending_steps() {
+        let mut history = History::default();
+        for i in 0..3 {
+            history.push("Move", transforms_command(i));
+        }
+
+        history.go_to(1);
+        assert_eq!(history.pending_steps, -2);
+
+        history.go_to(3);
+        assert_eq!(history.pending_steps, 0);
+
+        let entry = history.undo_stack.pop().unwrap();
+        history.redo_stack.push(entry);
+        history.go_to(3);
+        assert_eq!(history.pending_steps, 1);
+    }
+
+    #[test]
+    fn remap_replaces_entities_of_all_entries() {
+        let mut history = History::default();
+        history.push("Move", transforms_command(0));
+        history.push("Move", transforms_command(1));
+        let entry = history.undo_stack.pop().unwrap();
+        history.redo_stack.push(entry);
+        history.waiting_for_load = true;
+
+        let mut entity_map = HashMap::new();
+        entity_map.insert(Entity::from_raw(0), Entity::from_raw(10));
+        entity_map.insert(Entity::from_raw(1), Entity::from_raw(11));
+        history.remap(&entity_map);
+
+        for (entries, entity) in [
+            (history.undo_entries(), Entity::from_raw(10)),
+            (history.redo_entries(), Entity::from_raw(11)),
+        ] {
+            match &entries[0].command {
+                Command::Transforms(transforms) => assert_eq!(transforms[0].0, entity),
+                _ => panic!("not a transforms command"),
+            }
+        }
+        assert!(!history.waiting_for_load);
+    }
+}
@@ -0,0 +1,448 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use assert::*;
+    use std::f32::consts::PI;
+
+    fn two_link_chain() -> Vec<Transform> {
+        let t1 = Transform::default();
+        let t2 = Transform {
+            translation: Vec3::new(0.0, 1.0, 0.0),
+            ..Default::default()
+        };
+        vec![t2, t1]
+    }
+
+    fn get_tip_with_rotations(mut chain: Vec<Transform>, rotations: &Vec<Quat>) -> Vec2 {
+        for i in 0..chain.len() {
+            chain[i].rotation = rotations[i];
+        }
+        kinematic_chain::get_tip_chain(&chain).truncate()
+    }
+
+    #[test]
+    fn clamp_to_constraint_works() {
+        let c = bone::AngleConstraint {
+            start: 0.0,
+            end: PI / 4.0,
+        };
+
+        assert_quat_eq(
+            &clamp_to_constraint(Quat::from_rotation_z(PI / 8.0), &c),
+            &Quat::from_rotation_z(PI / 8.0),
+        );
+        assert_quat_eq(
+            &clamp_to_constraint(Quat::from_rotation_z(PI / 2.0), &c),
+            &Quat::from_rotation_z(PI / 4.0),
+        );
+    }
+
+    #[test]
+    fn get_joint_positions_works() {
+        let positions = get_joint_positions(&two_link_chain());
+
+        assert_eq!(
+            positions,
+            vec![Vec2::new(0.0, 0.0), Vec2::new(0.0, 1.0), Vec2::new(0.0, 2.0)]
+        );
+    }
+
+    #[test]
+    fn fabrik_reaches_target() {
+        let chain = two_link_chain();
+        let target = Vec2::new(1.0, 1.0);
+
+        let rotations = get_target_rotations_fabrik(chain.clone(), vec![None, None], target, 0.01, 50);
+
+        assert!(get_tip_with_rotations(chain, &rotations).distance(target) <= 0.01);
+    }
+
+    #[test]
+    fn fabrik_stretches_towards_unreachable_target() {
+        let chain = two_link_chain();
+        let target = Vec2::new(4.0, 0.0);
+
+        let rotations = get_target_rotations_fabrik(chain.clone(), vec![None, None], target, 0.01, 50);
+
+        let tip = get_tip_with_rotations(chain, &rotations);
+        assert!(tip.distance(Vec2::new(2.0, 0.0)) <= 0.001);
+    }
+
+    #[test]
+    fn fabrik_keeps_chain_at_target() {
+        let chain = two_link_chain();
+
+        let rotations =
+            get_target_rotations_fabrik(chain.clone(), vec![None, None], Vec2::new(0.0, 2.0), 0.01, 50);
+
+        assert_quat_eq(&rotations[0], &chain[0].rotation);
+        assert_quat_eq(&rotations[1], &chain[1].rotation);
+    }
+
+    #[test]
+    fn fabrik_honors_angle_constraints() {
+        let chain = vec![Transform::default()];
+        let constraint = bone::AngleConstraint {
+            start: 0.0,
+            end: PI / 4.0,
+        };
+
+        let rotations =
+            get_target_rotations_fabrik(chain, vec![Some(constraint)], Vec2::new(-2.0, 0.0), 0.01, 50);
+
+        assert_quat_eq(&rotations[0], &Quat::from_rotation_z(PI / 4.0));
+    }
+
+    #[test]
+    fn two_bone_reaches_target() {
+        let chain = two_link_chain();
+        let target = Vec2::new(1.0, 1.0);
+
+        let rotations = get_target_rotations_two_bone(
+            chain.clone(),
+            vec![None, None],
+            target,
+            BendDirection::CounterClockwise,
+            0.0,
+            0.01,
+            10,
+        );
+
+        assert!(get_tip_with_rotations(chain, &rotations).distance(target) <= 0.001);
+    }
+
+    #[test]
+    fn two_bone_bends_in_bend_direction() {
+        let chain = two_link_chain();
+        let target = Vec2::new(0.0, 1.0);
+
+        // Elbow lies to the right of the line from root to target, if the joint bends counterclockwise
+        let rotations = get_target_rotations_two_bone(
+            chain.clone(),
+            vec![None, None],
+            target,
+            BendDirection::CounterClockwise,
+            0.0,
+            0.01,
+            10,
+        );
+        let mut posed = chain.clone();
+        posed[0].rotation = rotations[0];
+        posed[1].rotation = rotations[1];
+        let elbow = kinematic_chain::get_gl_transform(0, &posed).translation;
+        assert!(elbow.x > 0.0);
+
+        let rotations = get_target_rotations_two_bone(
+            chain.clone(),
+            vec![None, None],
+            target,
+            BendDirection::Clockwise,
+            0.0,
+            0.01,
+            10,
+        );
+        posed[0].rotation = rotations[0];
+        posed[1].rotation = rotations[1];
+        let elbow = kinematic_chain::get_gl_transform(0, &posed).translation;
+        assert!(elbow.x < 0.0);
+    }
+
+    #[test]
+    fn two_bone_soft_limit_prevents_full_extension() {
+        let chain = two_link_chain();
+        let target = Vec2::new(2.0, 0.0);
+
+        let rotations = get_target_rotations_two_bone(
+            chain.clone(),
+            vec![None, None],
+            target,
+            BendDirection::CounterClockwise,
+            0.1,
+            0.01,
+            10,
+        );
+
+        let tip = get_tip_with_rotations(chain, &rotations);
+        assert!(tip.length() < 2.0 - 0.001);
+        assert!(tip.length() > 1.8);
+        assert!(tip.y.abs() <= 0.001);
+    }
+
+    #[test]
+    fn jacobian_honors_angle_constraints() {
+        let chain = vec![Transform::default()];
+        let constraint = bone::AngleConstraint {
+            start: 0.0,
+            end: PI / 4.0,
+        };
+
+        let rotations = get_target_rotations_jacobian(
+            chain,
+            vec![Some(constraint)],
+            Vec2::new(-2.0, 0.0),
+            None,
+            0.01,
+            1.0,
+            50,
+        );
+
+        assert_quat_eq(&rotations[0], &Quat::from_rotation_z(PI / 4.0));
+    }
+
+    #[test]
+    fn jacobian_keeps_constrained_joint_in_range() {
+        let chain = two_link_chain();
+        let constraint = bone::AngleConstraint {
+            start: 0.0,
+            end: 0.1,
+        };
+
+        let rotations = get_target_rotations_jacobian(
+            chain,
+            vec![None, Some(constraint)],
+            Vec2::new(1.0, 1.0),
+            None,
+            0.01,
+            1.0,
+            50,
+        );
+
+        let root_angle = rotations[1].to_euler(EulerRot::XYZ).2;
+        assert!(root_angle >= -0.0001 && root_angle <= 0.1001);
+    }
+
+    /// Target reached with the root at 0.3 and the leaf at 1.0, but the first full step takes the root
+    /// beyond its limit of 0.6
+    fn target_inside_root_limit(leaf_angle: f32) -> (Vec2, bone::AngleConstraint) {
+        let rotations = vec![Quat::from_rotation_z(leaf_angle), Quat::from_rotation_z(0.3)];
+        let constraint = bone::AngleConstraint { start: 0.0, end: 0.6 };
+        (get_tip_with_rotations(two_link_chain(), &rotations), constraint)
+    }
+
+    #[test]
+    fn jacobian_leaves_limit_after_overshooting() {
+        let chain = two_link_chain();
+        let (target, constraint) = target_inside_root_limit(1.0);
+
+        let rotations = get_target_rotations_jacobian(
+            chain.clone(),
+            vec![None, Some(constraint)],
+            target,
+            None,
+            0.01,
+            1.0,
+            50,
+        );
+
+        assert!(get_tip_with_rotations(chain, &rotations).distance(target) <= 0.01);
+        let root_angle = rotations[1].to_euler(EulerRot::XYZ).2;
+        assert!(root_angle >= -0.0001 && root_angle <= 0.6001);
+    }
+
+    #[test]
+    fn dls_reaches_target() {
+        let chain = two_link_chain();
+        let target = Vec2::new(1.0, 1.0);
+
+        let rotations = get_target_rotations_dls(chain.clone(), vec![None, None], target, None, 0.1, 0.01, 100);
+
+        assert!(get_tip_with_rotations(chain, &rotations).distance(target) <= 0.01);
+    }
+
+    #[test]
+    fn dls_is_stable_for_unreachable_target() {
+        let chain = two_link_chain();
+        let target = Vec2::new(0.0, 4.0);
+
+        // Chain is already fully extended towards the target, which is singular for the pseudo inverse
+        let rotations = get_target_rotations_dls(chain.clone(), vec![None, None], target, None, 0.1, 0.01, 100);
+
+        let tip = get_tip_with_rotations(chain, &rotations);
+        assert!(tip.distance(Vec2::new(0.0, 2.0)) <= 0.001);
+    }
+
+    #[test]
+    fn dls_leaves_limit_after_overshooting() {
+        let chain = two_link_chain();
+        let (target, constraint) = target_inside_root_limit(1.5);
+
+        let rotations =
+            get_target_rotations_dls(chain.clone(), vec![None, Some(constraint)], target, None, 0.1, 0.01, 100);
+
+        assert!(get_tip_with_rotations(chain, &rotations).distance(target) <= 0.01);
+    }
+
+    fn get_end_effector_angle_with_rotations(mut chain: Vec<Transform>, rotations: &Vec<Quat>) -> f32 {
+        for i in 0..chain.len() {
+            chain[i].rotation = rotations[i];
+        }
+        get_end_effector_angle(&chain)
+    }
+
+    fn three_link_chain() -> Vec<Transform> {
+        let mut chain = two_link_chain();
+        chain.insert(
+            0,
+            Transform {
+                translation: Vec3::new(0.0, 1.0, 0.0),
+                ..Default::default()
+            },
+        );
+        chain
+    }
+
+    #[test]
+    fn wrap_angle_works() {
+        assert!((wrap_angle(1.5 * PI) - (-0.5 * PI)).abs() < 0.0001);
+        assert!((wrap_angle(-1.5 * PI) - 0.5 * PI).abs() < 0.0001);
+        assert!((wrap_angle(0.25 * PI) - 0.25 * PI).abs() < 0.0001);
+    }
+
+    #[test]
+    fn fabrik_matches_goal_rotation() {
+        let chain = three_link_chain();
+        let target = Vec2::new(1.5, 1.0);
+        let goal_angle = -PI / 2.0;
+
+        let rotations = solve_with_orientation(
+            chain.clone(),
+            vec![None, None, None],
+            target,
+            goal_angle,
+            |chain, constraints, target| get_target_rotations_fabrik(chain, constraints, target, 0.001, 50),
+        );
+
+        assert!(get_tip_with_rotations(chain.clone(), &rotations).distance(target) <= 0.01);
+        assert!(wrap_angle(get_end_effector_angle_with_rotations(chain, &rotations) - goal_angle).abs() <= 0.001);
+    }
+
+    #[test]
+    fn dls_matches_goal_rotation() {
+        let chain = three_link_chain();
+        let target = Vec2::new(1.5, 1.0);
+        let goal_angle = -PI / 2.0;
+
+        let rotations =
+            get_target_rotations_dls(chain.clone(), vec![None, None, None], target, Some(goal_angle), 0.1, 0.001, 200);
+
+        assert!(get_tip_with_rotations(chain.clone(), &rotations).distance(target) <= 0.01);
+        assert!(wrap_angle(get_end_effector_angle_with_rotations(chain, &rotations) - goal_angle).abs() <= 0.01);
+    }
+
+    #[test]
+    fn group_overlapping_chains_works() {
+        let e = |i| Entity::from_raw(i);
+        let chains = vec![vec![e(1), e(2)], vec![e(3)], vec![e(2), e(4)], vec![e(5), e(3)], vec![e(6)]];
+
+        let mut groups = group_overlapping_chains(&chains);
+        groups.sort();
+
+        assert_eq!(groups, vec![vec![0, 2], vec![1, 3], vec![4]]);
+    }
+
+    fn y_shaped_tree() -> JointTree {
+        let arm = Transform {
+            translation: Vec3::new(0.0, 1.0, 0.0),
+            ..Default::default()
+        };
+        JointTree {
+            transforms: vec![Transform::default(), arm, arm],
+            parents: vec![None, Some(0), Some(0)],
+            parent_gl_transforms: vec![Transform::default(); 3],
+            constraints: vec![None, None, None],
+        }
+    }
+
+    fn get_tree_tip_with_rotations(mut tree: JointTree, rotations: &Vec<Quat>, joint: usize) -> Vec2 {
+        for i in 0..tree.transforms.len() {
+            tree.transforms[i].rotation = rotations[i];
+        }
+        kinematic_chain::get_tip(&tree.get_gl_transform(joint)).truncate()
+    }
+
+    #[test]
+    fn stacked_jacobian_reaches_targets_sharing_bones() {
+        let tree = y_shaped_tree();
+        // Both targets can only be reached by bending the spine
+        let left_target = Vec2::new(-0.5045, 1.5553);
+        let right_target = Vec2::new(1.0955, 1.5553);
+        let effectors = vec![
+            Effector {
+                joint: 1,
+                target: left_target,
+                goal_angle: None,
+                priority: 0,
+            },
+            Effector {
+                joint: 2,
+                target: right_target,
+                goal_angle: None,
+                priority: 0,
+            },
+        ];
+
+        for damping in [None, Some(0.01)] {
+            let rotations = get_target_rotations_stacked(tree.clone(), &effectors, damping, 0.001, 200);
+
+            assert!(get_tree_tip_with_rotations(tree.clone(), &rotations, 1).distance(left_target) <= 0.01);
+            assert!(get_tree_tip_with_rotations(tree.clone(), &rotations, 2).distance(right_target) <= 0.01);
+        }
+    }
+
+    #[test]
+    fn stacked_jacobian_prefers_higher_priority() {
+        let tree = JointTree {
+            transforms: vec![Transform::default()],
+            parents: vec![None],
+            parent_gl_transforms: vec![Transform::default()],
+            constraints: vec![None],
+        };
+        let effectors = vec![
+            Effector {
+                joint: 0,
+                target: Vec2::new(-1.0, 0.0),
+                goal_angle: None,
+                priority: 2,
+            },
+            Effector {
+                joint: 0,
+                target: Vec2::new(1.0, 0.0),
+                goal_angle: None,
+                priority: 0,
+            },
+        ];
+
+        let rotations = get_target_rotations_stacked(tree.clone(), &effectors, None, 0.001, 50);
+
+        assert!(get_tree_tip_with_rotations(tree, &rotations, 0).x < -0.9);
+    }
+
+    #[test]
+    fn bend_towards_pole_mirrors_chain() {
+        let mut chain = two_link_chain();
+        chain[1].rotation = Quat::from_rotation_z(0.5);
+        chain[0].rotation = Quat::from_rotation_z(-1.0);
+        let tip = kinematic_chain::get_tip_chain(&chain).truncate();
+
+        // Elbow points to the left, pole lies to the right
+        let rotations = bend_towards_pole(chain.clone(), &vec![None, None], Vec2::new(1.0, 1.0), 2);
+
+        let mut bent = chain.clone();
+        bent[0].rotation = rotations[0];
+        bent[1].rotation = rotations[1];
+        assert!(kinematic_chain::get_tip_chain(&bent).truncate().distance(tip) <= 0.001);
+        assert!(kinematic_chain::get_gl_transform(0, &bent).translation.x > 0.0);
+    }
+
+    #[test]
+    fn bend_towards_pole_keeps_chain_on_pole_side() {
+        let mut chain = two_link_chain();
+        chain[1].rotation = Quat::from_rotation_z(0.5);
+        chain[0].rotation = Quat::from_rotation_z(-1.0);
+
+        let rotations = bend_towards_pole(chain.clone(), &vec![None, None], Vec2::new(-1.0, 1.0), 2);
+
+        assert_quat_eq(&rotations[0], &chain[0].rotation);
+        assert_quat_eq(&rotations[1], &chain[1].rotation);
+    }
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use assert::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn samples_are_spread_evenly_between_first_and_last_keyframe() {
+        let anim = Animation {
+            keyframes: vec![1., 2., 3.],
+            ..Default::default()
+        };
+
+        assert_eq!(get_sample_times(&anim, 2.), vec![1., 1.5, 2., 2.5]);
+    }
+
+    #[test]
+    fn sample_count_is_limited() {
+        let anim = Animation {
+            keyframes: vec![0., 1000.],
+            ..Default::default()
+        };
+
+        let times = get_sample_times(&anim, 30.);
+
+        assert_eq!(times.len(), MAX_SAMPLES);
+        assert!(*times.last().unwrap() < 1000.);
+    }
+
+    #[test]
+    fn closest_keyframe_point_is_grabbed() {
+        let points = vec![Vec2::ZERO, Vec2::new(GRAB_DISTANCE / 2., 0.), Vec2::X];
+
+        assert_eq!(get_grabbed_keyframe(&points, Vec2::new(GRAB_DISTANCE * 0.4, 0.)), Some(1));
+        assert_eq!(get_grabbed_keyframe(&points, Vec2::new(GRAB_DISTANCE * 0.1, 0.)), Some(0));
+        assert_eq!(get_grabbed_keyframe(&points, Vec2::new(0.5, 0.)), None);
+    }
+
+    #[test]
+    fn bone_tip_is_rotated_towards_point() {
+        let gl_transform = Transform {
+            translation: Vec3::new(1., 0., 0.),
+            rotation: Quat::from_rotation_z(PI / 4.),
+            scale: Vec3::new(1., 2., 1.),
+        };
+        // Parent is rotated by 45°, so the local rotation differs from the global one
+        let local_rotation = Quat::IDENTITY;
+
+        let rotation =
+            rotate_tip_towards(local_rotation, &gl_transform, Vec2::new(3., 0.)).unwrap();
+
+        let rotated = Transform {
+            rotation: Quat::from_rotation_z(PI / 4.) * rotation,
+            ..gl_transform
+        };
+        assert_vec2_eq(Bone::get_tip_global(&rotated), Vec2::new(3., 0.));
+    }
+
+    #[test]
+    fn bone_is_not_rotated_towards_its_base() {
+        let gl_transform = Transform::from_xyz(1., 0., 0.);
+
+        assert!(rotate_tip_towards(Quat::IDENTITY, &gl_transform, Vec2::new(1., 0.)).is_none());
+    }
+}
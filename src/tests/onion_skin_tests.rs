@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::animation::ComponentAnimation;
+
+    /// Animation that moves one entity from x = 0 to x = 2 within 2 seconds
+    fn get_animation() -> Animation {
+        let mut anim = Animation {
+            keyframes: vec![0., 1., 2.],
+            ..Default::default()
+        };
+        anim.comp_animations.insert(
+            Entity::from_raw(0),
+            ComponentAnimation {
+                transforms: (0..3).map(|x| Transform::from_xyz(x as f32, 0., 0.)).collect(),
+                interpolation_functions: vec![interpolate::Function::Linear; 3],
+                ..Default::default()
+            },
+        );
+        anim
+    }
+
+    fn get_x(pose: &GhostPose) -> f32 {
+        pose.local_transforms[&Entity::from_raw(0)].translation.x
+    }
+
+    #[test]
+    fn keyframe_ghosts_stop_at_animation_bounds() {
+        let state = State {
+            count_previous: 1,
+            count_next: 2,
+            ..Default::default()
+        };
+
+        let poses = get_ghost_poses(&state, &get_animation(), 1);
+
+        assert_eq!(poses.iter().map(get_x).collect::<Vec<f32>>(), vec![0., 2.]);
+    }
+
+    #[test]
+    fn ghosts_fade_with_distance() {
+        let state = State {
+            count_previous: 0,
+            count_next: 2,
+            ..Default::default()
+        };
+
+        let poses = get_ghost_poses(&state, &get_animation(), 0);
+
+        assert_eq!(poses.iter().map(get_x).collect::<Vec<f32>>(), vec![1., 2.]);
+        assert!((poses[0].color.a() - state.tint_next[3]).abs() < 0.0001);
+        assert!((poses[1].color.a() - state.tint_next[3] / 2.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn time_offset_ghosts_are_sampled_around_keyframe() {
+        let state = State {
+            mode: Mode::TimeOffsets,
+            time_offset: 0.5,
+            ..Default::default()
+        };
+
+        let poses = get_ghost_poses(&state, &get_animation(), 1);
+
+        for (pose, x) in poses.iter().zip([0.5, 1.5]) {
+            assert!((get_x(pose) - x).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn time_offset_ghosts_wrap_around() {
+        let state = State {
+            mode: Mode::TimeOffsets,
+            time_offset: 0.5,
+            count_next: 0,
+            ..Default::default()
+        };
+
+        let poses = get_ghost_poses(&state, &get_animation(), 0);
+
+        assert_eq!(poses.len(), 1);
+        assert!((get_x(&poses[0]) - 1.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn no_ghosts_without_selected_keyframe() {
+        let poses = get_ghost_poses(&State::default(), &get_animation(), 3);
+
+        assert!(poses.is_empty());
+    }
+}
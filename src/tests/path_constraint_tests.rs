@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use assert::*;
+
+    #[test]
+    fn catmull_rom_passes_through_points() {
+        let points = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(3.0, 1.0),
+        ];
+
+        let polyline = sample_spline(&points, SplineType::CatmullRom);
+
+        assert_eq!(polyline.len(), 3 * SAMPLES_PER_SEGMENT + 1);
+        for i in 0..points.len() {
+            assert_vec2_eq(polyline[i * SAMPLES_PER_SEGMENT], points[i]);
+        }
+    }
+
+    #[test]
+    fn bezier_passes_through_end_points_only() {
+        let points = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(2.0, 0.0),
+        ];
+
+        let polyline = sample_spline(&points, SplineType::Bezier);
+
+        assert_vec2_eq(polyline[0], points[0]);
+        assert_vec2_eq(*polyline.last().unwrap(), points[3]);
+        assert_vec2_eq(polyline[SAMPLES_PER_SEGMENT / 2], Vec2::new(1.0, 0.75));
+    }
+
+    #[test]
+    fn get_point_at_length_works() {
+        let polyline = vec![Vec2::new(0.0, 0.0), Vec2::new(0.0, 1.0), Vec2::new(2.0, 1.0)];
+
+        assert_eq!(get_point_at_length(&polyline, 0.5), (0, Vec2::new(0.0, 0.5)));
+        assert_eq!(get_point_at_length(&polyline, 2.0), (1, Vec2::new(1.0, 1.0)));
+        assert_eq!(get_point_at_length(&polyline, 5.0), (1, Vec2::new(2.0, 1.0)));
+    }
+
+    #[test]
+    fn joints_follow_straight_path() {
+        let polyline = vec![Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0)];
+
+        let positions = get_path_joint_positions(&polyline, Vec2::new(2.0, 0.0), 2.0, &vec![1.0, 2.0]);
+
+        assert_vec2_eq(positions[0], Vec2::new(2.0, 0.0));
+        assert_vec2_eq(positions[1], Vec2::new(3.0, 0.0));
+        assert_vec2_eq(positions[2], Vec2::new(5.0, 0.0));
+    }
+
+    #[test]
+    fn joints_continue_behind_end_of_path() {
+        let polyline = vec![Vec2::new(0.0, 0.0), Vec2::new(0.0, 1.0)];
+
+        let positions = get_path_joint_positions(&polyline, Vec2::ZERO, 0.0, &vec![1.0, 1.0]);
+
+        assert_vec2_eq(positions[2], Vec2::new(0.0, 2.0));
+    }
+
+    #[test]
+    fn joints_lie_on_curved_path() {
+        let points = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 2.0),
+            Vec2::new(3.0, 2.0),
+            Vec2::new(4.0, 0.0),
+        ];
+        let polyline = sample_spline(&points, SplineType::CatmullRom);
+        let lengths = vec![1.0, 1.0, 1.0];
+
+        let positions = get_path_joint_positions(&polyline, Vec2::ZERO, 0.0, &lengths);
+
+        for i in 0..lengths.len() {
+            assert!((positions[i].distance(positions[i + 1]) - lengths[i]).abs() <= 0.0001);
+            let distance_to_path = polyline
+                .windows(2)
+                .map(|w| distance_segment_point(w[0], w[1], positions[i + 1]))
+                .fold(f32::MAX, f32::min);
+            assert!(distance_to_path <= 0.0001);
+        }
+    }
+
+    #[test]
+    fn get_closest_length_projects_onto_path() {
+        let polyline = vec![Vec2::new(0.0, 0.0), Vec2::new(0.0, 2.0), Vec2::new(4.0, 2.0)];
+
+        assert!((get_closest_length(&polyline, Vec2::new(-1.0, 1.0)) - 1.0).abs() <= 0.0001);
+        assert!((get_closest_length(&polyline, Vec2::new(3.0, 3.0)) - 5.0).abs() <= 0.0001);
+    }
+
+    #[test]
+    fn root_chain_is_moved_to_position() {
+        let polyline = vec![Vec2::new(0.0, 0.0), Vec2::new(0.0, 2.0), Vec2::new(4.0, 2.0)];
+        let joint_positions = vec![Vec2::new(0.0, 0.0), Vec2::new(0.0, 1.0), Vec2::new(0.0, 2.0)];
+
+        let positions = lay_chain_along_path(&polyline, &joint_positions, 1.0, true);
+
+        assert_vec2_eq(positions[0], Vec2::new(2.0, 2.0));
+        assert_vec2_eq(positions[1], Vec2::new(3.0, 2.0));
+        assert_vec2_eq(positions[2], Vec2::new(4.0, 2.0));
+    }
+
+    #[test]
+    fn attached_chain_follows_path_from_its_first_joint() {
+        let polyline = vec![Vec2::new(0.0, 0.0), Vec2::new(0.0, 2.0), Vec2::new(4.0, 2.0)];
+        let joint_positions = vec![Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), Vec2::new(2.0, 0.0)];
+
+        // The position is ignored, the chain can't leave its parent
+        let positions = lay_chain_along_path(&polyline, &joint_positions, 1.0, false);
+
+        assert_vec2_eq(positions[0], Vec2::new(0.0, 0.0));
+        assert_vec2_eq(positions[1], Vec2::new(0.0, 1.0));
+        assert_vec2_eq(positions[2], Vec2::new(0.0, 2.0));
+    }
+}
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn mirrored_name_swaps_side_words() {
+        assert_eq!(mirrored_name("left_arm"), Some(String::from("right_arm")));
+        assert_eq!(mirrored_name("RightLeg"), Some(String::from("LeftLeg")));
+        assert_eq!(mirrored_name("upperArmLeft"), Some(String::from("upperArmRight")));
+        assert_eq!(mirrored_name("HAND_RIGHT"), Some(String::from("HAND_LEFT")));
+        assert_eq!(mirrored_name("finger left 2"), Some(String::from("finger right 2")));
+    }
+
+    #[test]
+    fn mirrored_name_swaps_side_letters() {
+        assert_eq!(mirrored_name("l_hand"), Some(String::from("r_hand")));
+        assert_eq!(mirrored_name("R.foot"), Some(String::from("L.foot")));
+        assert_eq!(mirrored_name("arm_r"), Some(String::from("arm_l")));
+        assert_eq!(mirrored_name("upper_arm.L"), Some(String::from("upper_arm.R")));
+        assert_eq!(mirrored_name("shin-l"), Some(String::from("shin-r")));
+    }
+
+    #[test]
+    fn mirrored_name_ignores_names_that_only_look_like_sides() {
+        for name in [
+            "tail_base",
+            "spine_lower",
+            "leftover",
+            "cleft",
+            "Leftover",
+            "LEFTOVER",
+            "bright",
+            "ball_root",
+            "neck_lr",
+            "l",
+            "",
+        ] {
+            assert_eq!(mirrored_name(name), None, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    /// A bone with an IK target, whose pole is keyed in the only animation
+    const SNAPSHOT: &str = r#"{
+        "skeleton": {
+            "bones": [
+                {"entity": 1, "parent": null, "translation": [0.0, 0.0, 0.0], "scale": [1.0, 1.0, 1.0], "rotation": [0.0, 0.0, 0.0, 1.0]}
+            ],
+            "skins": [],
+            "targets": [
+                {"opt_ik_method": null, "entity": 2, "bone": 1, "depth": 1, "translation": [0.0, 2.0, 0.0],
+                 "pole": {"entity": 3, "translation": [1.0, 0.0, 0.0]}}
+            ],
+            "skin_mappings": []
+        },
+        "animations": {"map": {"anim_0": {"keyframes": [0.0], "comp_animations": {
+            "3": {"translations": [[1.0, 0.0, 0.0]], "scales": [[1.0, 1.0, 1.0]], "rotations": [[0.0, 0.0, 0.0, 1.0]],
+                  "interpolation_functions": ["Linear"]}
+        }}}},
+        "animation_layers": ["anim_0"],
+        "blending_style": "Layering"
+    }"#;
+
+    /// Spawns every saved entity again with a new id, like `load` does
+    fn respawn(data: &CompleteJson, first_id: u32) -> HashMap<Entity, Entity> {
+        let mut saved_entities: Vec<Entity> = vec![];
+        saved_entities.extend(data.skeleton.bones.iter().map(|bone| bone.entity));
+        saved_entities.extend(data.skeleton.skins.iter().map(|skin| skin.entity));
+        for target in data.skeleton.targets.iter() {
+            saved_entities.push(target.entity);
+            saved_entities.extend(target.pole.as_ref().map(|pole| pole.entity));
+        }
+        for path in data.skeleton.paths.iter() {
+            saved_entities.extend(path.points.iter().map(|point| point.entity));
+        }
+        saved_entities
+            .into_iter()
+            .enumerate()
+            .map(|(i, entity)| (entity, Entity::from_raw(first_id + i as u32)))
+            .collect()
+    }
+
+    /// A bone following a path of two points, the second one is keyed
+    const PATH_SNAPSHOT: &str = r#"{
+        "skeleton": {
+            "bones": [
+                {"entity": 1, "parent": null, "translation": [0.0, 0.0, 0.0], "scale": [1.0, 1.0, 1.0], "rotation": [0.0, 0.0, 0.0, 1.0]}
+            ],
+            "skins": [],
+            "targets": [],
+            "skin_mappings": [],
+            "paths": [
+                {"bone": 1, "spline_type": "CatmullRom", "depth": 1, "position": 0.0, "points": [
+                    {"entity": 4, "translation": [0.0, 0.0, 0.0]},
+                    {"entity": 5, "translation": [2.0, 0.0, 0.0]}
+                ]}
+            ]
+        },
+        "animations": {"map": {"anim_0": {"keyframes": [0.0], "comp_animations": {
+            "5": {"translations": [[2.0, 0.0, 0.0]], "scales": [[1.0, 1.0, 1.0]], "rotations": [[0.0, 0.0, 0.0, 1.0]],
+                  "interpolation_functions": ["Linear"]}
+        }}}},
+        "animation_layers": ["anim_0"],
+        "blending_style": "Layering"
+    }"#;
+
+    #[test]
+    fn pole_animation_survives_reload_after_remap() {
+        let mut snapshot: CompleteJson = serde_json::from_str(SNAPSHOT).unwrap();
+
+        // Load once, which remaps the history's snapshots to the spawned entities
+        let spawned_entities = respawn(&snapshot, 100);
+        snapshot.remap(&spawned_entities);
+        let pole = snapshot.skeleton.targets[0].pole.as_ref().unwrap().entity;
+        assert_eq!(pole, spawned_entities[&Entity::from_raw(3)]);
+
+        // Undo loads the remapped snapshot again
+        let spawned_entities = respawn(&snapshot, 200);
+        let animations = snapshot.animations.as_animations(&spawned_entities);
+        let comp_animations = &animations.map["anim_0"].comp_animations;
+        assert_eq!(comp_animations.len(), 1);
+        assert!(comp_animations.contains_key(&spawned_entities[&pole]));
+    }
+
+    #[test]
+    fn path_survives_reload_after_remap() {
+        let mut snapshot: CompleteJson = serde_json::from_str(PATH_SNAPSHOT).unwrap();
+
+        let spawned_entities = respawn(&snapshot, 100);
+        snapshot.remap(&spawned_entities);
+
+        // The path's bone and points are found among the entities spawned by the next load
+        let spawned_entities = respawn(&snapshot, 200);
+        let path = &snapshot.skeleton.paths[0];
+        assert!(spawned_entities.contains_key(&path.bone));
+        let point = path.points[1].entity;
+        let animations = snapshot.animations.as_animations(&spawned_entities);
+        assert!(animations.map["anim_0"]
+            .comp_animations
+            .contains_key(&spawned_entities[&point]));
+    }
+
+    #[test]
+    fn constraint_target_survives_reload_after_remap() {
+        let mut snapshot: CompleteJson = serde_json::from_str(SNAPSHOT).unwrap();
+        let mut constraint = Constraint::new(constraints::ConstraintType::CopyRotation);
+        constraint.target = Some(Entity::from_raw(2));
+        snapshot.skeleton.bones[0].constraints.push(constraint);
+
+        let spawned_entities = respawn(&snapshot, 100);
+        snapshot.remap(&spawned_entities);
+
+        let target = snapshot.skeleton.bones[0].constraints[0].target.unwrap();
+        assert_eq!(target, spawned_entities[&Entity::from_raw(2)]);
+        assert!(respawn(&snapshot, 200).contains_key(&target));
+    }
+}
@@ -0,0 +1,285 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use assert::*;
+    use std::f32::consts::PI;
+
+    /// Vertex position as computed from positions relative to each bone
+    fn skin_vertex_relative(
+        mapping: &VertexMapping,
+        bone_gl_transforms: &HashMap<Entity, Transform>,
+    ) -> Vec2 {
+        let total_weight: f32 = mapping.weights.iter().sum();
+        let mut position = Vec3::ZERO;
+        for b_i in 0..mapping.bones.len() {
+            let vertex_rel_transform =
+                Transform::from_translation(mapping.rel_positions[b_i].extend(0.));
+            let vertex_gl_transform = combined_transform(
+                &bone_gl_transforms[&mapping.bones[b_i]],
+                &vertex_rel_transform,
+            );
+            position += mapping.weights[b_i] / total_weight * vertex_gl_transform.translation;
+        }
+        position.truncate()
+    }
+
+    /// Position of `gl_position` relative to a bone with the global transform `bone_gl_transform`
+    fn get_rel_position(bone_gl_transform: &Transform, gl_position: Vec2) -> Vec2 {
+        get_relative_transform(
+            bone_gl_transform,
+            &Transform::from_translation(gl_position.extend(0.)),
+        )
+        .translation
+        .truncate()
+    }
+
+    #[test]
+    fn matrices_match_relative_positions() {
+        let parent = Entity::from_raw(0);
+        let child = Entity::from_raw(1);
+        let parent_bind = Transform {
+            translation: Vec3::new(1.0, 2.0, 0.0),
+            rotation: Quat::from_rotation_z(0.3),
+            scale: Vec3::new(1.0, 2.0, 1.0),
+        };
+        let child_bind = combined_transform(
+            &parent_bind,
+            &Transform {
+                translation: Vec3::new(0.0, 1.0, 0.0),
+                rotation: Quat::from_rotation_z(-0.5),
+                scale: Vec3::new(1.0, 0.5, 1.0),
+            },
+        );
+        let parent_posed = Transform {
+            rotation: Quat::from_rotation_z(PI / 2.0),
+            ..parent_bind
+        };
+        let child_posed = combined_transform(
+            &parent_posed,
+            &Transform {
+                translation: Vec3::new(0.0, 1.0, 0.0),
+                rotation: Quat::from_rotation_z(0.8),
+                scale: Vec3::new(1.0, 0.5, 1.0),
+            },
+        );
+        let bind_gl_transforms: HashMap<Entity, Transform> =
+            [(parent, parent_bind), (child, child_bind)].iter().copied().collect();
+        let bone_gl_transforms: HashMap<Entity, Transform> =
+            [(parent, parent_posed), (child, child_posed)].iter().copied().collect();
+
+        let matrices = get_skinning_matrices(&bone_gl_transforms, &bind_gl_transforms);
+
+        for &bind_vertex in [Vec2::new(1.5, 3.0), Vec2::new(-2.0, 0.5), Vec2::new(0.0, 4.0)].iter() {
+            let mapping = VertexMapping {
+                is_free: false,
+                weights: vec![0.25, 0.5],
+                bones: vec![parent, child],
+                rel_positions: vec![
+                    get_rel_position(&parent_bind, bind_vertex),
+                    get_rel_position(&child_bind, bind_vertex),
+                ],
+            };
+            assert_vec2_eq(
+                skin_vertex(&mapping, bind_vertex, &matrices),
+                skin_vertex_relative(&mapping, &bone_gl_transforms),
+            );
+        }
+    }
+
+    #[test]
+    fn bind_pose_keeps_vertices_in_place() {
+        let bone = Entity::from_raw(0);
+        let bind_gl_transform = Transform {
+            translation: Vec3::new(-1.0, 0.5, 0.0),
+            rotation: Quat::from_rotation_z(1.2),
+            scale: Vec3::new(1.0, 3.0, 1.0),
+        };
+        let gl_transforms: HashMap<Entity, Transform> =
+            [(bone, bind_gl_transform)].iter().copied().collect();
+        let mapping = VertexMapping {
+            is_free: false,
+            weights: vec![1.0],
+            bones: vec![bone],
+            rel_positions: vec![],
+        };
+
+        let matrices = get_skinning_matrices(&gl_transforms, &gl_transforms);
+
+        assert_vec2_eq(
+            skin_vertex(&mapping, Vec2::new(2.0, -1.0), &matrices),
+            Vec2::new(2.0, -1.0),
+        );
+    }
+
+    #[test]
+    fn dual_quaternion_matches_matrix_for_single_bone() {
+        let bone = Entity::from_raw(0);
+        let bind_gl_transforms: HashMap<Entity, Transform> = [(
+            bone,
+            Transform {
+                translation: Vec3::new(1.0, -1.0, 0.0),
+                rotation: Quat::from_rotation_z(0.6),
+                scale: Vec3::new(1.0, 2.0, 1.0),
+            },
+        )]
+        .iter()
+        .copied()
+        .collect();
+        let bone_gl_transforms: HashMap<Entity, Transform> = [(
+            bone,
+            Transform {
+                translation: Vec3::new(-2.0, 0.5, 0.0),
+                rotation: Quat::from_rotation_z(-1.1),
+                scale: Vec3::new(1.0, 3.0, 1.0),
+            },
+        )]
+        .iter()
+        .copied()
+        .collect();
+        let mapping = VertexMapping {
+            is_free: false,
+            weights: vec![1.0],
+            bones: vec![bone],
+            rel_positions: vec![],
+        };
+
+        let transforms = get_skinning_transforms(&bone_gl_transforms, &bind_gl_transforms);
+
+        for &bind_vertex in [Vec2::new(1.5, 3.0), Vec2::new(-2.0, 0.5)].iter() {
+            assert_vec2_eq(
+                skin_vertex_dual_quaternion(&mapping, bind_vertex, &transforms.dual_quaternions),
+                skin_vertex(&mapping, bind_vertex, &transforms.matrices),
+            );
+        }
+    }
+
+    #[test]
+    fn dual_quaternion_keeps_volume_when_twisting() {
+        let bones = [Entity::from_raw(0), Entity::from_raw(1)];
+        let bind_gl_transforms: HashMap<Entity, Transform> = bones
+            .iter()
+            .map(|&bone| (bone, Transform::default()))
+            .collect();
+        let bone_gl_transforms: HashMap<Entity, Transform> = [
+            (bones[0], Transform::default()),
+            (bones[1], Transform::from_rotation(Quat::from_rotation_z(0.9 * PI))),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        let mapping = VertexMapping {
+            is_free: false,
+            weights: vec![0.5, 0.5],
+            bones: bones.to_vec(),
+            rel_positions: vec![],
+        };
+
+        let transforms = get_skinning_transforms(&bone_gl_transforms, &bind_gl_transforms);
+
+        // Linear blending collapses the vertex towards the joint
+        let linear = skin_vertex(&mapping, Vec2::new(1.0, 0.0), &transforms.matrices);
+        assert!(linear.length() < 0.2, "{:?}", linear);
+        // Dual quaternions rotate it halfway
+        assert_vec2_eq(
+            skin_vertex_dual_quaternion(&mapping, Vec2::new(1.0, 0.0), &transforms.dual_quaternions),
+            Vec2::from_angle(0.45 * PI),
+        );
+    }
+
+    #[test]
+    fn rebind_keeps_vertices_in_place() {
+        let bones = [Entity::from_raw(0), Entity::from_raw(1)];
+        let bind_gl_transforms: HashMap<Entity, Transform> = bones
+            .iter()
+            .map(|&bone| (bone, Transform::default()))
+            .collect();
+        let bone_gl_transforms: HashMap<Entity, Transform> = [
+            (bones[0], Transform::from_xyz(1.0, 0.0, 0.0)),
+            (
+                bones[1],
+                Transform {
+                    translation: Vec3::new(0.0, 2.0, 0.0),
+                    rotation: Quat::from_rotation_z(0.7),
+                    scale: Vec3::new(1.0, 2.0, 1.0),
+                },
+            ),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        let transforms = get_skinning_transforms(&bone_gl_transforms, &bind_gl_transforms);
+
+        for skinning_method in SkinningMethod::all() {
+            let mapping = VertexMapping {
+                is_free: false,
+                weights: vec![0.3, 0.7],
+                bones: bones.to_vec(),
+                rel_positions: vec![Vec2::ZERO; 2],
+            };
+            let bind_vertex = Vec2::new(1.5, -0.5);
+            let skinned_vertex = match skinning_method {
+                SkinningMethod::Linear => skin_vertex(&mapping, bind_vertex, &transforms.matrices),
+                SkinningMethod::DualQuaternion => {
+                    skin_vertex_dual_quaternion(&mapping, bind_vertex, &transforms.dual_quaternions)
+                }
+            };
+            let mut skin_mapping = SkinMapping {
+                skin: None,
+                vertex_mappings: vec![mapping],
+                bind_vertices: vec![bind_vertex],
+            };
+
+            rebind_vertices(&mut skin_mapping, skinning_method, &transforms, &bone_gl_transforms);
+
+            // The current pose is the new bind pose, so the vertex stays where it is
+            assert_vec2_eq(skin_mapping.bind_vertices[0], skinned_vertex);
+            assert_vec2_eq(
+                skin_vertex_relative(&skin_mapping.vertex_mappings[0], &bone_gl_transforms),
+                skinned_vertex,
+            );
+        }
+    }
+
+    #[test]
+    fn normalize_keeps_zero_weights() {
+        let mut mapping = mapping_with_weights(vec![0.0, 0.0]);
+
+        mapping.normalize();
+
+        assert_eq!(mapping.weights, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn prune_keeps_largest_weight() {
+        let mut mapping = mapping_with_weights(vec![0.02, 0.6, 0.2]);
+        mapping.prune(0.1);
+        assert_eq!(mapping.influence_count(), 2);
+        assert_f32_eq(mapping.weights[1], 0.75);
+
+        let mut mapping = mapping_with_weights(vec![0.02, 0.04]);
+        mapping.prune(0.1);
+        assert_eq!(mapping.weights, vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn limit_influences_keeps_largest_weights() {
+        let mut skin_mapping = SkinMapping {
+            skin: None,
+            vertex_mappings: vec![
+                mapping_with_weights(vec![0.1, 0.4, 0.2, 0.3]),
+                mapping_with_weights(vec![0.5, 0.5]),
+            ],
+            bind_vertices: vec![],
+        };
+        assert_eq!(skin_mapping.count_exceeding_influences(2), 1);
+
+        skin_mapping.limit_influences(2);
+
+        assert_eq!(skin_mapping.count_exceeding_influences(2), 0);
+        let weights = &skin_mapping.vertex_mappings[0].weights;
+        assert_eq!(weights[0], 0.0);
+        assert_eq!(weights[2], 0.0);
+        assert_f32_eq(weights[1], 4.0 / 7.0);
+        assert_eq!(skin_mapping.vertex_mappings[1].weights, vec![0.5, 0.5]);
+    }
+}
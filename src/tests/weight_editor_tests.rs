@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use assert::*;
+
+    #[test]
+    fn paste_adds_missing_bones_and_normalizes() {
+        let mut mapping = mapping_with_weights(vec![0.5, 0.5]);
+        let new_bone = Entity::from_raw(5);
+        let mut rel_positions = HashMap::new();
+        rel_positions.insert(new_bone, Vec2::new(1., 2.));
+
+        set_weights(
+            &mut mapping,
+            &vec![(Entity::from_raw(1), 1.), (new_bone, 3.)],
+            &rel_positions,
+        );
+
+        assert_eq!(mapping.bones[2], new_bone);
+        assert_eq!(mapping.rel_positions[2], Vec2::new(1., 2.));
+        assert_eq!(mapping.weights, vec![0., 0.25, 0.75]);
+        assert_eq!(get_weights(&mapping), vec![(Entity::from_raw(1), 0.25), (new_bone, 0.75)]);
+    }
+
+    #[test]
+    fn paste_without_known_bones_keeps_weights() {
+        let mut mapping = mapping_with_weights(vec![0.5, 0.5]);
+
+        set_weights(&mut mapping, &vec![(Entity::from_raw(5), 1.)], &HashMap::new());
+
+        assert_eq!(mapping.weights, vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn mirror_swaps_bone_pairs() {
+        let (left, right, spine) = (Entity::from_raw(0), Entity::from_raw(1), Entity::from_raw(2));
+        let mut mirrored_bones = HashMap::new();
+        mirrored_bones.insert(left, right);
+        mirrored_bones.insert(right, left);
+
+        let weights = mirror_weights(&vec![(left, 0.7), (spine, 0.3)], &mirrored_bones);
+
+        assert_eq!(weights, vec![(right, 0.7), (spine, 0.3)]);
+    }
+
+    #[test]
+    fn find_vertex_on_opposite_side() {
+        let vertices = vec![
+            Vec2::new(-1., 2.),
+            Vec2::new(0., 0.),
+            Vec2::new(1.02, 2.),
+            Vec2::new(0.5, -2.),
+        ];
+
+        assert_eq!(find_mirrored_vertex(&vertices, 0, MirrorAxis::Y), Some(2));
+        assert_eq!(find_mirrored_vertex(&vertices, 1, MirrorAxis::Y), Some(1));
+        assert_eq!(find_mirrored_vertex(&vertices, 3, MirrorAxis::Y), None);
+        assert_eq!(find_mirrored_vertex(&vertices, 0, MirrorAxis::X), None);
+    }
+
+    #[test]
+    fn find_vertex_of_off_center_skin() {
+        // Symmetric about x = 5 and y = 3
+        let vertices = vec![
+            Vec2::new(4., 1.),
+            Vec2::new(6., 1.),
+            Vec2::new(4., 5.),
+            Vec2::new(6., 5.),
+            Vec2::new(5., 3.),
+        ];
+
+        assert_eq!(get_center(&vertices), Vec2::new(5., 3.));
+        assert_eq!(find_mirrored_vertex(&vertices, 0, MirrorAxis::Y), Some(1));
+        assert_eq!(find_mirrored_vertex(&vertices, 3, MirrorAxis::Y), Some(2));
+        assert_eq!(find_mirrored_vertex(&vertices, 0, MirrorAxis::X), Some(2));
+        assert_eq!(find_mirrored_vertex(&vertices, 4, MirrorAxis::X), Some(4));
+    }
+}
@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use assert::*;
+
+    #[test]
+    fn falloff_is_full_at_center_and_zero_at_border() {
+        for falloff in BrushFalloff::all() {
+            assert_eq!(falloff.get_factor(0., 2.), 1.);
+            assert_eq!(falloff.get_factor(2., 2.), 0.);
+            let half = falloff.get_factor(1., 2.);
+            assert!(half > 0. && half <= 1.);
+        }
+        assert_eq!(BrushFalloff::Linear.get_factor(1.5, 2.), 0.25);
+        assert_eq!(BrushFalloff::Smooth.get_factor(1., 2.), 0.5);
+    }
+
+    #[test]
+    fn brush_modes() {
+        assert_f32_eq(apply_brush(BrushMode::Add, 0.5, 1., 0.25, 0.), 0.75);
+        assert_f32_eq(apply_brush(BrushMode::Add, 0.9, 1., 0.5, 0.), 1.);
+        assert_f32_eq(apply_brush(BrushMode::Subtract, 0.5, 1., 0.25, 0.), 0.25);
+        assert_f32_eq(apply_brush(BrushMode::Replace, 0.2, 0.6, 0.5, 0.), 0.4);
+        assert_f32_eq(apply_brush(BrushMode::Smooth, 0.2, 1., 0.5, 0.6), 0.4);
+    }
+
+    #[test]
+    fn set_weight_renormalizes_other_influences() {
+        let mut mapping = mapping_with_weights(vec![0.5, 0.3, 0.2]);
+
+        set_weight(&mut mapping, Entity::from_raw(0), 0.75, Vec2::ZERO);
+
+        assert_eq!(mapping.weights[0], 0.75);
+        assert_f32_eq(mapping.weights[1], 0.15);
+        assert_f32_eq(mapping.weights[2], 0.1);
+    }
+
+    #[test]
+    fn set_weight_adds_new_bone() {
+        let mut mapping = mapping_with_weights(vec![1.]);
+        let rel_position = Vec2::new(0.5, -1.);
+
+        set_weight(&mut mapping, Entity::from_raw(7), 0.25, rel_position);
+
+        assert_eq!(mapping.bones[1], Entity::from_raw(7));
+        assert_eq!(mapping.rel_positions[1], rel_position);
+        assert_eq!(mapping.weights, vec![0.75, 0.25]);
+    }
+
+    #[test]
+    fn set_weight_moves_removed_weight_to_closest_bone() {
+        let mut mapping = mapping_with_weights(vec![1., 0., 0.]);
+        mapping.rel_positions = vec![Vec2::ZERO, Vec2::new(3., 0.), Vec2::new(0., 1.)];
+
+        assert!(set_weight(&mut mapping, Entity::from_raw(0), 0.25, Vec2::ZERO));
+
+        assert_eq!(mapping.weights, vec![0.25, 0., 0.75]);
+    }
+
+    #[test]
+    fn set_weight_keeps_only_bone() {
+        let mut mapping = mapping_with_weights(vec![1.]);
+
+        assert!(!can_lower_weight(&mapping, Entity::from_raw(0)));
+        assert!(!set_weight(&mut mapping, Entity::from_raw(0), 0.3, Vec2::ZERO));
+
+        assert_eq!(mapping.weights, vec![1.]);
+        assert!(set_weight(&mut mapping, Entity::from_raw(0), 1., Vec2::ZERO));
+    }
+
+    #[test]
+    fn neighbors_of_quad() {
+        let neighbors = get_neighbors(&vec![0, 1, 2, 0, 2, 3], 5);
+
+        assert_eq!(neighbors[0], vec![1, 2, 3]);
+        assert_eq!(neighbors[1], vec![0, 2]);
+        assert!(neighbors[4].is_empty());
+    }
+}
@@ -54,6 +54,9 @@ pub enum Action {
 pub struct Transformable {
     pub is_selected: bool,
     pub is_part_of_layer: bool,
+    /// Hidden and locked transformables can't be selected
+    pub is_hidden: bool,
+    pub is_locked: bool,
     pub translatable: bool,
     pub rotatable: bool,
     pub scalable: bool,
@@ -64,6 +67,8 @@ impl Default for Transformable {
         Self {
             is_selected: true,
             is_part_of_layer: false,
+            is_hidden: false,
+            is_locked: false,
             translatable: true,
             rotatable: true,
             scalable: true,
@@ -76,6 +81,9 @@ impl Transformable {
         self.collision_shape = shape;
         self
     }
+    pub fn is_selectable(&self) -> bool {
+        !self.is_hidden && !self.is_locked
+    }
 }
 
 #[derive(PartialEq)]
@@ -105,7 +113,7 @@ pub fn start_action(
     cursor_pos: Res<CursorPos>,
     mut state: ResMut<State>,
     keys: Res<Input<KeyCode>>,
-    q: Query<&Transform, With<Transform>>,
+    q: Query<(&Transform, &Transformable)>,
 ) {
     // // // WIP
     // // // Currently doesn't work with parent-child-hierarchies
@@ -142,13 +150,16 @@ pub fn start_action(
     // Store cursor position at moment action is started
     state.cursor_anchor = cursor_pos.0;
     // Store selected entities' transforms at moment action is started
+    // Hidden and locked entities are skipped, in case they are still selected
     state.original_transforms.clear();
     for e in state.selected_entities.clone() {
-        let transform = q.get(e).unwrap();
-        state.original_transforms.insert(e, transform.clone());
+        let (transform, transformable) = q.get(e).unwrap();
+        if transformable.is_selectable() {
+            state.original_transforms.insert(e, transform.clone());
+        }
     }
     // Don't start action if no transformables are selected
-    if state.selected_entities.len() == 0 {
+    if state.original_transforms.len() == 0 {
         state.action = Action::None;
     }
 }
@@ -273,13 +284,15 @@ pub fn remove(
     mut state: ResMut<State>,
     mut skeleton: ResMut<skeleton::Skeleton>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut history: ResMut<history::History>,
 ) {
     // Remove transformable only if DELETE was pressed
     if !keys.just_released(KeyCode::Delete) {
         return;
     }
     for (entity, transformable, skin, bone) in q.iter() {
-        if transformable.is_selected {
+        if transformable.is_selected && transformable.is_selectable() {
+            history.record_snapshot("Delete");
             commands.entity(entity).despawn_recursive();
             state.selected_entities.retain(|&e| e != entity);
             if let Some(skin) = skin {
@@ -289,10 +302,30 @@ pub fn remove(
     }
 }
 
-pub fn complete_action(mouse: Res<Input<MouseButton>>, mut state: ResMut<State>) {
+pub fn complete_action(
+    mouse: Res<Input<MouseButton>>,
+    mut state: ResMut<State>,
+    mut history: ResMut<history::History>,
+    q: Query<&Transform>,
+) {
     // If current action is a transformation finnish this action
     if state.action != Action::None && state.action != Action::Done {
         if mouse.just_released(MouseButton::Left) {
+            // Add transformation to history
+            let changes = state
+                .original_transforms
+                .iter()
+                .filter_map(|(&entity, &before)| match q.get(entity) {
+                    Ok(&after) if after != before => Some((entity, before, after)),
+                    _ => None,
+                })
+                .collect::<Vec<(Entity, Transform, Transform)>>();
+            if !changes.is_empty() {
+                history.push(
+                    &format!("{:?}", state.action),
+                    history::Command::Transforms(changes),
+                );
+            }
             state.action = Action::Done
         }
     // Otherwise set state.action to None in case it was Done
@@ -342,6 +375,14 @@ pub fn select(
     cursor_pos: Res<CursorPos>,
     mut q: Query<(&GlobalTransform, &mut Transformable, Entity)>,
 ) {
+    // Hidden and locked transformables can't stay selected, e.g. after loading or undoing
+    for (_, mut transformable, entity) in q.iter_mut() {
+        if transformable.is_selected && !transformable.is_selectable() {
+            transformable.is_selected = false;
+            state.selected_entities.remove(&entity);
+        }
+    }
+
     // Select/Unselect only if conditions are fulfilled
     if !mouse.just_released(MouseButton::Left)
         || state.action != Action::None
@@ -355,6 +396,9 @@ pub fn select(
     if !state.drag_select {
         let mut shortest_distance = 999.;
         for (gl_transform, transformable, entity) in q.iter_mut() {
+            if !transformable.is_selectable() {
+                continue;
+            }
             let distance: f32 =
                 if let PhantomShape::Rectangle(min, max) = transformable.collision_shape {
                     Vec2::distance((min + max) / 2., cursor_pos.0)
@@ -406,6 +450,9 @@ pub fn select(
         }
     } else {
         for (gl_transform, transformable, entity) in q.iter_mut() {
+            if !transformable.is_selectable() {
+                continue;
+            }
             let center: Vec2 =
                 if let PhantomShape::Rectangle(min, max) = transformable.collision_shape {
                     (min + max) / 2.
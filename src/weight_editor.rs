@@ -0,0 +1,368 @@
+use crate::{
+    bone::Bone,
+    pose::MirrorAxis,
+    skeleton::{SkinMapping, VertexMapping},
+    skin::Skin,
+    *,
+};
+use bevy::utils::HashMap;
+
+#[cfg(test)]
+#[path = "tests/weight_editor_tests.rs"]
+mod weight_editor_tests;
+
+/// Maximum distance between the cursor and a vertex to select it
+const VERTEX_SELECT_DISTANCE: f32 = 0.15;
+/// Maximum distance between a vertex's mirrored position and the vertex on the opposite side
+const MIRROR_TOLERANCE: f32 = 0.1;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SelectedVertex {
+    pub skin: Entity,
+    pub index: usize,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    Copy,
+    Paste,
+    Mirror,
+}
+
+pub struct State {
+    pub selected_vertices: Vec<SelectedVertex>,
+    pub mirror_axis: MirrorAxis,
+    pub pending_action: Option<Action>,
+    clipboard: Vec<(Entity, f32)>,
+}
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            selected_vertices: vec![],
+            mirror_axis: MirrorAxis::Y,
+            pending_action: None,
+            clipboard: vec![],
+        }
+    }
+}
+impl State {
+    pub fn has_copied_weights(&self) -> bool {
+        !self.clipboard.is_empty()
+    }
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new()
+        .with_system(select_vertices)
+        .with_system(apply_pending_action.after(select_vertices))
+}
+
+/// Bones and weights of a vertex, leaving out bones without weight
+pub fn get_weights(mapping: &VertexMapping) -> Vec<(Entity, f32)> {
+    mapping
+        .bones
+        .iter()
+        .zip(mapping.weights.iter())
+        .filter(|(_, weight)| **weight > 0.)
+        .map(|(&bone, &weight)| (bone, weight))
+        .collect()
+}
+
+/// Replaces the weights of a vertex and normalizes them. Bones, that don't influence the vertex yet, are
+/// added with their position from `rel_positions` or skipped, if it is missing.
+pub fn set_weights(
+    mapping: &mut VertexMapping,
+    weights: &Vec<(Entity, f32)>,
+    rel_positions: &HashMap<Entity, Vec2>,
+) {
+    let weights_before = mapping.weights.clone();
+    for weight in mapping.weights.iter_mut() {
+        *weight = 0.;
+    }
+    for &(bone, weight) in weights.iter() {
+        if let Some(index) = mapping.bones.iter().position(|&b| b == bone) {
+            mapping.weights[index] += weight;
+        } else if let Some(&rel_position) = rel_positions.get(&bone) {
+            mapping.bones.push(bone);
+            mapping.weights.push(weight);
+            mapping.rel_positions.push(rel_position);
+        }
+    }
+    // None of the bones could be added
+    if mapping.weights.iter().all(|&weight| weight == 0.) {
+        mapping.weights = weights_before;
+        return;
+    }
+    mapping.normalize();
+}
+
+/// Replaces each bone with its counterpart on the opposite side. Bones without a counterpart, e.g. the
+/// spine, keep their weights.
+pub fn mirror_weights(
+    weights: &Vec<(Entity, f32)>,
+    mirrored_bones: &HashMap<Entity, Entity>,
+) -> Vec<(Entity, f32)> {
+    let mut mirrored: Vec<(Entity, f32)> = vec![];
+    for &(bone, weight) in weights.iter() {
+        let mirrored_bone = *mirrored_bones.get(&bone).unwrap_or(&bone);
+        match mirrored.iter_mut().find(|(b, _)| *b == mirrored_bone) {
+            Some((_, mirrored_weight)) => *mirrored_weight += weight,
+            None => mirrored.push((mirrored_bone, weight)),
+        }
+    }
+    mirrored
+}
+
+/// Center of the bounding box of `vertices`, which a symmetric skin is mirrored about
+pub fn get_center(vertices: &Vec<Vec2>) -> Vec2 {
+    if vertices.is_empty() {
+        return Vec2::ZERO;
+    }
+    let min = vertices.iter().fold(Vec2::splat(f32::MAX), |min, &v| min.min(v));
+    let max = vertices.iter().fold(Vec2::splat(f32::MIN), |max, &v| max.max(v));
+    (min + max) / 2.
+}
+
+/// Mirrors `position` across the line through `pivot` along `axis`
+pub fn mirror_position(position: Vec2, axis: MirrorAxis, pivot: Vec2) -> Vec2 {
+    match axis {
+        MirrorAxis::X => Vec2::new(position.x, 2. * pivot.y - position.y),
+        MirrorAxis::Y => Vec2::new(2. * pivot.x - position.x, position.y),
+    }
+}
+
+/// Index of the vertex closest to the mirrored position of vertex `index`, if it is close enough. The skin
+/// is mirrored about its own center, so it doesn't need to sit on the world axis.
+pub fn find_mirrored_vertex(vertices: &Vec<Vec2>, index: usize, axis: MirrorAxis) -> Option<usize> {
+    let mirrored = mirror_position(*vertices.get(index)?, axis, get_center(vertices));
+    let mut closest: Option<(usize, f32)> = None;
+    for (i, vertex) in vertices.iter().enumerate() {
+        let distance = vertex.distance(mirrored);
+        if distance <= MIRROR_TOLERANCE && closest.map_or(true, |(_, d)| distance < d) {
+            closest = Some((i, distance));
+        }
+    }
+    closest.map(|(i, _)| i)
+}
+
+/// Positions of a bind pose vertex relative to each bone
+fn get_rel_positions(
+    bind_gl_transforms: &HashMap<Entity, Transform>,
+    bind_vertex: Vec2,
+) -> HashMap<Entity, Vec2> {
+    bind_gl_transforms
+        .iter()
+        .map(|(&bone, bind_gl_transform)| {
+            let rel_transform = transform::get_relative_transform(
+                bind_gl_transform,
+                &Transform::from_translation(bind_vertex.extend(0.)),
+            );
+            (bone, rel_transform.translation.truncate())
+        })
+        .collect()
+}
+
+/// Selects the vertex of a bound skin closest to the cursor on click while **V** is held. **LShift** adds to
+/// or removes from the selection.
+pub fn select_vertices(
+    meshes: Res<Assets<Mesh>>,
+    skeleton: Res<skeleton::Skeleton>,
+    mut state: ResMut<State>,
+    mut transform_state: ResMut<transform::State>,
+    egui_state: Res<egui::State>,
+    gpu_state: Res<gpu_skinning::State>,
+    skinning_materials: Res<Assets<gpu_skinning::SkinningMaterial>>,
+    cursor_pos: Res<CursorPos>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    q_skins: Query<&Skin>,
+) {
+    // Forget vertices of skins, that were unbound or removed
+    state.selected_vertices.retain(|selected| {
+        skeleton.skin_mappings.iter().any(|skin_mapping| {
+            skin_mapping.skin == Some(selected.skin)
+                && selected.index < skin_mapping.vertex_mappings.len()
+        })
+    });
+
+    if !keys.pressed(KeyCode::V)
+        || keys.pressed(KeyCode::LControl)
+        || egui_state.ui_hover
+        || (transform_state.action != transform::Action::None
+            && transform_state.action != transform::Action::Done)
+    {
+        return;
+    }
+    if !mouse.pressed(MouseButton::Left) && !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+    // Prevent selecting entities or drag selecting
+    transform_state.action = transform::Action::Done;
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+
+    let mut closest: Option<(SelectedVertex, f32)> = None;
+    for skin_mapping in skeleton.skin_mappings.iter() {
+        let skin_entity = match skin_mapping.skin {
+            Some(entity) if !skin_mapping.vertex_mappings.is_empty() => entity,
+            _ => continue,
+        };
+        let mesh = match q_skins
+            .get(skin_entity)
+            .ok()
+            .and_then(|skin| meshes.get(&skin.mesh_handle.clone().unwrap().0))
+        {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        let vertices =
+            gpu_skinning::get_vertices(mesh, skin_entity, &gpu_state, &skinning_materials);
+        for (index, vertex) in vertices.iter().enumerate() {
+            let distance = vertex.truncate().distance(cursor_pos.0);
+            if distance <= VERTEX_SELECT_DISTANCE && closest.map_or(true, |(_, d)| distance < d)
+            {
+                closest = Some((
+                    SelectedVertex {
+                        skin: skin_entity,
+                        index,
+                    },
+                    distance,
+                ));
+            }
+        }
+    }
+
+    let add_to_selection = keys.pressed(KeyCode::LShift);
+    if !add_to_selection {
+        state.selected_vertices.clear();
+    }
+    if let Some((vertex, _)) = closest {
+        if let Some(i) = state.selected_vertices.iter().position(|&v| v == vertex) {
+            state.selected_vertices.remove(i);
+        } else {
+            state.selected_vertices.push(vertex);
+        }
+    }
+}
+
+/// Copies the weights of the first selected vertex, pastes them into all selected vertices or mirrors
+/// the weights of the selected vertices onto the vertices on the opposite side.
+pub fn apply_pending_action(
+    mut state: ResMut<State>,
+    mut skeleton: ResMut<skeleton::Skeleton>,
+    mut history: ResMut<history::History>,
+    q_bones: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    q_bone_entities: Query<(Entity, &Bone)>,
+) {
+    let action = match state.pending_action.take() {
+        Some(action) => action,
+        None => return,
+    };
+    let mapping_index = |skeleton: &skeleton::Skeleton, skin: Entity| {
+        skeleton
+            .skin_mappings
+            .iter()
+            .position(|skin_mapping| skin_mapping.skin == Some(skin))
+    };
+
+    if action == Action::Copy {
+        let first = match state.selected_vertices.first() {
+            Some(&first) => first,
+            None => return,
+        };
+        if let Some(i) = mapping_index(&skeleton, first.skin) {
+            state.clipboard = get_weights(&skeleton.skin_mappings[i].vertex_mappings[first.index]);
+        }
+        return;
+    }
+
+    let bind_gl_transforms = skeleton::get_bind_gl_transforms(&q_bones, &q_bone_entities);
+    let weights_before: Vec<SkinMapping> = skeleton.skin_mappings.clone();
+    let name = match action {
+        Action::Paste => {
+            for selected in state.selected_vertices.iter() {
+                let skin_mapping = match mapping_index(&skeleton, selected.skin) {
+                    Some(i) => &mut skeleton.skin_mappings[i],
+                    None => continue,
+                };
+                let bind_vertex = skin_mapping
+                    .bind_vertices
+                    .get(selected.index)
+                    .copied()
+                    .unwrap_or_default();
+                set_weights(
+                    &mut skin_mapping.vertex_mappings[selected.index],
+                    &state.clipboard,
+                    &get_rel_positions(&bind_gl_transforms, bind_vertex),
+                );
+            }
+            "Paste weights"
+        }
+        Action::Mirror => {
+            // Bones without a counterpart are mirrored onto themselves
+            let mut mirrored_bones: HashMap<Entity, Entity> = HashMap::new();
+            for (entity, bone) in q_bone_entities.iter() {
+                let opt_mirrored_name = pose::mirrored_name(&bone.name);
+                if let Some((mirrored_entity, _)) = q_bone_entities
+                    .iter()
+                    .find(|(_, other)| Some(&other.name) == opt_mirrored_name.as_ref())
+                {
+                    mirrored_bones.insert(entity, mirrored_entity);
+                }
+            }
+
+            for selected in state.selected_vertices.iter() {
+                let skin_mapping = match mapping_index(&skeleton, selected.skin) {
+                    Some(i) => &mut skeleton.skin_mappings[i],
+                    None => continue,
+                };
+                let mirrored_index = match find_mirrored_vertex(
+                    &skin_mapping.bind_vertices,
+                    selected.index,
+                    state.mirror_axis,
+                ) {
+                    Some(index) if index != selected.index => index,
+                    // Vertices on the axis are their own counterpart
+                    Some(_) => continue,
+                    None => {
+                        println!(
+                            "apply_pending_action: No vertex found on the opposite side of vertex {}",
+                            selected.index
+                        );
+                        continue;
+                    }
+                };
+                let weights = mirror_weights(
+                    &get_weights(&skin_mapping.vertex_mappings[selected.index]),
+                    &mirrored_bones,
+                );
+                set_weights(
+                    &mut skin_mapping.vertex_mappings[mirrored_index],
+                    &weights,
+                    &get_rel_positions(
+                        &bind_gl_transforms,
+                        skin_mapping.bind_vertices[mirrored_index],
+                    ),
+                );
+            }
+            "Mirror weights"
+        }
+        Action::Copy => return,
+    };
+
+    if skeleton.skin_mappings.iter().zip(weights_before.iter()).any(|(a, b)| {
+        a.vertex_mappings
+            .iter()
+            .zip(b.vertex_mappings.iter())
+            .any(|(a, b)| a.weights != b.weights)
+    }) {
+        history.push(
+            name,
+            history::Command::Weights {
+                before: weights_before,
+                after: skeleton.skin_mappings.clone(),
+            },
+        );
+    }
+}
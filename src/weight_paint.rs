@@ -0,0 +1,575 @@
+use crate::{
+    bone::Bone,
+    skeleton::{SkinMapping, VertexMapping},
+    skin::Skin,
+    *,
+};
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    reflect::TypeUuid,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+        },
+    },
+    sprite::{Material2d, Material2dKey, MaterialMesh2dBundle},
+};
+use std::cmp;
+
+#[cfg(test)]
+#[path = "tests/weight_paint_tests.rs"]
+mod weight_paint_tests;
+
+/// Heatmaps are drawn slightly in front of the skins
+const HEATMAP_DEPTH: f32 = 0.05;
+/// Distance between two dabs of a stroke, relative to the brush size
+const DAB_SPACING: f32 = 0.25;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum BrushMode {
+    /// Adds the brush weight
+    Add,
+    /// Subtracts the brush weight
+    Subtract,
+    /// Blends towards the brush weight
+    Replace,
+    /// Blends towards the average weight of the neighboring vertices
+    Smooth,
+}
+impl BrushMode {
+    pub fn all() -> impl ExactSizeIterator<Item = BrushMode> {
+        [Self::Add, Self::Subtract, Self::Replace, Self::Smooth]
+            .iter()
+            .copied()
+    }
+}
+impl ToString for BrushMode {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Add => String::from("Add"),
+            Self::Subtract => String::from("Subtract"),
+            Self::Replace => String::from("Replace"),
+            Self::Smooth => String::from("Smooth"),
+        }
+    }
+}
+
+/// How the brush's influence decreases from its center to its border
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum BrushFalloff {
+    Constant,
+    Linear,
+    Smooth,
+    Sphere,
+}
+impl BrushFalloff {
+    pub fn all() -> impl ExactSizeIterator<Item = BrushFalloff> {
+        [Self::Constant, Self::Linear, Self::Smooth, Self::Sphere]
+            .iter()
+            .copied()
+    }
+    /// Influence at `distance` from the center of a brush of radius `size`, between 0 and 1
+    pub fn get_factor(&self, distance: f32, size: f32) -> f32 {
+        if distance >= size {
+            return 0.;
+        }
+        let t = distance / size;
+        match self {
+            Self::Constant => 1.,
+            Self::Linear => 1. - t,
+            Self::Smooth => 1. - t * t * (3. - 2. * t),
+            Self::Sphere => f32::sqrt(1. - t * t),
+        }
+    }
+}
+impl ToString for BrushFalloff {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Constant => String::from("Constant"),
+            Self::Linear => String::from("Linear"),
+            Self::Smooth => String::from("Smooth"),
+            Self::Sphere => String::from("Sphere"),
+        }
+    }
+}
+
+pub struct State {
+    pub mode: BrushMode,
+    pub falloff: BrushFalloff,
+    /// Influence of a single dab at the brush's center
+    pub strength: f32,
+    /// Weight, that is added, subtracted or blended towards
+    pub weight: f32,
+    pub show_heatmap: bool,
+    /// Whether the current stroke tried to lower the weight of a vertex's only bone
+    pub has_locked_vertices: bool,
+    heatmaps: Vec<Heatmap>,
+}
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            mode: BrushMode::Add,
+            falloff: BrushFalloff::Smooth,
+            strength: 0.5,
+            weight: 1.,
+            show_heatmap: true,
+            has_locked_vertices: false,
+            heatmaps: vec![],
+        }
+    }
+}
+
+/// Weights before the current stroke and where the last dab was painted
+pub struct Stroke {
+    weights_before: Vec<SkinMapping>,
+    last_dab: Vec2,
+}
+
+/// A copy of a skin's mesh, colored by the weights of the selected bone, reused every frame
+struct Heatmap {
+    entity: Entity,
+    mesh_handle: Handle<Mesh>,
+}
+
+#[derive(Component)]
+pub struct WeightHeatmap;
+
+/// Material drawing a mesh with its vertex colors
+#[derive(AsBindGroup, TypeUuid, Clone)]
+#[uuid = "8f2d5b7a-1c4e-4a39-b6d0-5e7c9a3f2b18"]
+pub struct HeatmapMaterial {
+    #[uniform(0)]
+    pub tint: Color,
+}
+impl Material2d for HeatmapMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/heatmap.wgsl".into()
+    }
+    fn fragment_shader() -> ShaderRef {
+        "shaders/heatmap.wgsl".into()
+    }
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(1),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+pub fn system_set() -> SystemSet {
+    SystemSet::new().with_system(paint_weights)
+}
+
+/// Indices of the vertices sharing a triangle with each vertex
+pub fn get_neighbors(indices: &Vec<u16>, vertex_count: usize) -> Vec<Vec<usize>> {
+    let mut neighbors: Vec<Vec<usize>> = vec![vec![]; vertex_count];
+    for triangle in indices.chunks_exact(3) {
+        for k in 0..3 {
+            let (a, b) = (triangle[k] as usize, triangle[(k + 1) % 3] as usize);
+            if !neighbors[a].contains(&b) {
+                neighbors[a].push(b);
+            }
+            if !neighbors[b].contains(&a) {
+                neighbors[b].push(a);
+            }
+        }
+    }
+    neighbors
+}
+
+pub fn get_weight(mapping: &VertexMapping, bone: Entity) -> f32 {
+    match mapping.bones.iter().position(|&b| b == bone) {
+        Some(index) => mapping.weights[index],
+        None => 0.,
+    }
+}
+
+/// Sets the weight of `bone` and scales the other weights, so that all weights sum up to 1. The bone is
+/// added with `rel_position`, if it doesn't influence the vertex yet.
+///
+/// If no other bone has weight, the removed weight goes to the closest of the vertex's other bones. Returns
+/// false, if there is none, in which case `bone` keeps the full weight.
+pub fn set_weight(
+    mapping: &mut VertexMapping,
+    bone: Entity,
+    weight: f32,
+    rel_position: Vec2,
+) -> bool {
+    let index = match mapping.bones.iter().position(|&b| b == bone) {
+        Some(index) => index,
+        None => {
+            mapping.bones.push(bone);
+            mapping.weights.push(0.);
+            mapping.rel_positions.push(rel_position);
+            mapping.bones.len() - 1
+        }
+    };
+    let weight = weight.clamp(0., 1.);
+    let others_total: f32 = mapping.weights.iter().sum::<f32>() - mapping.weights[index];
+    if others_total <= 0. {
+        let opt_fallback = if weight < 1. {
+            get_fallback_index(mapping, index)
+        } else {
+            None
+        };
+        for other_weight in mapping.weights.iter_mut() {
+            *other_weight = 0.;
+        }
+        return match opt_fallback {
+            Some(fallback) => {
+                mapping.weights[index] = weight;
+                mapping.weights[fallback] = 1. - weight;
+                true
+            }
+            None => {
+                mapping.weights[index] = 1.;
+                weight == 1.
+            }
+        };
+    }
+    for (i, other_weight) in mapping.weights.iter_mut().enumerate() {
+        if i == index {
+            *other_weight = weight;
+        } else {
+            *other_weight *= (1. - weight) / others_total;
+        }
+    }
+    true
+}
+
+/// Whether the weight of `bone` can be lowered, i.e. the vertex has other bones to receive the weight
+pub fn can_lower_weight(mapping: &VertexMapping, bone: Entity) -> bool {
+    match mapping.bones.iter().position(|&b| b == bone) {
+        Some(index) => get_fallback_index(mapping, index).is_some(),
+        None => true,
+    }
+}
+
+/// Index of the bone other than `index`, that is closest to the vertex in the bind pose
+fn get_fallback_index(mapping: &VertexMapping, index: usize) -> Option<usize> {
+    (0..mapping.bones.len())
+        .filter(|&i| i != index)
+        .min_by(|&a, &b| {
+            mapping.rel_positions[a]
+                .length()
+                .partial_cmp(&mapping.rel_positions[b].length())
+                .unwrap_or(cmp::Ordering::Equal)
+        })
+}
+
+/// New weight of a vertex for a dab with influence `factor`. `neighbor_average` is only used by
+/// [`BrushMode::Smooth`].
+pub fn apply_brush(
+    mode: BrushMode,
+    weight: f32,
+    brush_weight: f32,
+    factor: f32,
+    neighbor_average: f32,
+) -> f32 {
+    let weight = match mode {
+        BrushMode::Add => weight + brush_weight * factor,
+        BrushMode::Subtract => weight - brush_weight * factor,
+        BrushMode::Replace => weight + (brush_weight - weight) * factor,
+        BrushMode::Smooth => weight + (neighbor_average - weight) * factor,
+    };
+    weight.clamp(0., 1.)
+}
+
+/// Color of a weight from blue (0) over green (0.5) to red (1)
+pub fn get_heatmap_color(weight: f32) -> [f32; 4] {
+    let weight = weight.clamp(0., 1.);
+    if weight < 0.5 {
+        [0., 2. * weight, 1. - 2. * weight, 1.]
+    } else {
+        [2. * weight - 1., 2. - 2. * weight, 0., 1.]
+    }
+}
+
+/// Paints the weights of the selected bone on all bound skins with click and drag. Every stroke is a
+/// single history entry.
+pub fn paint_weights(
+    meshes: Res<Assets<Mesh>>,
+    mut skeleton: ResMut<skeleton::Skeleton>,
+    mut transform_state: ResMut<transform::State>,
+    mut egui_state: ResMut<egui::State>,
+    mut paint_state: ResMut<State>,
+    gpu_state: Res<gpu_skinning::State>,
+    skinning_materials: Res<Assets<gpu_skinning::SkinningMaterial>>,
+    cursor_pos: Res<CursorPos>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut mouse_wheel_evr: EventReader<MouseWheel>,
+    mut history: ResMut<history::History>,
+    q_skins: Query<(&Skin, Entity)>,
+    q_bones: Query<(&Transform, Option<&Parent>), With<Bone>>,
+    q_bone_entities: Query<(Entity, &Bone)>,
+    mut stroke: Local<Option<Stroke>>,
+) {
+    if keys.just_pressed(KeyCode::W) {
+        egui_state.adjust_vertex_weights_mode = !egui_state.adjust_vertex_weights_mode;
+    }
+    if !egui_state.adjust_vertex_weights_mode {
+        *stroke = None;
+        return;
+    }
+
+    for mouse_wheel in mouse_wheel_evr.iter() {
+        let change_value = mouse_wheel.y * {if mouse_wheel.unit == MouseScrollUnit::Line {0.05} else {0.0005}};
+        egui_state.brush_size = f32::max(0.05, egui_state.brush_size + change_value);
+    }
+    if keys.just_pressed(KeyCode::E) {
+        paint_state.mode = BrushMode::Add;
+    } else if keys.just_pressed(KeyCode::Q) {
+        paint_state.mode = BrushMode::Subtract;
+    }
+
+    // The first selected bone is painted
+    let bone_entity = match transform_state
+        .selected_entities
+        .iter()
+        .find(|&&entity| q_bones.contains(entity))
+    {
+        Some(&entity) => entity,
+        None => {
+            *stroke = None;
+            return;
+        }
+    };
+
+    let is_new_stroke = stroke.is_none()
+        && mouse.just_pressed(MouseButton::Left)
+        && !egui_state.ui_hover
+        && transform_state.action == transform::Action::None;
+    if is_new_stroke {
+        paint_state.has_locked_vertices = false;
+        *stroke = Some(Stroke {
+            weights_before: skeleton.skin_mappings.clone(),
+            last_dab: cursor_pos.0,
+        });
+    }
+    if stroke.is_none() {
+        return;
+    }
+    // Keep other transform actions and selection from reacting to the mouse
+    transform_state.action = transform::Action::Done;
+
+    if !mouse.pressed(MouseButton::Left) {
+        let weights_before = stroke.take().unwrap().weights_before;
+        let is_changed = skeleton.skin_mappings.len() != weights_before.len()
+            || skeleton
+                .skin_mappings
+                .iter()
+                .zip(weights_before.iter())
+                .any(|(a, b)| {
+                    a.vertex_mappings
+                        .iter()
+                        .zip(b.vertex_mappings.iter())
+                        .any(|(a, b)| a.weights != b.weights)
+                });
+        if is_changed {
+            history.push(
+                "Paint weights",
+                history::Command::Weights {
+                    before: weights_before,
+                    after: skeleton.skin_mappings.clone(),
+                },
+            );
+        }
+        return;
+    }
+    let current_stroke = stroke.as_mut().unwrap();
+    if !is_new_stroke
+        && current_stroke.last_dab.distance(cursor_pos.0) < egui_state.brush_size * DAB_SPACING
+    {
+        return;
+    }
+    current_stroke.last_dab = cursor_pos.0;
+
+    let bind_gl_transform = match skeleton::get_bind_gl_transforms(&q_bones, &q_bone_entities)
+        .get(&bone_entity)
+    {
+        Some(&bind_gl_transform) => bind_gl_transform,
+        None => return,
+    };
+    for skin_mapping in skeleton.skin_mappings.iter_mut() {
+        if skin_mapping.vertex_mappings.is_empty() {
+            continue;
+        }
+        let (skin, skin_entity) = match skin_mapping.skin.and_then(|entity| q_skins.get(entity).ok()) {
+            Some(skin) => skin,
+            None => continue,
+        };
+        let mesh = match meshes.get(&skin.mesh_handle.clone().unwrap().0) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        let vertices =
+            gpu_skinning::get_vertices(mesh, skin_entity, &gpu_state, &skinning_materials);
+        let neighbors = match paint_state.mode {
+            BrushMode::Smooth => get_neighbors(&skin.indices, vertices.len()),
+            _ => vec![],
+        };
+        // Smoothing uses the weights before this dab, so that it doesn't depend on the vertex order
+        let weights: Vec<f32> = skin_mapping
+            .vertex_mappings
+            .iter()
+            .map(|mapping| get_weight(mapping, bone_entity))
+            .collect();
+
+        for v_i in 0..vertices.len().min(skin_mapping.vertex_mappings.len()) {
+            if skin_mapping.vertex_mappings[v_i].is_free {
+                continue;
+            }
+            let distance = vertices[v_i].truncate().distance(cursor_pos.0);
+            let factor = paint_state.strength
+                * paint_state.falloff.get_factor(distance, egui_state.brush_size);
+            if factor == 0. {
+                continue;
+            }
+            let neighbor_average = match neighbors.get(v_i) {
+                Some(vertex_neighbors) if !vertex_neighbors.is_empty() => {
+                    vertex_neighbors.iter().map(|&n| weights[n]).sum::<f32>()
+                        / vertex_neighbors.len() as f32
+                }
+                _ => weights[v_i],
+            };
+            let weight = apply_brush(
+                paint_state.mode,
+                weights[v_i],
+                paint_state.weight,
+                factor,
+                neighbor_average,
+            );
+            if weight == weights[v_i] {
+                continue;
+            }
+            // Bones, that don't influence the vertex yet, need its position relative to the bone
+            let rel_position = match skin_mapping.bind_vertices.get(v_i) {
+                Some(&bind_vertex) => transform::get_relative_transform(
+                    &bind_gl_transform,
+                    &Transform::from_translation(bind_vertex.extend(0.)),
+                )
+                .translation
+                .truncate(),
+                // The bone already influences the vertex, its relative position is kept
+                None if skin_mapping.vertex_mappings[v_i].bones.contains(&bone_entity) => {
+                    Vec2::ZERO
+                }
+                None => continue,
+            };
+            if !set_weight(
+                &mut skin_mapping.vertex_mappings[v_i],
+                bone_entity,
+                weight,
+                rel_position,
+            ) {
+                paint_state.has_locked_vertices = true;
+            }
+        }
+    }
+}
+
+/// Shows the weights of the selected bone as colored copies of the bound skins' meshes
+pub fn update_heatmaps(
+    mut commands: Commands,
+    mut paint_state: ResMut<State>,
+    egui_state: Res<egui::State>,
+    transform_state: Res<transform::State>,
+    skeleton: Res<skeleton::Skeleton>,
+    gpu_state: Res<gpu_skinning::State>,
+    skinning_materials: Res<Assets<gpu_skinning::SkinningMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<HeatmapMaterial>>,
+    mut opt_material_handle: Local<Option<Handle<HeatmapMaterial>>>,
+    q_skins: Query<&Skin>,
+    q_bones: Query<Entity, With<Bone>>,
+) {
+    let opt_bone = transform_state
+        .selected_entities
+        .iter()
+        .find(|&&entity| q_bones.contains(entity))
+        .copied();
+    let mut heatmap_count = 0;
+    if let (true, true, Some(bone_entity)) = (
+        egui_state.adjust_vertex_weights_mode,
+        paint_state.show_heatmap,
+        opt_bone,
+    ) {
+        let material_handle = opt_material_handle
+            .get_or_insert_with(|| {
+                materials.add(HeatmapMaterial {
+                    tint: Color::rgba(1., 1., 1., 0.6),
+                })
+            })
+            .clone();
+        for skin_mapping in skeleton.skin_mappings.iter() {
+            if skin_mapping.vertex_mappings.is_empty() {
+                continue;
+            }
+            let (skin, skin_entity) = match skin_mapping
+                .skin
+                .and_then(|entity| q_skins.get(entity).ok().map(|skin| (skin, entity)))
+            {
+                Some(skin) => skin,
+                None => continue,
+            };
+            let vertices = match meshes.get(&skin.mesh_handle.clone().unwrap().0) {
+                Some(mesh) => {
+                    gpu_skinning::get_vertices(mesh, skin_entity, &gpu_state, &skinning_materials)
+                }
+                None => continue,
+            };
+            let colors: Vec<[f32; 4]> = (0..vertices.len())
+                .map(|v_i| match skin_mapping.vertex_mappings.get(v_i) {
+                    Some(mapping) => get_heatmap_color(get_weight(mapping, bone_entity)),
+                    None => get_heatmap_color(0.),
+                })
+                .collect();
+
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_POSITION,
+                vertices.iter().map(|v| [v.x, v.y, 0.]).collect::<Vec<[f32; 3]>>(),
+            );
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+            mesh.set_indices(Some(Indices::U16(skin.indices.clone())));
+
+            // Reuse a heatmap from the last frame if possible
+            if let Some(heatmap) = paint_state.heatmaps.get(heatmap_count) {
+                if let Some(heatmap_mesh) = meshes.get_mut(&heatmap.mesh_handle) {
+                    *heatmap_mesh = mesh;
+                }
+            } else {
+                let mesh_handle = meshes.add(mesh);
+                let entity = commands
+                    .spawn_bundle(MaterialMesh2dBundle {
+                        mesh: mesh_handle.clone().into(),
+                        material: material_handle.clone(),
+                        transform: Transform::from_translation(Vec3::new(0., 0., HEATMAP_DEPTH)),
+                        ..default()
+                    })
+                    .insert(WeightHeatmap)
+                    .id();
+                paint_state.heatmaps.push(Heatmap {
+                    entity,
+                    mesh_handle,
+                });
+            }
+            heatmap_count += 1;
+        }
+    }
+
+    // Remove heatmaps that aren't needed anymore
+    let unused_heatmaps: Vec<Heatmap> = paint_state.heatmaps.drain(heatmap_count..).collect();
+    for heatmap in unused_heatmaps {
+        commands.entity(heatmap.entity).despawn();
+        meshes.remove(heatmap.mesh_handle);
+    }
+}